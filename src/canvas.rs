@@ -0,0 +1,754 @@
+//! Export of Obsidian Canvas files (`board.canvas`) as Zola pages.
+//!
+//! A canvas is a JSON document of positioned nodes (text cards, files, web links and groups)
+//! and the edges connecting them. obsidian-export only copies canvas files, so
+//! [`export_canvases`] runs after it and writes a page for each canvas in place of the copy,
+//! `board.canvas` becoming `board.md`. Links to the canvas are pointed at that page by the
+//! link postprocessor.
+
+use crate::embeds::{replace_wiki_references, WikiReference};
+use crate::postprocessors::try_convert_to_zola_link_with_context;
+use crate::utils::{
+    encode_asset_url_path, escape_html_attribute, is_canvas_file, is_image_file, is_markdown_file,
+    normalize_path,
+};
+use eyre::{Result, WrapErr};
+use obsidian_export::{vault_contents, Context, WalkOptions};
+use serde::Deserialize;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Space around the nodes of a canvas rendered with [`CanvasMode::Layout`], in pixels.
+const LAYOUT_PADDING: f64 = 20.0;
+
+/// How canvas pages are rendered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum CanvasMode {
+    /// Absolutely positioned cards with the edges drawn as SVG, like in Obsidian
+    #[default]
+    Layout,
+    /// A list of the cards in each group, followed by the connections between them
+    List,
+}
+
+/// An Obsidian Canvas document.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Canvas {
+    #[serde(default)]
+    pub nodes: Vec<CanvasNode>,
+    #[serde(default)]
+    pub edges: Vec<CanvasEdge>,
+}
+
+/// A card or group placed on a canvas.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CanvasNode {
+    pub id: String,
+    #[serde(flatten)]
+    pub kind: CanvasNodeKind,
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+    /// A preset color (`"1"` to `"6"`) or a hex color
+    #[serde(default)]
+    pub color: Option<String>,
+}
+
+/// The content of a canvas node.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum CanvasNodeKind {
+    /// A markdown card
+    Text { text: String },
+    /// A vault file, `subpath` is a `#heading` within a note
+    File {
+        file: String,
+        #[serde(default)]
+        subpath: Option<String>,
+    },
+    /// A web page
+    Link { url: String },
+    /// A labeled area grouping the nodes inside it
+    Group {
+        #[serde(default)]
+        label: Option<String>,
+    },
+    #[serde(other)]
+    Unknown,
+}
+
+/// An arrow between two canvas nodes.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CanvasEdge {
+    pub from_node: String,
+    #[serde(default)]
+    pub from_side: Option<String>,
+    /// `arrow` or `none` (the default)
+    #[serde(default)]
+    pub from_end: Option<String>,
+    pub to_node: String,
+    #[serde(default)]
+    pub to_side: Option<String>,
+    /// `arrow` (the default) or `none`
+    #[serde(default)]
+    pub to_end: Option<String>,
+    #[serde(default)]
+    pub label: Option<String>,
+}
+
+impl CanvasNode {
+    /// Returns the point an edge attaches to, the middle of `side` or the center of the node.
+    fn anchor(&self, side: Option<&str>) -> (f64, f64) {
+        let (center_x, center_y) = (self.x + self.width / 2.0, self.y + self.height / 2.0);
+        match side {
+            Some("top") => (center_x, self.y),
+            Some("bottom") => (center_x, self.y + self.height),
+            Some("left") => (self.x, center_y),
+            Some("right") => (self.x + self.width, center_y),
+            _ => (center_x, center_y),
+        }
+    }
+
+    /// Whether the center of `other` lies within this node.
+    fn contains(&self, other: &CanvasNode) -> bool {
+        let (x, y) = other.anchor(None);
+        x >= self.x && x <= self.x + self.width && y >= self.y && y <= self.y + self.height
+    }
+
+    /// Returns a short plain-text name for the node, used for connections.
+    fn title(&self) -> String {
+        match &self.kind {
+            CanvasNodeKind::Text { text } => text
+                .lines()
+                .map(|line| line.trim_start_matches('#').trim())
+                .find(|line| !line.is_empty())
+                .unwrap_or("Card")
+                .to_string(),
+            CanvasNodeKind::File { file, .. } => file_title(file),
+            CanvasNodeKind::Link { url } => url.clone(),
+            CanvasNodeKind::Group { label } => label.clone().unwrap_or_else(|| "Group".into()),
+            CanvasNodeKind::Unknown => self.id.clone(),
+        }
+    }
+
+    fn area(&self) -> f64 {
+        self.width * self.height
+    }
+}
+
+/// Returns the page path a canvas is exported to, `boards/plan.canvas` becoming
+/// `boards/plan.md`.
+///
+/// # Arguments
+///
+/// * `canvas_path` - The vault-relative path of the canvas
+///
+/// # Returns
+///
+/// The vault-relative path of the generated page.
+pub fn canvas_page_path(canvas_path: &str) -> String {
+    let stem = match canvas_path.rfind('.') {
+        Some(dot) if is_canvas_file(canvas_path) => &canvas_path[..dot],
+        _ => canvas_path,
+    };
+    format!("{}.md", stem)
+}
+
+/// Writes a page for every canvas in the vault and removes the canvas files obsidian-export
+/// copied. Canvases that can't be parsed, or whose page would replace a note, are skipped
+/// with a warning and stay copied as-is.
+///
+/// # Arguments
+///
+/// * `source` - The source vault directory
+/// * `destination` - The export destination directory
+/// * `mode` - How the canvases are rendered
+/// * `frontmatter` - Whether pages get frontmatter with the canvas name as title
+/// * `walk_options` - The options the vault was exported with, canvases they leave out are
+///   not turned into pages
+///
+/// # Returns
+///
+/// The paths of the generated pages.
+pub fn export_canvases(
+    source: &Path,
+    destination: &Path,
+    mode: CanvasMode,
+    frontmatter: bool,
+    walk_options: WalkOptions<'_>,
+) -> Result<Vec<PathBuf>> {
+    let vault_files: Vec<String> = vault_contents(source, walk_options)
+        .wrap_err("Failed to list vault files")?
+        .iter()
+        .filter_map(|path| path.strip_prefix(source).ok())
+        .map(normalize_path)
+        .collect();
+
+    let mut pages = Vec::new();
+    for canvas_path in vault_files.iter().filter(|path| is_canvas_file(path)) {
+        let page_path = canvas_page_path(canvas_path);
+        if vault_files.contains(&page_path) {
+            eprintln!(
+                "Warning: Skipping canvas, a note with the same name exists\n\tCanvas: '{}'\n\tNote: '{}'\n",
+                canvas_path, page_path,
+            );
+            continue;
+        }
+
+        let content = fs::read_to_string(source.join(canvas_path))
+            .wrap_err_with(|| format!("Failed to read canvas {}", canvas_path))?;
+        let canvas: Canvas = match serde_json::from_str(&content) {
+            Ok(canvas) => canvas,
+            Err(e) => {
+                eprintln!(
+                    "Warning: Unable to parse canvas ({})\n\tSource: '{}'\n",
+                    e, canvas_path,
+                );
+                continue;
+            }
+        };
+
+        let mut page = String::new();
+        if frontmatter {
+            let mut fields = serde_yaml::Mapping::new();
+            fields.insert("title".into(), file_title(canvas_path).into());
+            let yaml = serde_yaml::to_string(&fields).wrap_err("Failed to write frontmatter")?;
+            page.push_str(&format!("---\n{}---\n\n", yaml));
+        }
+        page.push_str(&render_canvas(
+            &canvas,
+            canvas_path,
+            source,
+            &vault_files,
+            mode,
+        ));
+
+        let page_file = destination.join(&page_path);
+        if let Some(parent) = page_file.parent() {
+            fs::create_dir_all(parent).wrap_err("Failed to create destination directory")?;
+        }
+        fs::write(&page_file, page).wrap_err("Failed to write canvas page")?;
+
+        let copied_canvas = destination.join(canvas_path);
+        if copied_canvas.is_file() {
+            fs::remove_file(&copied_canvas).wrap_err("Failed to remove copied canvas")?;
+        }
+
+        pages.push(page_file);
+    }
+
+    Ok(pages)
+}
+
+/// Renders a canvas as the markdown body of a Zola page.
+///
+/// # Arguments
+///
+/// * `canvas` - The parsed canvas
+/// * `canvas_path` - The vault-relative path of the canvas, links are resolved from there
+/// * `source_dir` - The source vault directory
+/// * `vault_files` - The vault-relative paths of all files, used to look up wikilinks
+/// * `mode` - How the canvas is rendered
+///
+/// # Returns
+///
+/// The page body. Note cards and wikilinks in text cards become `@/` links.
+pub fn render_canvas(
+    canvas: &Canvas,
+    canvas_path: &str,
+    source_dir: &Path,
+    vault_files: &[String],
+    mode: CanvasMode,
+) -> String {
+    let links = CanvasLinks {
+        context: Context::new(
+            source_dir.join(canvas_path),
+            PathBuf::from(canvas_page_path(canvas_path)),
+        ),
+        source_dir,
+        depth: canvas_path.matches('/').count(),
+        vault_files,
+    };
+
+    match mode {
+        CanvasMode::Layout => render_layout(canvas, &links),
+        CanvasMode::List => render_list(canvas, &links),
+    }
+}
+
+/// Resolves the links of a canvas the same way the link postprocessor resolves note links.
+struct CanvasLinks<'a> {
+    context: Context,
+    source_dir: &'a Path,
+    /// Number of directories between the vault root and the canvas
+    depth: usize,
+    vault_files: &'a [String],
+}
+
+impl CanvasLinks<'_> {
+    /// Converts a vault-relative path to its Zola URL.
+    fn resolve(&self, vault_path: &str, section: Option<&str>) -> Option<String> {
+        let relative = format!("{}{}", "../".repeat(self.depth), vault_path);
+        let mut url = encode_asset_url_path(&relative);
+        if let Some(section) = section.filter(|section| !section.is_empty()) {
+            url.push('#');
+            url.push_str(&slug::slugify(section));
+        }
+        try_convert_to_zola_link_with_context(&url, &self.context, self.source_dir)
+    }
+
+    /// Finds the file a wikilink points at, matching obsidian-export's lookup.
+    fn lookup(&self, target: &str) -> Option<&str> {
+        let target = target.to_lowercase();
+        let target_md = format!("{}.md", target);
+        self.vault_files
+            .iter()
+            .find(|path| {
+                let path = path.to_lowercase();
+                let path = Path::new(&path);
+                path.ends_with(&target) || path.ends_with(&target_md)
+            })
+            .map(String::as_str)
+    }
+
+    /// Rewrites the wikilinks of a text card as markdown links.
+    fn render_text(&self, text: &str) -> String {
+        replace_wiki_references(text, |reference: &WikiReference| {
+            let display = reference.display();
+            let Some(file) = &reference.file else {
+                return display;
+            };
+
+            let url = self
+                .lookup(file)
+                .and_then(|path| self.resolve(path, reference.section.as_deref()));
+            match url {
+                Some(url) => {
+                    markdown_link(&display, &url, reference.is_embed && is_image_file(file))
+                }
+                None => {
+                    eprintln!(
+                        "Warning: Unable to find referenced note\n\tReference: '{}'\n\tSource: '{}'\n",
+                        file,
+                        self.context.current_file().display(),
+                    );
+                    format!("*{}*", display)
+                }
+            }
+        })
+    }
+
+    /// Renders the markdown content of a card.
+    fn render_card(&self, node: &CanvasNode) -> String {
+        match &node.kind {
+            CanvasNodeKind::Text { text } => self.render_text(text.trim()),
+            CanvasNodeKind::File { file, subpath } => {
+                let section = subpath
+                    .as_deref()
+                    .map(|subpath| subpath.trim_start_matches('#'));
+                match self.resolve(file, section) {
+                    Some(url) => markdown_link(&file_title(file), &url, is_image_file(file)),
+                    None => format!("*{}*", file_title(file)),
+                }
+            }
+            CanvasNodeKind::Link { url } => markdown_link(url, url, false),
+            CanvasNodeKind::Group { .. } | CanvasNodeKind::Unknown => String::new(),
+        }
+    }
+}
+
+/// Renders the canvas as absolutely positioned cards. The cards are HTML blocks wrapping
+/// markdown, so Zola still resolves the `@/` links inside them.
+fn render_layout(canvas: &Canvas, links: &CanvasLinks<'_>) -> String {
+    if canvas.nodes.is_empty() {
+        return String::new();
+    }
+
+    let min_x = canvas
+        .nodes
+        .iter()
+        .map(|n| n.x)
+        .fold(f64::INFINITY, f64::min)
+        - LAYOUT_PADDING;
+    let min_y = canvas
+        .nodes
+        .iter()
+        .map(|n| n.y)
+        .fold(f64::INFINITY, f64::min)
+        - LAYOUT_PADDING;
+    let max_x = canvas
+        .nodes
+        .iter()
+        .map(|n| n.x + n.width)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let max_y = canvas
+        .nodes
+        .iter()
+        .map(|n| n.y + n.height)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let (width, height) = (
+        max_x + LAYOUT_PADDING - min_x,
+        max_y + LAYOUT_PADDING - min_y,
+    );
+
+    let style = |node: &CanvasNode, border: &str| {
+        format!(
+            "position:absolute;left:{}px;top:{}px;width:{}px;height:{}px;box-sizing:border-box;border:1px {};border-radius:8px;{}",
+            node.x - min_x,
+            node.y - min_y,
+            node.width,
+            node.height,
+            border,
+            node_color(node)
+                .map(|color| format!("border-color:{};", color))
+                .unwrap_or_default(),
+        )
+    };
+
+    let mut out = String::new();
+    out.push_str("<div class=\"canvas-scroll\" style=\"overflow:auto\">\n");
+    let _ = writeln!(
+        out,
+        "<div class=\"canvas\" style=\"position:relative;width:{}px;height:{}px\">",
+        width, height
+    );
+
+    // Groups are drawn first so they stay behind the cards, largest first
+    let mut groups: Vec<&CanvasNode> = canvas
+        .nodes
+        .iter()
+        .filter(|node| matches!(node.kind, CanvasNodeKind::Group { .. }))
+        .collect();
+    groups.sort_by(|a, b| b.area().total_cmp(&a.area()));
+    for group in groups {
+        let _ = writeln!(
+            out,
+            "<div class=\"canvas-node canvas-group\" style=\"{}\"><div class=\"canvas-group-label\">{}</div></div>",
+            style(group, "dashed"),
+            escape_html_attribute(&group.title()),
+        );
+    }
+
+    render_edges(&mut out, canvas, width, height, (min_x, min_y));
+
+    for node in &canvas.nodes {
+        let kind = match node.kind {
+            CanvasNodeKind::Text { .. } => "text",
+            CanvasNodeKind::File { .. } => "file",
+            CanvasNodeKind::Link { .. } => "link",
+            CanvasNodeKind::Group { .. } | CanvasNodeKind::Unknown => continue,
+        };
+        let _ = write!(
+            out,
+            "\n<div class=\"canvas-node canvas-{}\" style=\"{}padding:0 12px;overflow:auto\">\n\n{}\n\n</div>\n",
+            kind,
+            style(node, "solid"),
+            links.render_card(node),
+        );
+    }
+
+    out.push_str("\n</div>\n</div>\n");
+    out
+}
+
+/// Draws the edges of a canvas as an SVG overlay.
+fn render_edges(out: &mut String, canvas: &Canvas, width: f64, height: f64, origin: (f64, f64)) {
+    let find = |id: &str| canvas.nodes.iter().find(|node| node.id == id);
+    let edges: Vec<_> = canvas
+        .edges
+        .iter()
+        .filter_map(|edge| Some((edge, find(&edge.from_node)?, find(&edge.to_node)?)))
+        .collect();
+    if edges.is_empty() {
+        return;
+    }
+
+    let _ = writeln!(
+        out,
+        "<svg class=\"canvas-edges\" width=\"{}\" height=\"{}\" style=\"position:absolute;left:0;top:0;pointer-events:none\">",
+        width, height
+    );
+    out.push_str("<defs><marker id=\"canvas-arrow\" viewBox=\"0 0 10 10\" refX=\"10\" refY=\"5\" markerWidth=\"8\" markerHeight=\"8\" orient=\"auto-start-reverse\"><path d=\"M0,0L10,5L0,10z\" fill=\"currentColor\"/></marker></defs>\n");
+    for (edge, from, to) in edges {
+        let (x1, y1) = from.anchor(edge.from_side.as_deref());
+        let (x2, y2) = to.anchor(edge.to_side.as_deref());
+        let (x1, y1, x2, y2) = (x1 - origin.0, y1 - origin.1, x2 - origin.0, y2 - origin.1);
+
+        let mut markers = String::new();
+        if edge.from_end.as_deref() == Some("arrow") {
+            markers.push_str(" marker-start=\"url(#canvas-arrow)\"");
+        }
+        if edge.to_end.as_deref() != Some("none") {
+            markers.push_str(" marker-end=\"url(#canvas-arrow)\"");
+        }
+        let _ = writeln!(
+            out,
+            "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"currentColor\" stroke-width=\"2\"{}/>",
+            x1, y1, x2, y2, markers
+        );
+        if let Some(label) = &edge.label {
+            let _ = writeln!(
+                out,
+                "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\" fill=\"currentColor\">{}</text>",
+                (x1 + x2) / 2.0,
+                (y1 + y2) / 2.0,
+                escape_html_attribute(label),
+            );
+        }
+    }
+    out.push_str("</svg>\n");
+}
+
+/// Renders the canvas as plain markdown: the cards outside of groups, a section per group
+/// and the connections between cards.
+fn render_list(canvas: &Canvas, links: &CanvasLinks<'_>) -> String {
+    let by_position =
+        |a: &&CanvasNode, b: &&CanvasNode| a.y.total_cmp(&b.y).then(a.x.total_cmp(&b.x));
+
+    let mut cards: Vec<&CanvasNode> = canvas
+        .nodes
+        .iter()
+        .filter(|node| {
+            !matches!(
+                node.kind,
+                CanvasNodeKind::Group { .. } | CanvasNodeKind::Unknown
+            )
+        })
+        .collect();
+    cards.sort_by(by_position);
+    let mut groups: Vec<&CanvasNode> = canvas
+        .nodes
+        .iter()
+        .filter(|node| matches!(node.kind, CanvasNodeKind::Group { .. }))
+        .collect();
+    groups.sort_by(by_position);
+
+    // Each card belongs to the smallest group around it
+    let group_of = |card: &CanvasNode| {
+        groups
+            .iter()
+            .filter(|group| group.contains(card))
+            .min_by(|a, b| a.area().total_cmp(&b.area()))
+            .map(|group| group.id.as_str())
+    };
+
+    let list = |members: &[&CanvasNode]| {
+        members
+            .iter()
+            .map(|card| format!("- {}\n", links.render_card(card).replace('\n', "\n  ")))
+            .collect::<String>()
+    };
+
+    let mut sections = Vec::new();
+    let ungrouped: Vec<&CanvasNode> = cards
+        .iter()
+        .copied()
+        .filter(|card| group_of(card).is_none())
+        .collect();
+    if !ungrouped.is_empty() {
+        sections.push(list(&ungrouped));
+    }
+    for group in &groups {
+        let members: Vec<&CanvasNode> = cards
+            .iter()
+            .copied()
+            .filter(|card| group_of(card) == Some(group.id.as_str()))
+            .collect();
+        sections.push(format!("## {}\n\n{}", group.title(), list(&members)));
+    }
+
+    let find = |id: &str| canvas.nodes.iter().find(|node| node.id == id);
+    let connections: String = canvas
+        .edges
+        .iter()
+        .filter_map(|edge| {
+            let (from, to) = (find(&edge.from_node)?, find(&edge.to_node)?);
+            let label = edge
+                .label
+                .as_ref()
+                .map(|label| format!(": {}", label))
+                .unwrap_or_default();
+            Some(format!("- {} → {}{}\n", from.title(), to.title(), label))
+        })
+        .collect();
+    if !connections.is_empty() {
+        sections.push(format!("## Connections\n\n{}", connections));
+    }
+
+    sections.join("\n")
+}
+
+/// Returns the CSS color of a node, mapping Obsidian's preset colors.
+fn node_color(node: &CanvasNode) -> Option<&str> {
+    let color = node.color.as_deref()?;
+    let preset = match color {
+        "1" => "#fb464c",
+        "2" => "#e9973f",
+        "3" => "#e0de71",
+        "4" => "#44cf6e",
+        "5" => "#53dfdd",
+        "6" => "#a882ff",
+        hex => {
+            let digits = hex.strip_prefix('#')?;
+            let valid =
+                matches!(digits.len(), 3 | 6) && digits.chars().all(|c| c.is_ascii_hexdigit());
+            return valid.then_some(hex);
+        }
+    };
+    Some(preset)
+}
+
+/// Returns the name shown for a file: notes without their extension, other files as-is.
+fn file_title(path: &str) -> String {
+    let file_name = path.rsplit('/').next().unwrap_or(path);
+    if is_markdown_file(file_name) || is_canvas_file(file_name) {
+        file_name
+            .rsplit_once('.')
+            .map_or(file_name, |(stem, _)| stem)
+            .to_string()
+    } else {
+        file_name.to_string()
+    }
+}
+
+/// Formats a markdown link or image, wrapping destinations that contain spaces or
+/// parentheses in `<...>`.
+fn markdown_link(text: &str, url: &str, image: bool) -> String {
+    let text = text.replace('[', "\\[").replace(']', "\\]");
+    let destination = if url.contains(|c: char| c.is_whitespace() || c == '(' || c == ')') {
+        format!("<{}>", url)
+    } else {
+        url.to_string()
+    };
+    format!(
+        "{}[{}]({})",
+        if image { "!" } else { "" },
+        text,
+        destination
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CANVAS: &str = r##"{
+        "nodes": [
+            {"id": "g", "type": "group", "x": 0, "y": 0, "width": 600, "height": 300, "label": "Ideas"},
+            {"id": "a", "type": "text", "x": 20, "y": 40, "width": 200, "height": 100, "text": "# Start\nSee [[My Note#Next Steps|next]]", "color": "1"},
+            {"id": "b", "type": "file", "x": 300, "y": 40, "width": 200, "height": 100, "file": "notes/My Note.md"},
+            {"id": "c", "type": "link", "x": 20, "y": 400, "width": 200, "height": 60, "url": "https://example.com"}
+        ],
+        "edges": [
+            {"id": "e", "fromNode": "a", "fromSide": "right", "toNode": "b", "toSide": "left", "label": "leads to"}
+        ]
+    }"##;
+
+    fn vault_files() -> Vec<String> {
+        vec!["board.canvas".to_string(), "notes/My Note.md".to_string()]
+    }
+
+    #[test]
+    fn test_canvas_page_path() {
+        assert_eq!(canvas_page_path("board.canvas"), "board.md");
+        assert_eq!(canvas_page_path("boards/Plan.Canvas"), "boards/Plan.md");
+    }
+
+    #[test]
+    fn test_parse_canvas() {
+        let canvas: Canvas = serde_json::from_str(CANVAS).unwrap();
+        assert_eq!(canvas.nodes.len(), 4);
+        assert!(matches!(
+            &canvas.nodes[1].kind,
+            CanvasNodeKind::Text { text } if text.starts_with("# Start")
+        ));
+        assert_eq!(canvas.edges[0].to_node, "b");
+
+        // Node types added by future Obsidian versions don't fail the whole canvas
+        let canvas: Canvas = serde_json::from_str(
+            r#"{"nodes": [{"id": "x", "type": "widget", "x": 0, "y": 0, "width": 1, "height": 1}]}"#,
+        )
+        .unwrap();
+        assert!(matches!(canvas.nodes[0].kind, CanvasNodeKind::Unknown));
+    }
+
+    #[test]
+    fn test_render_canvas_layout() {
+        let canvas: Canvas = serde_json::from_str(CANVAS).unwrap();
+        let page = render_canvas(
+            &canvas,
+            "board.canvas",
+            Path::new("/vault"),
+            &vault_files(),
+            CanvasMode::Layout,
+        );
+
+        assert!(page.contains(
+            "<div class=\"canvas\" style=\"position:relative;width:640px;height:500px\">"
+        ));
+        assert!(page.contains("<div class=\"canvas-group-label\">Ideas</div>"));
+        assert!(page.contains("left:40px;top:60px;width:200px;height:100px;box-sizing:border-box;border:1px solid;border-radius:8px;border-color:#fb464c;padding"));
+        assert!(page.contains("\n\n# Start\nSee [next](<@/notes/My Note.md#next-steps>)\n\n</div>"));
+        assert!(page.contains("\n\n[My Note](<@/notes/My Note.md>)\n\n</div>"));
+        assert!(page.contains("[https://example.com](https://example.com)"));
+        assert!(page.contains(
+            "<line x1=\"240\" y1=\"110\" x2=\"320\" y2=\"110\" stroke=\"currentColor\" stroke-width=\"2\" marker-end=\"url(#canvas-arrow)\"/>"
+        ));
+        assert!(page.contains(">leads to</text>"));
+    }
+
+    #[test]
+    fn test_render_canvas_list() {
+        let canvas: Canvas = serde_json::from_str(CANVAS).unwrap();
+        let page = render_canvas(
+            &canvas,
+            "boards/board.canvas",
+            Path::new("/vault"),
+            &vault_files(),
+            CanvasMode::List,
+        );
+
+        assert_eq!(
+            page,
+            "- [https://example.com](https://example.com)\n\
+             \n\
+             ## Ideas\n\
+             \n\
+             - # Start\n  See [next](<@/notes/My Note.md#next-steps>)\n\
+             - [My Note](<@/notes/My Note.md>)\n\
+             \n\
+             ## Connections\n\
+             \n\
+             - Start → My Note: leads to\n"
+        );
+    }
+
+    #[test]
+    fn test_export_canvases() {
+        let vault = tempfile::TempDir::new().unwrap();
+        let output = tempfile::TempDir::new().unwrap();
+        fs::create_dir(vault.path().join("notes")).unwrap();
+        fs::write(vault.path().join("notes/My Note.md"), "# My Note\n").unwrap();
+        fs::write(vault.path().join("board.canvas"), CANVAS).unwrap();
+        fs::write(vault.path().join("broken.canvas"), "{").unwrap();
+        fs::write(output.path().join("board.canvas"), CANVAS).unwrap();
+
+        let pages = export_canvases(
+            vault.path(),
+            output.path(),
+            CanvasMode::List,
+            true,
+            WalkOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(pages, vec![output.path().join("board.md")]);
+        let page = fs::read_to_string(output.path().join("board.md")).unwrap();
+        assert!(page.starts_with("---\ntitle: board\n---\n\n- [https://example.com]"));
+        assert!(!output.path().join("board.canvas").exists());
+        assert!(!output.path().join("broken.md").exists());
+    }
+}
@@ -0,0 +1,807 @@
+//! Checking a vault for problems that would break its export or the Zola build.
+//!
+//! A check reads the vault without writing anything and reports each problem as a
+//! [`Diagnostic`] pointing at a line of a file: links resolving to no file or to several,
+//! missing attachments, shortcode calls Zola would run by accident, frontmatter Zola would
+//! reject, pages published at the same URL and files whose names only differ by case.
+//! Links are resolved the way obsidian-export resolves them during an export.
+
+use crate::canvas::canvas_page_path;
+use crate::postprocessors::split_fragment;
+use crate::utils::{decode_url_path, is_canvas_file, is_excalidraw_file, is_markdown_file};
+use crate::vault::VaultFiles;
+use eyre::{Result, WrapErr};
+use obsidian_export::pulldown_cmark::{Event, Options, Parser, Tag};
+use obsidian_export::serde_yaml::{Mapping, Value};
+use obsidian_export::WalkOptions;
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// The kind of problem a diagnostic reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticKind {
+    /// A link to a note or file that isn't in the vault
+    BrokenLink,
+    /// A link matching several files of the vault
+    AmbiguousLink,
+    /// An embed or image whose file isn't in the vault
+    MissingAttachment,
+    /// Shortcode syntax Zola would run or fail to parse
+    Tera,
+    /// Frontmatter Zola would reject
+    Frontmatter,
+    /// Pages published at the same URL
+    SlugCollision,
+    /// Files whose paths only differ by case
+    CaseClash,
+}
+
+/// A problem found at a line of a file of the vault.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// Vault-relative path of the file
+    pub path: String,
+    /// Line of the problem, starting at 1
+    pub line: usize,
+    /// What kind of problem it is
+    pub kind: DiagnosticKind,
+    /// Description of the problem
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.path, self.line, self.message)
+    }
+}
+
+/// Checks a vault, reading it without writing anything.
+///
+/// Passthrough files are copied as-is by the export, so only their paths are checked, not
+/// their content. Excalidraw drawings are exported as images, their content isn't checked
+/// either.
+///
+/// # Arguments
+///
+/// * `source` - The source vault directory
+/// * `walk_options` - The walk options of the export, files its filter rejects are taken as
+///   passthrough files
+///
+/// # Returns
+///
+/// The problems found, sorted by file and line, or an error if the vault can't be read.
+pub fn check_vault(source: &Path, walk_options: &WalkOptions<'_>) -> Result<Vec<Diagnostic>> {
+    let vault_files = VaultFiles::walk(source, walk_options)?;
+    let mut files: Vec<(String, bool)> = vault_files
+        .exported()
+        .map(|path| (path.to_string(), false))
+        .chain(vault_files.copied().map(|path| (path.to_string(), true)))
+        .collect();
+    files.sort();
+
+    let mut diagnostics = check_case_clashes(files.iter().map(|(path, _)| path.as_str()));
+    let mut urls: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (path, passthrough) in &files {
+        if is_canvas_file(path) && !passthrough {
+            urls.entry(page_url(&canvas_page_path(path), None))
+                .or_default()
+                .push(path.clone());
+        }
+        if !is_markdown_file(path) || is_excalidraw_file(path) {
+            continue;
+        }
+        let content = fs::read_to_string(source.join(path))
+            .wrap_err_with(|| format!("Failed to read {}", source.join(path).display()))?;
+        if *passthrough {
+            urls.entry(page_url(path, None))
+                .or_default()
+                .push(path.clone());
+            continue;
+        }
+
+        let frontmatter = check_frontmatter(path, &content, &mut diagnostics);
+        urls.entry(page_url(path, frontmatter.as_ref()))
+            .or_default()
+            .push(path.clone());
+        diagnostics.extend(check_links(path, &content, &vault_files));
+        diagnostics.extend(check_shortcodes(path, &content));
+    }
+
+    for (url, paths) in urls.iter().filter(|(_, paths)| paths.len() > 1) {
+        for path in paths {
+            let others: Vec<&str> = paths
+                .iter()
+                .filter(|other| *other != path)
+                .map(String::as_str)
+                .collect();
+            diagnostics.push(Diagnostic {
+                path: path.clone(),
+                line: 1,
+                kind: DiagnosticKind::SlugCollision,
+                message: format!(
+                    "published at /{}/ like {}, Zola would only keep one of them",
+                    url,
+                    others.join(", ")
+                ),
+            });
+        }
+    }
+
+    diagnostics.sort_by(|a, b| (&a.path, a.line).cmp(&(&b.path, b.line)));
+    Ok(diagnostics)
+}
+
+/// Checks the links of a note: wikilinks and markdown links that point to no file, wikilinks
+/// that match several files, and embeds and images whose file is missing. Links in code, in
+/// HTML and in the frontmatter aren't links and are skipped, so are links to websites and to
+/// headings of the note itself.
+///
+/// # Arguments
+///
+/// * `note` - The vault-relative path of the note
+/// * `content` - The content of the note
+/// * `files` - The files of the export, links to files it leaves out are broken
+///
+/// # Returns
+///
+/// The problems found, in order of appearance.
+pub fn check_links(note: &str, content: &str, files: &VaultFiles) -> Vec<Diagnostic> {
+    static WIKILINK_RE: OnceLock<Regex> = OnceLock::new();
+    let wikilink_re = WIKILINK_RE.get_or_init(|| Regex::new(r"(!?)\[\[([^\]|#^]*)").unwrap());
+
+    let note_dir = Path::new(note).parent().unwrap_or_else(|| Path::new(""));
+    let mut found = Vec::new();
+    let mut skipped = Vec::new();
+    for (event, range) in Parser::new_ext(content, parser_options()).into_offset_iter() {
+        match event {
+            Event::Start(Tag::CodeBlock(_) | Tag::MetadataBlock(_) | Tag::HtmlBlock)
+            | Event::Code(_)
+            | Event::InlineHtml(_) => skipped.push(range),
+            Event::Start(Tag::Link { dest_url, .. })
+                if !resolves_in_vault(&dest_url, note_dir, files) =>
+            {
+                let kind = if is_markdown_file(split_fragment(&dest_url).0) {
+                    DiagnosticKind::BrokenLink
+                } else {
+                    DiagnosticKind::MissingAttachment
+                };
+                found.push((range.start, kind, dest_url.into_string()));
+            }
+            Event::Start(Tag::Image { dest_url, .. })
+                if !resolves_in_vault(&dest_url, note_dir, files) =>
+            {
+                found.push((
+                    range.start,
+                    DiagnosticKind::MissingAttachment,
+                    dest_url.into_string(),
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    let mut diagnostics: Vec<Diagnostic> = found
+        .into_iter()
+        .map(|(offset, kind, target)| link_diagnostic(note, content, offset, kind, &target, &[]))
+        .collect();
+    for captures in wikilink_re.captures_iter(content) {
+        let offset = captures.get(0).unwrap().start();
+        let target = captures[2].trim();
+        if target.is_empty() || skipped.iter().any(|range| range.contains(&offset)) {
+            continue;
+        }
+        let matches = files.resolve_wikilink(target);
+        let kind = match matches.len() {
+            1 => continue,
+            0 if !captures[1].is_empty() && !is_note_target(target) => {
+                DiagnosticKind::MissingAttachment
+            }
+            0 => DiagnosticKind::BrokenLink,
+            _ => DiagnosticKind::AmbiguousLink,
+        };
+        diagnostics.push(link_diagnostic(
+            note, content, offset, kind, target, &matches,
+        ));
+    }
+    diagnostics.sort_by_key(|diagnostic| diagnostic.line);
+    diagnostics
+}
+
+/// Builds the diagnostic of a link problem.
+fn link_diagnostic(
+    note: &str,
+    content: &str,
+    offset: usize,
+    kind: DiagnosticKind,
+    target: &str,
+    matches: &[&str],
+) -> Diagnostic {
+    let message = match kind {
+        DiagnosticKind::MissingAttachment => format!("missing attachment '{}'", target),
+        DiagnosticKind::AmbiguousLink => format!(
+            "ambiguous link '{}' matches {}, use a longer path to pick one",
+            target,
+            matches.join(", ")
+        ),
+        _ => format!("broken link '{}'", target),
+    };
+    Diagnostic {
+        path: note.to_string(),
+        line: line_at(content, offset),
+        kind,
+        message,
+    }
+}
+
+/// Checks whether a wikilink target names a note rather than an attachment: it has no
+/// extension, or the `.md` one.
+fn is_note_target(target: &str) -> bool {
+    let name = target.rsplit('/').next().unwrap_or(target);
+    !name.contains('.') || is_markdown_file(name)
+}
+
+/// Checks whether a markdown link destination points to a file the export publishes. Links
+/// to websites, to site paths and to headings are assumed to resolve.
+fn resolves_in_vault(url: &str, note_dir: &Path, files: &VaultFiles) -> bool {
+    if url.contains("://") || url.starts_with("mailto:") {
+        return true;
+    }
+    let (path, _) = split_fragment(url);
+    let path = decode_url_path(path);
+    if path.is_empty() || path.starts_with('/') {
+        return true;
+    }
+    files.resolve_link(note_dir, &path).is_some()
+}
+
+/// Checks the shortcode syntax of a note.
+///
+/// Zola runs shortcodes everywhere in a page, code blocks and code spans included, so calls
+/// written there to show them are reported. Outside of code, shortcodes with a body need a
+/// matching `{% end %}`. Inline shortcode calls outside of code are taken as intended.
+///
+/// # Arguments
+///
+/// * `note` - The vault-relative path of the note
+/// * `content` - The content of the note
+///
+/// # Returns
+///
+/// The problems found, in order of appearance.
+pub fn check_shortcodes(note: &str, content: &str) -> Vec<Diagnostic> {
+    static SHORTCODE_RE: OnceLock<Regex> = OnceLock::new();
+    let shortcode_re = SHORTCODE_RE.get_or_init(|| {
+        Regex::new(r"\{\{-?\s*([A-Za-z_]\w*)\s*\(|\{%-?\s*(?:([A-Za-z_]\w*)\s*\(|(end)\s*-?%\})")
+            .unwrap()
+    });
+
+    let mut code: Vec<Range<usize>> = Vec::new();
+    let mut frontmatter = 0..0;
+    for (event, range) in Parser::new_ext(content, parser_options()).into_offset_iter() {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) | Event::Code(_) => code.push(range),
+            Event::Start(Tag::MetadataBlock(_)) => frontmatter = range,
+            _ => {}
+        }
+    }
+
+    let mut diagnostics = Vec::new();
+    let mut open: Vec<(&str, usize)> = Vec::new();
+    let mut report = |offset: usize, message: String| {
+        diagnostics.push(Diagnostic {
+            path: note.to_string(),
+            line: line_at(content, offset),
+            kind: DiagnosticKind::Tera,
+            message,
+        });
+    };
+    for captures in shortcode_re.captures_iter(content) {
+        let offset = captures.get(0).unwrap().start();
+        if frontmatter.contains(&offset) {
+            continue;
+        }
+        if code.iter().any(|range| range.contains(&offset)) {
+            let (name, start, end) = match (captures.get(1), captures.get(2)) {
+                (Some(name), _) => (name.as_str(), "{{", "}}"),
+                (_, Some(name)) => (name.as_str(), "{%", "%}"),
+                _ => continue,
+            };
+            report(
+                offset,
+                format!(
+                    "Zola runs shortcodes in code too, escape '{}' as {}/* {}(...) */{}",
+                    name, start, name, end
+                ),
+            );
+        } else if let Some(name) = captures.get(2) {
+            open.push((name.as_str(), offset));
+        } else if captures.get(3).is_some() && open.pop().is_none() {
+            report(offset, "{% end %} closes no shortcode".to_string());
+        }
+    }
+    for (name, offset) in open {
+        report(offset, format!("shortcode '{}' has no {{% end %}}", name));
+    }
+    diagnostics.sort_by_key(|diagnostic| diagnostic.line);
+    diagnostics
+}
+
+/// The type Zola expects for a frontmatter field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FieldType {
+    String,
+    Date,
+    Bool,
+    Integer,
+    Strings,
+    Taxonomies,
+    Table,
+}
+
+impl FieldType {
+    /// Describes the type for diagnostics.
+    fn description(self) -> &'static str {
+        match self {
+            Self::String => "a string",
+            Self::Date => "a date like 2024-01-31 or 2024-01-31T10:00:00Z",
+            Self::Bool => "true or false",
+            Self::Integer => "a positive integer",
+            Self::Strings => "a list of strings",
+            Self::Taxonomies => "a map of lists of strings",
+            Self::Table => "a map",
+        }
+    }
+
+    /// Checks whether a YAML value has the type.
+    fn accepts(self, value: &Value) -> bool {
+        static DATE_RE: OnceLock<Regex> = OnceLock::new();
+        let date_re = DATE_RE.get_or_init(|| {
+            Regex::new(
+                r"^\d{4}-\d{2}-\d{2}([T ]\d{2}:\d{2}(:\d{2}(\.\d+)?)?(Z|[+-]\d{2}:?\d{2})?)?$",
+            )
+            .unwrap()
+        });
+        let is_strings = |value: &Value| {
+            value
+                .as_sequence()
+                .is_some_and(|items| items.iter().all(Value::is_string))
+        };
+
+        match self {
+            Self::String => value.is_string(),
+            Self::Date => value.as_str().is_some_and(|date| date_re.is_match(date)),
+            Self::Bool => value.is_bool(),
+            Self::Integer => value.is_u64(),
+            Self::Strings => is_strings(value),
+            Self::Taxonomies => value
+                .as_mapping()
+                .is_some_and(|taxonomies| taxonomies.values().all(is_strings)),
+            Self::Table => value.is_mapping(),
+        }
+    }
+}
+
+/// Returns the type of a field of page frontmatter, `None` for fields Zola doesn't know.
+fn page_field_type(field: &str) -> Option<FieldType> {
+    match field {
+        "title" | "description" | "slug" | "path" | "template" => Some(FieldType::String),
+        "date" | "updated" => Some(FieldType::Date),
+        "draft" | "in_search_index" => Some(FieldType::Bool),
+        "weight" => Some(FieldType::Integer),
+        "aliases" | "authors" => Some(FieldType::Strings),
+        "taxonomies" => Some(FieldType::Taxonomies),
+        "extra" => Some(FieldType::Table),
+        _ => None,
+    }
+}
+
+/// Returns the type of a field of section (`_index.md`) frontmatter, `None` for fields Zola
+/// doesn't know.
+fn section_field_type(field: &str) -> Option<FieldType> {
+    match field {
+        "title"
+        | "description"
+        | "sort_by"
+        | "template"
+        | "page_template"
+        | "paginate_path"
+        | "insert_anchor_links"
+        | "redirect_to" => Some(FieldType::String),
+        "draft" | "in_search_index" | "render" | "transparent" | "paginate_reversed"
+        | "generate_feeds" => Some(FieldType::Bool),
+        "weight" | "paginate_by" => Some(FieldType::Integer),
+        "aliases" => Some(FieldType::Strings),
+        "extra" => Some(FieldType::Table),
+        _ => None,
+    }
+}
+
+/// Checks the frontmatter of a note against the fields Zola accepts for pages, or for
+/// sections in `_index.md` files. Zola rejects unknown fields and fields of the wrong type,
+/// and obsidian-export rejects frontmatter that isn't valid YAML.
+///
+/// # Returns
+///
+/// The frontmatter, when it is a valid YAML mapping.
+fn check_frontmatter(
+    note: &str,
+    content: &str,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Option<Mapping> {
+    let yaml = frontmatter_block(content)?;
+    let mut report = |line: usize, message: String| {
+        diagnostics.push(Diagnostic {
+            path: note.to_string(),
+            line,
+            kind: DiagnosticKind::Frontmatter,
+            message,
+        });
+    };
+
+    let frontmatter = match obsidian_export::serde_yaml::from_str::<Value>(yaml) {
+        Ok(Value::Mapping(frontmatter)) => frontmatter,
+        Ok(Value::Null) => return Some(Mapping::new()),
+        Ok(_) => {
+            report(2, "frontmatter is not a map of fields".to_string());
+            return None;
+        }
+        Err(e) => {
+            let line = e.location().map_or(0, |location| location.line() - 1);
+            let error = e.to_string();
+            let error = error.split(" at line ").next().unwrap_or(&error);
+            report(2 + line, format!("invalid frontmatter: {}", error));
+            return None;
+        }
+    };
+
+    let is_section = Path::new(note)
+        .file_name()
+        .is_some_and(|name| name == "_index.md");
+    for (key, value) in &frontmatter {
+        let Some(field) = key.as_str() else {
+            report(2, "frontmatter field names must be strings".to_string());
+            continue;
+        };
+        let line = field_line(yaml, field);
+        let expected = if is_section {
+            section_field_type(field)
+        } else {
+            page_field_type(field)
+        };
+        match expected {
+            None => report(
+                line,
+                format!(
+                    "Zola doesn't know the frontmatter field '{}', move it under 'extra'{}",
+                    field,
+                    if is_section { "" } else { " or 'taxonomies'" }
+                ),
+            ),
+            Some(expected) if !expected.accepts(value) => report(
+                line,
+                format!(
+                    "frontmatter field '{}' should be {}",
+                    field,
+                    expected.description()
+                ),
+            ),
+            Some(_) => {}
+        }
+    }
+    Some(frontmatter)
+}
+
+/// Returns the YAML frontmatter of a note, which starts on its second line.
+fn frontmatter_block(content: &str) -> Option<&str> {
+    let rest = content
+        .strip_prefix("---\n")
+        .or_else(|| content.strip_prefix("---\r\n"))?;
+    let mut end = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == "---" {
+            return Some(&rest[..end]);
+        }
+        end += line.len();
+    }
+    None
+}
+
+/// Returns the line of a note a top-level frontmatter field is on.
+fn field_line(yaml: &str, field: &str) -> usize {
+    yaml.lines()
+        .position(|line| {
+            line.strip_prefix(field)
+                .is_some_and(|rest| rest.trim_start().starts_with(':'))
+        })
+        .map_or(2, |index| index + 2)
+}
+
+/// Returns the URL path Zola publishes a note at, without surrounding slashes.
+///
+/// Like Zola, the `path` field wins, then the `slug` field, then the file name without its
+/// date prefix. `index.md` and `_index.md` are published at their directory.
+fn page_url(note: &str, frontmatter: Option<&Mapping>) -> String {
+    static DATE_PREFIX_RE: OnceLock<Regex> = OnceLock::new();
+    let date_prefix_re =
+        DATE_PREFIX_RE.get_or_init(|| Regex::new(r"^\d{4}-\d{2}-\d{2}[_-]").unwrap());
+
+    let field = |name: &str| {
+        frontmatter
+            .and_then(|fields| fields.get(name))
+            .and_then(Value::as_str)
+    };
+    if let Some(path) = field("path") {
+        return path.trim_matches('/').to_string();
+    }
+
+    let path = Path::new(note);
+    let mut components: Vec<String> = path
+        .parent()
+        .into_iter()
+        .flat_map(Path::components)
+        .map(|component| slug::slugify(component.as_os_str().to_string_lossy()))
+        .collect();
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default();
+    if stem != "index" && stem != "_index" {
+        let name = field("slug").unwrap_or_else(|| {
+            date_prefix_re
+                .find(&stem)
+                .map_or(&stem, |prefix| &stem[prefix.end()..])
+        });
+        components.push(slug::slugify(name));
+    }
+    components.join("/")
+}
+
+/// Reports the files whose vault-relative paths only differ by case. They overwrite each
+/// other when the vault or the site is checked out on a case-insensitive filesystem.
+fn check_case_clashes<'a, I: IntoIterator<Item = &'a str>>(paths: I) -> Vec<Diagnostic> {
+    let mut by_lowercase: BTreeMap<String, Vec<&str>> = BTreeMap::new();
+    for path in paths {
+        by_lowercase
+            .entry(path.to_lowercase())
+            .or_default()
+            .push(path);
+    }
+
+    let mut diagnostics = Vec::new();
+    for paths in by_lowercase.values().filter(|paths| paths.len() > 1) {
+        for path in paths {
+            let others: Vec<&str> = paths
+                .iter()
+                .copied()
+                .filter(|other| other != path)
+                .collect();
+            diagnostics.push(Diagnostic {
+                path: path.to_string(),
+                line: 1,
+                kind: DiagnosticKind::CaseClash,
+                message: format!(
+                    "only differs by case from {}, they clash on case-insensitive filesystems",
+                    others.join(", ")
+                ),
+            });
+        }
+    }
+    diagnostics
+}
+
+/// Options the notes are parsed with.
+fn parser_options() -> Options {
+    Options::ENABLE_TABLES | Options::ENABLE_FOOTNOTES | Options::ENABLE_YAML_STYLE_METADATA_BLOCKS
+}
+
+/// Returns the line of an offset of a note, starting at 1.
+fn line_at(content: &str, offset: usize) -> usize {
+    content[..offset].matches('\n').count() + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(diagnostics: &[Diagnostic]) -> Vec<String> {
+        diagnostics.iter().map(Diagnostic::to_string).collect()
+    }
+
+    #[test]
+    fn test_check_links() {
+        let files = VaultFiles::new(
+            [
+                "docs/guide.md",
+                "My Note.md",
+                "a/note.md",
+                "b/note.md",
+                "img.png",
+            ]
+            .into_iter()
+            .map(String::from),
+        );
+
+        let content = "---\nrelated: \"[[frontmatter]]\"\n---\n\n\
+[[Guide]] [[docs/guide#Part|the guide]] ![[img.png]] [[#Heading]]\n\
+[[missing]] [[note]] ![[lost.png|300]]\n\
+[ok](../My%20Note.md) [gone](gone.md#part) ![pic](pic.jpg) [site](https://x.org) [top](#top)\n\n\
+`[[code]]`\n\n<div>\n[[html]]\n</div>\n\n```\n[[block]]\n```\n";
+        assert_eq!(
+            messages(&check_links("docs/page.md", content, &files)),
+            vec![
+                "docs/page.md:6: broken link 'missing'",
+                "docs/page.md:6: ambiguous link 'note' matches a/note.md, b/note.md, use a longer path to pick one",
+                "docs/page.md:6: missing attachment 'lost.png'",
+                "docs/page.md:7: broken link 'gone.md#part'",
+                "docs/page.md:7: missing attachment 'pic.jpg'",
+            ]
+        );
+        assert_eq!(
+            check_links("docs/page.md", content, &files)[1].kind,
+            DiagnosticKind::AmbiguousLink
+        );
+    }
+
+    #[test]
+    fn test_check_shortcodes() {
+        let content = "---\ntitle: \"{{ title() }}\"\n---\n\
+{{ youtube(id=\"x\") }}\n\
+{% note(kind=\"info\") %}\nBody\n{% end %}\n\
+`{{ gist(url=\"x\") }}` {{/* escaped() */}}\n\
+```\n{% quote() %}\n```\n\
+{% if user %}{{ user.name }}{% endif %}\n\
+{% details() %}\nNo end\n{% end %}\n{% end %}\n{%- aside() -%}\n";
+        assert_eq!(
+            messages(&check_shortcodes("a.md", content)),
+            vec![
+                "a.md:8: Zola runs shortcodes in code too, escape 'gist' as {{/* gist(...) */}}",
+                "a.md:10: Zola runs shortcodes in code too, escape 'quote' as {%/* quote(...) */%}",
+                "a.md:16: {% end %} closes no shortcode",
+                "a.md:17: shortcode 'aside' has no {% end %}",
+            ]
+        );
+        assert!(check_shortcodes("a.md", "No shortcodes {{ here }}").is_empty());
+    }
+
+    #[test]
+    fn test_check_frontmatter() {
+        let check = |note: &str, content: &str| {
+            let mut diagnostics = Vec::new();
+            let frontmatter = check_frontmatter(note, content, &mut diagnostics);
+            (frontmatter.is_some(), messages(&diagnostics))
+        };
+
+        let content = "---\ntitle: Note\ndate: 2024-01-31\nweight: -1\ndraft: \"no\"\n\
+tags: [a]\ntaxonomies:\n  tags: [a, b]\naliases: [old]\nextra:\n  mood: good\n---\nBody\n";
+        assert_eq!(
+            check("note.md", content),
+            (
+                true,
+                vec![
+                    "note.md:4: frontmatter field 'weight' should be a positive integer".to_string(),
+                    "note.md:5: frontmatter field 'draft' should be true or false".to_string(),
+                    "note.md:6: Zola doesn't know the frontmatter field 'tags', move it under 'extra' or 'taxonomies'".to_string(),
+                ]
+            )
+        );
+        assert_eq!(
+            check("note.md", "---\ndate: 31/01/2024\ntaxonomies:\n  tags: a\n---\n").1,
+            vec![
+                "note.md:2: frontmatter field 'date' should be a date like 2024-01-31 or 2024-01-31T10:00:00Z",
+                "note.md:3: frontmatter field 'taxonomies' should be a map of lists of strings",
+            ]
+        );
+
+        // Sections have their own fields
+        assert!(check(
+            "docs/_index.md",
+            "---\nsort_by: date\npaginate_by: 5\n---\n"
+        )
+        .1
+        .is_empty());
+        assert_eq!(
+            check("docs/_index.md", "---\ndate: 2024-01-31\n---\n").1,
+            vec!["docs/_index.md:2: Zola doesn't know the frontmatter field 'date', move it under 'extra'"]
+        );
+
+        // Invalid YAML is reported at its line
+        assert_eq!(
+            check("note.md", "---\ntitle: Note\nbad: [unclosed\n---\n"),
+            (
+                false,
+                vec![
+                    "note.md:4: invalid frontmatter: did not find expected ',' or ']'".to_string()
+                ]
+            )
+        );
+        assert_eq!(check("note.md", "No frontmatter"), (false, Vec::new()));
+        assert_eq!(check("note.md", "---\n---\nEmpty"), (true, Vec::new()));
+    }
+
+    #[test]
+    fn test_page_url() {
+        let frontmatter =
+            |yaml: &str| obsidian_export::serde_yaml::from_str::<Mapping>(yaml).unwrap();
+        assert_eq!(
+            page_url("Blog Posts/My Note.md", None),
+            "blog-posts/my-note"
+        );
+        assert_eq!(page_url("blog/2024-01-31-first.md", None), "blog/first");
+        assert_eq!(page_url("blog/post/index.md", None), "blog/post");
+        assert_eq!(page_url("blog/_index.md", None), "blog");
+        assert_eq!(
+            page_url("blog/a.md", Some(&frontmatter("slug: Custom Slug"))),
+            "blog/custom-slug"
+        );
+        assert_eq!(
+            page_url("blog/a.md", Some(&frontmatter("path: /elsewhere/"))),
+            "elsewhere"
+        );
+    }
+
+    #[test]
+    fn test_check_vault() {
+        let vault = tempfile::TempDir::new().unwrap();
+        let write = |path: &str, content: &str| {
+            let path = vault.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        };
+        write("My Note.md", "[[dup]]");
+        write("a/dup.md", "");
+        write("b/dup.md", "");
+        write("my-note.md", "");
+        write("Other.md", "");
+        write("other.md", "");
+        write("board.canvas", "{}");
+        write("board/index.md", "");
+        write("raw/page.md", "+++\ntitle = \"Raw\"\n+++\n[[missing]]");
+        write("raw/Page.md", "");
+        write(
+            "drawings/flow.excalidraw.md",
+            "---\nexcalidraw-plugin: parsed\n---\n",
+        );
+        write(
+            "clean.md",
+            "---\ntitle: Clean\n---\n[[My Note]] ![[flow.excalidraw]] [draft](drafts/wip.md)",
+        );
+        write("drafts/wip.md", "");
+        write(".export-ignore", "drafts/\n");
+
+        fn not_raw(entry: &ignore::DirEntry) -> bool {
+            !entry
+                .path()
+                .parent()
+                .is_some_and(|parent| parent.ends_with("raw"))
+        }
+        let walk_options = WalkOptions {
+            filter_fn: Some(&not_raw),
+            ..WalkOptions::default()
+        };
+        let diagnostics = check_vault(vault.path(), &walk_options).unwrap();
+        assert_eq!(
+            messages(&diagnostics),
+            vec![
+                "My Note.md:1: ambiguous link 'dup' matches a/dup.md, b/dup.md, use a longer path to pick one",
+                "My Note.md:1: published at /my-note/ like my-note.md, Zola would only keep one of them",
+                "Other.md:1: only differs by case from other.md, they clash on case-insensitive filesystems",
+                "Other.md:1: published at /other/ like other.md, Zola would only keep one of them",
+                "board.canvas:1: published at /board/ like board/index.md, Zola would only keep one of them",
+                "board/index.md:1: published at /board/ like board.canvas, Zola would only keep one of them",
+                "clean.md:4: broken link 'drafts/wip.md'",
+                "my-note.md:1: published at /my-note/ like My Note.md, Zola would only keep one of them",
+                "other.md:1: only differs by case from Other.md, they clash on case-insensitive filesystems",
+                "other.md:1: published at /other/ like Other.md, Zola would only keep one of them",
+                "raw/Page.md:1: only differs by case from raw/page.md, they clash on case-insensitive filesystems",
+                "raw/Page.md:1: published at /raw/page/ like raw/page.md, Zola would only keep one of them",
+                "raw/page.md:1: only differs by case from raw/Page.md, they clash on case-insensitive filesystems",
+                "raw/page.md:1: published at /raw/page/ like raw/Page.md, Zola would only keep one of them",
+            ]
+        );
+    }
+}
@@ -0,0 +1,1208 @@
+//! Evaluation of simple Dataview queries (```` ```dataview ```` blocks) at export time.
+//!
+//! Zola has no equivalent of the Dataview plugin, so the common subset of its query language
+//! is evaluated against the frontmatter of every note in the vault and replaced with static
+//! markdown:
+//!
+//! ```text
+//! LIST [field] | TABLE [WITHOUT ID] field [AS "Header"], ...
+//! [FROM #tag | "folder" [and|or [-]#tag | "folder" ...]]
+//! [WHERE condition] [SORT field [ASC|DESC], ...] [LIMIT n]
+//! ```
+//!
+//! Conditions compare fields with `=`, `!=`, `<`, `<=`, `>` and `>=`, may use
+//! `contains(field, value)` and `!`, and are combined with `and`/`or`. Besides frontmatter
+//! fields, `file.name`, `file.path`, `file.folder` and `file.tags` are available.
+//! Anything else is reported as unsupported.
+//!
+//! Dataview's inline fields (`key:: value` lines in the note body) are fields too, and are
+//! also copied to the `extra` frontmatter of exported notes.
+
+use crate::utils::{encode_asset_url_path, is_excalidraw_file, is_markdown_file};
+use crate::vault::{resolve_wikilink, VaultFiles};
+use obsidian_export::serde_yaml::{self, Mapping};
+use regex::Regex;
+use std::cmp::Ordering;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// A query that can't be evaluated by the built-in evaluator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsupportedQuery {
+    pub reason: String,
+}
+
+impl UnsupportedQuery {
+    fn new(reason: impl Into<String>) -> Self {
+        Self {
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for UnsupportedQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.reason)
+    }
+}
+
+/// A field value, converted from frontmatter.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    List(Vec<Value>),
+}
+
+impl Value {
+    fn from_yaml(value: &serde_yaml::Value) -> Value {
+        match value {
+            serde_yaml::Value::Bool(b) => Value::Bool(*b),
+            serde_yaml::Value::Number(n) => n.as_f64().map_or(Value::Null, Value::Number),
+            serde_yaml::Value::String(s) => Value::String(s.clone()),
+            serde_yaml::Value::Sequence(items) => {
+                Value::List(items.iter().map(Value::from_yaml).collect())
+            }
+            serde_yaml::Value::Tagged(tagged) => Value::from_yaml(&tagged.value),
+            serde_yaml::Value::Null | serde_yaml::Value::Mapping(_) => Value::Null,
+        }
+    }
+
+    fn is_truthy(&self) -> bool {
+        match self {
+            Value::Null => false,
+            Value::Bool(b) => *b,
+            Value::Number(n) => *n != 0.0,
+            Value::String(s) => !s.is_empty(),
+            Value::List(items) => !items.is_empty(),
+        }
+    }
+
+    /// Orders values of different types as null < booleans < numbers < strings < lists.
+    fn compare(&self, other: &Value) -> Ordering {
+        fn rank(value: &Value) -> u8 {
+            match value {
+                Value::Null => 0,
+                Value::Bool(_) => 1,
+                Value::Number(_) => 2,
+                Value::String(_) => 3,
+                Value::List(_) => 4,
+            }
+        }
+
+        match (self, other) {
+            (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+            (Value::Number(a), Value::Number(b)) => a.total_cmp(b),
+            (Value::String(a), Value::String(b)) => a.cmp(b),
+            (Value::List(a), Value::List(b)) => a
+                .iter()
+                .zip(b)
+                .map(|(a, b)| a.compare(b))
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or_else(|| a.len().cmp(&b.len())),
+            _ => rank(self).cmp(&rank(other)),
+        }
+    }
+
+    fn contains(&self, needle: &Value) -> bool {
+        match (self, needle) {
+            (Value::List(items), _) => items.iter().any(|item| item.compare(needle).is_eq()),
+            (Value::String(haystack), Value::String(needle)) => haystack.contains(needle.as_str()),
+            _ => false,
+        }
+    }
+}
+
+/// A note of the vault with the fields queries can use.
+#[derive(Debug, Clone)]
+pub struct IndexedNote {
+    /// The vault-relative path of the note
+    pub path: String,
+    /// Frontmatter fields, followed by the inline fields of the body
+    pub fields: Mapping,
+    /// Frontmatter and inline tags, without `#`
+    pub tags: Vec<String>,
+}
+
+impl IndexedNote {
+    /// Indexes a note from its vault-relative path and raw content.
+    pub fn new(path: String, content: &str) -> Self {
+        let (mut fields, body) = split_frontmatter(content);
+        let mut tags = frontmatter_tags(&fields);
+        for tag in inline_tags(body) {
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+
+        for (key, value) in inline_fields(body) {
+            let key = serde_yaml::Value::from(key);
+            if !fields.contains_key(&key) {
+                fields.insert(key, inline_field_value(value));
+            }
+        }
+
+        Self { path, fields, tags }
+    }
+
+    /// Returns the file name without the extension.
+    pub fn name(&self) -> &str {
+        let file_name = self.path.rsplit('/').next().unwrap_or(&self.path);
+        file_name
+            .rsplit_once('.')
+            .map_or(file_name, |(stem, _)| stem)
+    }
+
+    fn folder(&self) -> &str {
+        self.path.rsplit_once('/').map_or("", |(folder, _)| folder)
+    }
+
+    fn field(&self, name: &str) -> Value {
+        match name {
+            "file.name" | "file.link" => return Value::String(self.name().to_string()),
+            "file.path" => return Value::String(self.path.clone()),
+            "file.folder" => return Value::String(self.folder().to_string()),
+            "file.tags" => {
+                return Value::List(
+                    self.tags
+                        .iter()
+                        .map(|tag| Value::String(format!("#{}", tag)))
+                        .collect(),
+                )
+            }
+            _ => {}
+        }
+
+        // Dataview also makes fields available under a lowercased, dashed key
+        let normalized = |key: &str| key.trim().to_lowercase().replace(' ', "-");
+        self.fields
+            .get(name)
+            .or_else(|| {
+                self.fields
+                    .iter()
+                    .find(|(key, _)| {
+                        key.as_str()
+                            .is_some_and(|key| normalized(key) == normalized(name))
+                    })
+                    .map(|(_, value)| value)
+            })
+            .map_or(Value::Null, Value::from_yaml)
+    }
+
+    fn has_tag(&self, tag: &str) -> bool {
+        let tag = tag.to_lowercase();
+        self.tags.iter().any(|own| {
+            let own = own.to_lowercase();
+            own == tag || own.starts_with(&format!("{}/", tag))
+        })
+    }
+}
+
+/// The notes of a vault, indexed once and shared by all queries of an export.
+#[derive(Debug, Clone, Default)]
+pub struct VaultIndex {
+    pub notes: Vec<IndexedNote>,
+}
+
+impl VaultIndex {
+    /// Indexes the notes an export converts, skipping Excalidraw drawings. Files the export
+    /// leaves out or copies as passthrough files aren't pages and aren't indexed.
+    ///
+    /// # Arguments
+    ///
+    /// * `source_dir` - The source vault directory
+    /// * `files` - The files of the export, see [`VaultFiles::walk`]
+    ///
+    /// # Returns
+    ///
+    /// The index. Notes that can't be read are left out.
+    pub fn build(source_dir: &Path, files: &VaultFiles) -> Self {
+        let notes = files
+            .exported()
+            .filter(|path| is_markdown_file(path) && !is_excalidraw_file(path))
+            .filter_map(|path| {
+                let content = fs::read_to_string(source_dir.join(path)).ok()?;
+                Some(IndexedNote::new(path.to_string(), &content))
+            })
+            .filter(|note| !note.fields.contains_key("excalidraw-plugin"))
+            .collect();
+
+        Self { notes }
+    }
+
+    /// Finds the note a wikilink target (`Note` or `folder/Note`) points at, the first one
+    /// when it is ambiguous.
+    fn lookup(&self, target: &str) -> Option<&IndexedNote> {
+        let paths = self.notes.iter().map(|note| note.path.as_str());
+        let path = *resolve_wikilink(paths, target).first()?;
+        self.notes.iter().find(|note| note.path == path)
+    }
+}
+
+/// What a query renders.
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryKind {
+    /// A list of note links, optionally followed by a field
+    List(Option<String>),
+    /// A table with a column per field
+    Table { columns: Vec<Column>, with_id: bool },
+}
+
+/// A column of a `TABLE` query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    pub field: String,
+    pub header: String,
+}
+
+/// A `FROM` source.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    Tag(String),
+    Folder(String),
+    Not(Box<Source>),
+    And(Box<Source>, Box<Source>),
+    Or(Box<Source>, Box<Source>),
+}
+
+impl Source {
+    fn matches(&self, note: &IndexedNote) -> bool {
+        match self {
+            Source::Tag(tag) => note.has_tag(tag),
+            Source::Folder(folder) => {
+                let folder = folder.trim_matches('/');
+                folder.is_empty()
+                    || note.path.starts_with(&format!("{}/", folder))
+                    || note.path.strip_suffix(".md") == Some(folder)
+            }
+            Source::Not(source) => !source.matches(note),
+            Source::And(a, b) => a.matches(note) && b.matches(note),
+            Source::Or(a, b) => a.matches(note) || b.matches(note),
+        }
+    }
+}
+
+/// Comparison operators of `WHERE` conditions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+}
+
+/// An expression of a `WHERE` condition.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Field(String),
+    Literal(Value),
+    Contains(Box<Expr>, Box<Expr>),
+    Compare(Box<Expr>, Comparison, Box<Expr>),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn eval(&self, note: &IndexedNote) -> Value {
+        match self {
+            Expr::Field(name) => note.field(name),
+            Expr::Literal(value) => value.clone(),
+            Expr::Contains(haystack, needle) => {
+                Value::Bool(haystack.eval(note).contains(&needle.eval(note)))
+            }
+            Expr::Compare(left, comparison, right) => {
+                let ordering = left.eval(note).compare(&right.eval(note));
+                Value::Bool(match comparison {
+                    Comparison::Eq => ordering.is_eq(),
+                    Comparison::NotEq => ordering.is_ne(),
+                    Comparison::Lt => ordering.is_lt(),
+                    Comparison::LtEq => ordering.is_le(),
+                    Comparison::Gt => ordering.is_gt(),
+                    Comparison::GtEq => ordering.is_ge(),
+                })
+            }
+            Expr::Not(expr) => Value::Bool(!expr.eval(note).is_truthy()),
+            Expr::And(a, b) => Value::Bool(a.eval(note).is_truthy() && b.eval(note).is_truthy()),
+            Expr::Or(a, b) => Value::Bool(a.eval(note).is_truthy() || b.eval(note).is_truthy()),
+        }
+    }
+}
+
+/// A parsed Dataview query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    pub kind: QueryKind,
+    pub from: Option<Source>,
+    pub filters: Vec<Expr>,
+    /// Fields to sort by, `true` for descending
+    pub sort: Vec<(String, bool)>,
+    pub limit: Option<usize>,
+}
+
+impl Query {
+    /// Parses a Dataview query.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The content of a ```` ```dataview ```` block
+    ///
+    /// # Returns
+    ///
+    /// The query, or the reason it isn't supported.
+    pub fn parse(source: &str) -> Result<Query, UnsupportedQuery> {
+        let tokens = tokenize(source)?;
+        Parser { tokens, pos: 0 }.query()
+    }
+
+    /// Runs the query against the vault.
+    ///
+    /// # Returns
+    ///
+    /// The matching notes, sorted and limited.
+    pub fn run<'a>(&self, index: &'a VaultIndex) -> Vec<&'a IndexedNote> {
+        let mut notes: Vec<&IndexedNote> = index
+            .notes
+            .iter()
+            .filter(|note| self.from.as_ref().is_none_or(|from| from.matches(note)))
+            .filter(|note| {
+                self.filters
+                    .iter()
+                    .all(|filter| filter.eval(note).is_truthy())
+            })
+            .collect();
+
+        if self.sort.is_empty() {
+            notes.sort_by(|a, b| a.path.cmp(&b.path));
+        } else {
+            notes.sort_by(|a, b| {
+                self.sort
+                    .iter()
+                    .map(|(field, descending)| {
+                        let ordering = a.field(field).compare(&b.field(field));
+                        if *descending {
+                            ordering.reverse()
+                        } else {
+                            ordering
+                        }
+                    })
+                    .find(|ordering| ordering.is_ne())
+                    .unwrap_or(Ordering::Equal)
+            });
+        }
+
+        if let Some(limit) = self.limit {
+            notes.truncate(limit);
+        }
+        notes
+    }
+
+    /// Renders the query results as markdown. Note links are relative to the note containing
+    /// the query, like the links obsidian-export generates.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The vault index
+    /// * `current_dir` - The vault-relative directory of the note containing the query
+    ///
+    /// # Returns
+    ///
+    /// A markdown list or table.
+    pub fn render(&self, index: &VaultIndex, current_dir: &Path) -> String {
+        let notes = self.run(index);
+        if notes.is_empty() {
+            return "*No results*\n".to_string();
+        }
+
+        let prefix = "../".repeat(current_dir.components().count());
+        let link = |note: &IndexedNote| {
+            format!(
+                "[{}]({})",
+                escape_markdown(note.name()),
+                encode_asset_url_path(&format!("{}{}", prefix, note.path))
+            )
+        };
+        let value = |note: &IndexedNote, field: &str| {
+            format_value(&note.field(field), &|target| index.lookup(target).map(link))
+        };
+
+        match &self.kind {
+            QueryKind::List(field) => notes
+                .iter()
+                .map(|note| match field {
+                    Some(field) => format!("- {}: {}\n", link(note), value(note, field)),
+                    None => format!("- {}\n", link(note)),
+                })
+                .collect(),
+            QueryKind::Table { columns, with_id } => {
+                let mut headers: Vec<String> = Vec::new();
+                if *with_id {
+                    headers.push("File".to_string());
+                }
+                headers.extend(columns.iter().map(|column| escape_cell(&column.header)));
+
+                let mut table = format!(
+                    "|{}|\n|{}|\n",
+                    headers.join("|"),
+                    vec!["---"; headers.len()].join("|")
+                );
+                for note in notes {
+                    let mut cells = Vec::new();
+                    if *with_id {
+                        cells.push(link(note));
+                    }
+                    cells.extend(
+                        columns
+                            .iter()
+                            .map(|column| escape_cell(&value(note, &column.field))),
+                    );
+                    table.push_str(&format!("|{}|\n", cells.join("|")));
+                }
+                table
+            }
+        }
+    }
+}
+
+/// Formats a value for display, turning `[[wikilinks]]` in strings into links.
+fn format_value(value: &Value, link: &dyn Fn(&str) -> Option<String>) -> String {
+    match value {
+        Value::Null => "-".to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => {
+            let target = s
+                .strip_prefix("[[")
+                .and_then(|s| s.strip_suffix("]]"))
+                .map(|target| target.split(['|', '#']).next().unwrap_or(target));
+            target.and_then(link).unwrap_or_else(|| escape_markdown(s))
+        }
+        Value::List(items) => items
+            .iter()
+            .map(|item| format_value(item, link))
+            .collect::<Vec<_>>()
+            .join(", "),
+    }
+}
+
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '[' | ']' | '*' | '_' | '`' | '<' | '>') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn escape_cell(text: &str) -> String {
+    text.replace('|', "\\|").replace(['\r', '\n'], " ")
+}
+
+/// How inline fields are rendered in the body of exported notes. They are copied to the
+/// `extra` frontmatter in every mode.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum InlineFieldMode {
+    /// Leave the `key:: value` lines as they are
+    #[default]
+    Keep,
+    /// Remove the `key:: value` lines
+    Strip,
+    /// Render consecutive fields as a definition list
+    List,
+}
+
+/// Parses a `key:: value` inline field line.
+///
+/// # Arguments
+///
+/// * `line` - A line of text
+///
+/// # Returns
+///
+/// The trimmed key and value, or `None` if the line isn't an inline field.
+pub fn parse_inline_field(line: &str) -> Option<(&str, &str)> {
+    let (key, value) = line.split_once("::")?;
+    // Bold keys (`**key**:: value`) are common
+    let key = key.trim().trim_matches('*');
+    let valid_key = key
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_alphanumeric() || c == '_')
+        && key
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | ' ' | '/'));
+    valid_key.then(|| (key, value.trim()))
+}
+
+/// Converts the text of an inline field value to a frontmatter value: numbers and booleans
+/// are parsed like Dataview does, anything else is kept as a string.
+///
+/// # Arguments
+///
+/// * `value` - The trimmed value text
+///
+/// # Returns
+///
+/// The frontmatter value, null for empty values.
+pub fn inline_field_value(value: &str) -> serde_yaml::Value {
+    if value.is_empty() {
+        return serde_yaml::Value::Null;
+    }
+    if let Ok(b) = value.parse::<bool>() {
+        return serde_yaml::Value::Bool(b);
+    }
+    if let Ok(n) = value.parse::<i64>() {
+        return serde_yaml::Value::from(n);
+    }
+    match value.parse::<f64>() {
+        Ok(n) if n.is_finite() => serde_yaml::Value::from(n),
+        _ => serde_yaml::Value::from(value),
+    }
+}
+
+/// Finds the `key:: value` lines of a note body (list items included), outside of code
+/// blocks.
+fn inline_fields(body: &str) -> Vec<(&str, &str)> {
+    let mut fields = Vec::new();
+    let mut in_code = false;
+    for line in body.lines() {
+        let line = line.trim_start();
+        if line.starts_with("```") {
+            in_code = !in_code;
+            continue;
+        }
+        if in_code {
+            continue;
+        }
+        let line = line
+            .strip_prefix(['-', '*', '+'])
+            .filter(|rest| rest.starts_with(' '))
+            .unwrap_or(line);
+        if let Some(field) = parse_inline_field(line) {
+            fields.push(field);
+        }
+    }
+    fields
+}
+
+/// Splits a note into its parsed frontmatter and body.
+fn split_frontmatter(content: &str) -> (Mapping, &str) {
+    let Some(rest) = content
+        .strip_prefix("---\n")
+        .or_else(|| content.strip_prefix("---\r\n"))
+    else {
+        return (Mapping::new(), content);
+    };
+
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == "---" {
+            let frontmatter = serde_yaml::from_str(&rest[..offset]).unwrap_or_default();
+            return (frontmatter, &rest[offset + line.len()..]);
+        }
+        offset += line.len();
+    }
+    (Mapping::new(), content)
+}
+
+/// Reads the `tags` (or `tag`) frontmatter field, a list or a comma/space separated string.
+pub(crate) fn frontmatter_tags(frontmatter: &Mapping) -> Vec<String> {
+    let value = frontmatter.get("tags").or_else(|| frontmatter.get("tag"));
+    let raw: Vec<String> = match value {
+        Some(serde_yaml::Value::Sequence(items)) => items
+            .iter()
+            .filter_map(|item| item.as_str().map(str::to_string))
+            .collect(),
+        Some(serde_yaml::Value::String(s)) => s.split([',', ' ']).map(str::to_string).collect(),
+        _ => Vec::new(),
+    };
+
+    raw.iter()
+        .map(|tag| tag.trim().trim_start_matches('#').to_string())
+        .filter(|tag| !tag.is_empty())
+        .collect()
+}
+
+/// Finds `#tags` in a note body, outside of code blocks.
+fn inline_tags(body: &str) -> Vec<String> {
+    static TAG_RE: OnceLock<Regex> = OnceLock::new();
+
+    let tag_re = TAG_RE.get_or_init(|| Regex::new(r"(?:^|\s)#([\p{L}\p{N}_/-]+)").unwrap());
+
+    let mut tags = Vec::new();
+    let mut in_code = false;
+    for line in body.lines() {
+        if line.trim_start().starts_with("```") {
+            in_code = !in_code;
+            continue;
+        }
+        if in_code {
+            continue;
+        }
+        for caps in tag_re.captures_iter(line) {
+            let tag = caps[1].to_string();
+            // Tags need at least one non-numerical character
+            if !tag.chars().all(|c| c.is_ascii_digit()) && !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+    }
+    tags
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    String(String),
+    Number(f64),
+    Tag(String),
+    Comparison(Comparison),
+    Bang,
+    Minus,
+    Comma,
+    LParen,
+    RParen,
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, UnsupportedQuery> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '"' => {
+                chars.next();
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('\\') => value.extend(chars.next()),
+                        Some('"') => break,
+                        Some(c) => value.push(c),
+                        None => return Err(UnsupportedQuery::new("unterminated string")),
+                    }
+                }
+                tokens.push(Token::String(value));
+            }
+            '#' => {
+                chars.next();
+                let mut tag = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_alphanumeric() || matches!(c, '_' | '-' | '/') {
+                        tag.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                tokens.push(Token::Tag(tag));
+            }
+            '=' | '!' | '<' | '>' => {
+                chars.next();
+                let followed_by_eq = chars.next_if_eq(&'=').is_some();
+                tokens.push(match (c, followed_by_eq) {
+                    ('=', _) => Token::Comparison(Comparison::Eq),
+                    ('!', true) => Token::Comparison(Comparison::NotEq),
+                    ('!', false) => Token::Bang,
+                    ('<', true) => Token::Comparison(Comparison::LtEq),
+                    ('<', false) => Token::Comparison(Comparison::Lt),
+                    ('>', true) => Token::Comparison(Comparison::GtEq),
+                    _ => Token::Comparison(Comparison::Gt),
+                });
+            }
+            '-' => {
+                chars.next();
+                tokens.push(Token::Minus);
+            }
+            ',' => {
+                chars.next();
+                tokens.push(Token::Comma);
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::LParen);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::RParen);
+            }
+            c if c.is_ascii_digit() => {
+                let mut number = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_ascii_digit() || c == '.' {
+                        number.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                let number = number
+                    .parse()
+                    .map_err(|_| UnsupportedQuery::new(format!("invalid number '{}'", number)))?;
+                tokens.push(Token::Number(number));
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_alphanumeric() || matches!(c, '_' | '-' | '.') {
+                        word.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                tokens.push(Token::Word(word));
+            }
+            '[' => return Err(UnsupportedQuery::new("links as sources or values")),
+            c => return Err(UnsupportedQuery::new(format!("unexpected '{}'", c))),
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// Whether the next token is the keyword `keyword` (case-insensitive).
+    fn at_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let at = self.at_keyword(keyword);
+        if at {
+            self.pos += 1;
+        }
+        at
+    }
+
+    fn at_clause(&self) -> bool {
+        ["FROM", "WHERE", "SORT", "LIMIT", "GROUP", "FLATTEN"]
+            .iter()
+            .any(|keyword| self.at_keyword(keyword))
+    }
+
+    fn query(mut self) -> Result<Query, UnsupportedQuery> {
+        let kind = if self.eat_keyword("LIST") {
+            let field = if self.peek().is_some() && !self.at_clause() {
+                Some(self.field()?)
+            } else {
+                None
+            };
+            QueryKind::List(field)
+        } else if self.eat_keyword("TABLE") {
+            let with_id = !(self.eat_keyword("WITHOUT") && self.eat_keyword("ID"));
+            let mut columns = Vec::new();
+            while self.peek().is_some() && !self.at_clause() {
+                let field = self.field()?;
+                let header = if self.eat_keyword("AS") {
+                    match self.next() {
+                        Some(Token::String(header)) | Some(Token::Word(header)) => header,
+                        _ => return Err(UnsupportedQuery::new("expected a column name after AS")),
+                    }
+                } else {
+                    field.clone()
+                };
+                columns.push(Column { field, header });
+                if self.peek() == Some(&Token::Comma) {
+                    self.next();
+                }
+            }
+            QueryKind::Table { columns, with_id }
+        } else {
+            let kind = match self.peek() {
+                Some(Token::Word(word)) => word.to_uppercase(),
+                _ => "this".to_string(),
+            };
+            return Err(UnsupportedQuery::new(format!("{} queries", kind)));
+        };
+
+        let from = if self.eat_keyword("FROM") {
+            Some(self.sources()?)
+        } else {
+            None
+        };
+
+        let mut query = Query {
+            kind,
+            from,
+            filters: Vec::new(),
+            sort: Vec::new(),
+            limit: None,
+        };
+        while let Some(token) = self.next() {
+            match token {
+                Token::Word(word) if word.eq_ignore_ascii_case("WHERE") => {
+                    query.filters.push(self.or_expr()?);
+                }
+                Token::Word(word) if word.eq_ignore_ascii_case("SORT") => loop {
+                    let field = self.field()?;
+                    let descending = self.eat_keyword("DESC") || self.eat_keyword("DESCENDING");
+                    if !descending && !self.eat_keyword("ASC") {
+                        self.eat_keyword("ASCENDING");
+                    }
+                    query.sort.push((field, descending));
+                    if self.peek() != Some(&Token::Comma) {
+                        break;
+                    }
+                    self.next();
+                },
+                Token::Word(word) if word.eq_ignore_ascii_case("LIMIT") => match self.next() {
+                    Some(Token::Number(n)) if n >= 0.0 && n.fract() == 0.0 => {
+                        query.limit = Some(n as usize)
+                    }
+                    _ => return Err(UnsupportedQuery::new("LIMIT needs a whole number")),
+                },
+                Token::Word(word) => {
+                    return Err(UnsupportedQuery::new(format!(
+                        "{} clauses",
+                        word.to_uppercase()
+                    )))
+                }
+                token => return Err(UnsupportedQuery::new(format!("unexpected {:?}", token))),
+            }
+        }
+
+        Ok(query)
+    }
+
+    fn field(&mut self) -> Result<String, UnsupportedQuery> {
+        match self.next() {
+            Some(Token::Word(word)) if self.peek() == Some(&Token::LParen) => {
+                Err(UnsupportedQuery::new(format!("the {}() function", word)))
+            }
+            Some(Token::Word(word)) => Ok(word),
+            _ => Err(UnsupportedQuery::new("expected a field name")),
+        }
+    }
+
+    fn sources(&mut self) -> Result<Source, UnsupportedQuery> {
+        let mut source = self.source()?;
+        loop {
+            if self.eat_keyword("AND") {
+                source = Source::And(Box::new(source), Box::new(self.source()?));
+            } else if self.eat_keyword("OR") {
+                source = Source::Or(Box::new(source), Box::new(self.source()?));
+            } else {
+                return Ok(source);
+            }
+        }
+    }
+
+    fn source(&mut self) -> Result<Source, UnsupportedQuery> {
+        match self.next() {
+            Some(Token::Tag(tag)) => Ok(Source::Tag(tag)),
+            Some(Token::String(folder)) => Ok(Source::Folder(folder)),
+            Some(Token::Minus) | Some(Token::Bang) => Ok(Source::Not(Box::new(self.source()?))),
+            Some(Token::LParen) => {
+                let source = self.sources()?;
+                match self.next() {
+                    Some(Token::RParen) => Ok(source),
+                    _ => Err(UnsupportedQuery::new("expected ')'")),
+                }
+            }
+            _ => Err(UnsupportedQuery::new(
+                "only #tags and \"folders\" are supported as sources",
+            )),
+        }
+    }
+
+    fn or_expr(&mut self) -> Result<Expr, UnsupportedQuery> {
+        let mut expr = self.and_expr()?;
+        while self.eat_keyword("OR") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and_expr()?));
+        }
+        Ok(expr)
+    }
+
+    fn and_expr(&mut self) -> Result<Expr, UnsupportedQuery> {
+        let mut expr = self.unary()?;
+        while self.eat_keyword("AND") {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, UnsupportedQuery> {
+        if self.peek() == Some(&Token::Bang) {
+            self.next();
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+
+        let left = self.operand()?;
+        match self.peek() {
+            Some(Token::Comparison(comparison)) => {
+                let comparison = *comparison;
+                self.next();
+                let right = self.operand()?;
+                Ok(Expr::Compare(Box::new(left), comparison, Box::new(right)))
+            }
+            _ => Ok(left),
+        }
+    }
+
+    fn operand(&mut self) -> Result<Expr, UnsupportedQuery> {
+        match self.next() {
+            Some(Token::LParen) => {
+                let expr = self.or_expr()?;
+                match self.next() {
+                    Some(Token::RParen) => Ok(expr),
+                    _ => Err(UnsupportedQuery::new("expected ')'")),
+                }
+            }
+            Some(Token::String(s)) => Ok(Expr::Literal(Value::String(s))),
+            Some(Token::Number(n)) => Ok(Expr::Literal(Value::Number(n))),
+            Some(Token::Minus) => match self.next() {
+                Some(Token::Number(n)) => Ok(Expr::Literal(Value::Number(-n))),
+                _ => Err(UnsupportedQuery::new("arithmetic")),
+            },
+            Some(Token::Tag(tag)) => Ok(Expr::Literal(Value::String(format!("#{}", tag)))),
+            Some(Token::Word(word)) if self.peek() == Some(&Token::LParen) => {
+                if !word.eq_ignore_ascii_case("contains") {
+                    return Err(UnsupportedQuery::new(format!("the {}() function", word)));
+                }
+                self.next();
+                let haystack = self.operand()?;
+                if self.next() != Some(Token::Comma) {
+                    return Err(UnsupportedQuery::new("contains() takes two arguments"));
+                }
+                let needle = self.operand()?;
+                if self.next() != Some(Token::RParen) {
+                    return Err(UnsupportedQuery::new("contains() takes two arguments"));
+                }
+                Ok(Expr::Contains(Box::new(haystack), Box::new(needle)))
+            }
+            Some(Token::Word(word)) => Ok(match word.to_lowercase().as_str() {
+                "true" => Expr::Literal(Value::Bool(true)),
+                "false" => Expr::Literal(Value::Bool(false)),
+                "null" => Expr::Literal(Value::Null),
+                _ => Expr::Field(word),
+            }),
+            _ => Err(UnsupportedQuery::new("expected a field or value")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index() -> VaultIndex {
+        VaultIndex {
+            notes: vec![
+                IndexedNote::new(
+                    "books/Dune.md".to_string(),
+                    "---\ntags: [book, scifi]\nrating: 5\nauthor: \"[[Frank Herbert]]\"\nstatus: read\n---\n# Dune\n",
+                ),
+                IndexedNote::new(
+                    "books/Emma.md".to_string(),
+                    "---\ntags: book\nrating: 3\nstatus: reading\n---\nA #classic novel.\n",
+                ),
+                IndexedNote::new(
+                    "books/Unrated.md".to_string(),
+                    "---\ntags: [book]\n---\n```\n#notatag\n```\n",
+                ),
+                IndexedNote::new(
+                    "people/Frank Herbert.md".to_string(),
+                    "# Frank\nborn:: 1920\n- **genre**:: scifi\n```\ncode:: ignored\n```\n",
+                ),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_build_index() {
+        let vault = tempfile::TempDir::new().unwrap();
+        fs::create_dir_all(vault.path().join("raw")).unwrap();
+        fs::write(vault.path().join("note.md"), "# Note").unwrap();
+        fs::write(vault.path().join("raw/page.md"), "# Passthrough").unwrap();
+        fs::write(vault.path().join("flow.excalidraw.md"), "# Drawing").unwrap();
+        fs::write(
+            vault.path().join("sketch.md"),
+            "---\nexcalidraw-plugin: parsed\n---\n# Drawing",
+        )
+        .unwrap();
+
+        fn not_raw(entry: &ignore::DirEntry) -> bool {
+            !entry
+                .path()
+                .components()
+                .any(|component| component.as_os_str() == "raw")
+        }
+        let walk_options = obsidian_export::WalkOptions {
+            filter_fn: Some(&not_raw),
+            ..Default::default()
+        };
+        let files = VaultFiles::walk(vault.path(), &walk_options).unwrap();
+        let index = VaultIndex::build(vault.path(), &files);
+        let paths: Vec<&str> = index.notes.iter().map(|note| note.path.as_str()).collect();
+        assert_eq!(paths, vec!["note.md"]);
+    }
+
+    #[test]
+    fn test_index_note() {
+        let index = index();
+        assert_eq!(index.notes[0].tags, vec!["book", "scifi"]);
+        assert_eq!(index.notes[1].tags, vec!["book", "classic"]);
+        assert_eq!(index.notes[2].tags, vec!["book"]);
+        assert_eq!(index.notes[0].field("rating"), Value::Number(5.0));
+        assert_eq!(index.notes[0].field("Rating"), Value::Number(5.0));
+        assert_eq!(
+            index.notes[1].field("file.folder"),
+            Value::String("books".to_string())
+        );
+        assert_eq!(index.notes[3].field("rating"), Value::Null);
+        assert_eq!(index.notes[3].field("born"), Value::Number(1920.0));
+        assert_eq!(
+            index.notes[3].field("genre"),
+            Value::String("scifi".to_string())
+        );
+        assert_eq!(index.notes[3].field("code"), Value::Null);
+    }
+
+    #[test]
+    fn test_parse_inline_field() {
+        assert_eq!(parse_inline_field("rating:: 5"), Some(("rating", "5")));
+        assert_eq!(
+            parse_inline_field("**Due Date**::  2024-01-05 "),
+            Some(("Due Date", "2024-01-05"))
+        );
+        assert_eq!(parse_inline_field("empty::"), Some(("empty", "")));
+        assert_eq!(parse_inline_field("no field here"), None);
+        assert_eq!(parse_inline_field(":: value"), None);
+        assert_eq!(parse_inline_field("see [link]:: value"), None);
+    }
+
+    #[test]
+    fn test_inline_field_value() {
+        assert_eq!(inline_field_value("5"), serde_yaml::Value::from(5));
+        assert_eq!(inline_field_value("2.5"), serde_yaml::Value::from(2.5));
+        assert_eq!(inline_field_value("true"), serde_yaml::Value::Bool(true));
+        assert_eq!(
+            inline_field_value("2024-01-05"),
+            serde_yaml::Value::from("2024-01-05")
+        );
+        assert_eq!(inline_field_value(""), serde_yaml::Value::Null);
+    }
+
+    #[test]
+    fn test_parse_query() {
+        let query = Query::parse(
+            "TABLE rating AS \"Stars\", status\nFROM #book and -\"archive\"\nWHERE rating >= 3 and !contains(file.tags, \"#draft\")\nSORT rating DESC, file.name\nLIMIT 10",
+        )
+        .unwrap();
+        assert_eq!(
+            query.kind,
+            QueryKind::Table {
+                columns: vec![
+                    Column {
+                        field: "rating".to_string(),
+                        header: "Stars".to_string()
+                    },
+                    Column {
+                        field: "status".to_string(),
+                        header: "status".to_string()
+                    },
+                ],
+                with_id: true,
+            }
+        );
+        assert_eq!(
+            query.from,
+            Some(Source::And(
+                Box::new(Source::Tag("book".to_string())),
+                Box::new(Source::Not(Box::new(Source::Folder("archive".to_string()))))
+            ))
+        );
+        assert_eq!(
+            query.sort,
+            vec![
+                ("rating".to_string(), true),
+                ("file.name".to_string(), false)
+            ]
+        );
+        assert_eq!(query.limit, Some(10));
+    }
+
+    #[test]
+    fn test_unsupported_queries() {
+        let reason = |query: &str| Query::parse(query).unwrap_err().reason;
+        assert_eq!(reason("TASK FROM #todo"), "TASK queries");
+        assert_eq!(reason("LIST FROM [[Index]]"), "links as sources or values");
+        assert_eq!(reason("LIST GROUP BY status"), "GROUP clauses");
+        assert_eq!(
+            reason("LIST WHERE date(due) < date(today)"),
+            "the date() function"
+        );
+    }
+
+    #[test]
+    fn test_run_query() {
+        let index = index();
+        let names = |query: &str| {
+            Query::parse(query)
+                .unwrap()
+                .run(&index)
+                .iter()
+                .map(|note| note.name().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(names("LIST FROM #book"), vec!["Dune", "Emma", "Unrated"]);
+        assert_eq!(names("LIST FROM #classic or #scifi"), vec!["Dune", "Emma"]);
+        assert_eq!(names("LIST FROM \"people\""), vec!["Frank Herbert"]);
+        assert_eq!(names("LIST FROM #book WHERE rating > 3"), vec!["Dune"]);
+        assert_eq!(
+            names("LIST FROM #book WHERE status = \"reading\" or !rating"),
+            vec!["Emma", "Unrated"]
+        );
+        assert_eq!(
+            names("LIST FROM #book SORT rating DESC LIMIT 2"),
+            vec!["Dune", "Emma"]
+        );
+    }
+
+    #[test]
+    fn test_render_query() {
+        let index = index();
+        let render =
+            |query: &str, dir: &str| Query::parse(query).unwrap().render(&index, Path::new(dir));
+
+        assert_eq!(
+            render("LIST author FROM #scifi", "notes"),
+            "- [Dune](../books/Dune.md): [Frank Herbert](../people/Frank%20Herbert.md)\n"
+        );
+        assert_eq!(
+            render("TABLE rating, status FROM #book WHERE rating SORT rating", ""),
+            "|File|rating|status|\n|---|---|---|\n|[Emma](books/Emma.md)|3|reading|\n|[Dune](books/Dune.md)|5|read|\n"
+        );
+        assert_eq!(
+            render("TABLE WITHOUT ID file.name AS \"Book\" FROM #nothing", ""),
+            "*No results*\n"
+        );
+    }
+}
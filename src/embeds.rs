@@ -0,0 +1,887 @@
+//! Rendering of embedded attachments (`![[talk.mp4]]`, `![[data.csv]]`) and external
+//! players (`![](https://www.youtube.com/watch?v=ID)`) for Zola.
+//!
+//! obsidian-export only turns embedded images into image tags; any other embedded file is
+//! emitted as a plain link, exactly like `[[talk.mp4]]` would be. To tell the two apart, the
+//! wikilink references of a note are read from its source in document order and matched
+//! against the links found in the exported events.
+
+use crate::utils::{decode_url_path, escape_html_attribute, quote_shortcode_string};
+use regex::Regex;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// Default maximum size of a code file embedded as a fenced code block.
+pub const DEFAULT_CODE_EMBED_MAX_BYTES: u64 = 64 * 1024;
+
+/// Default maximum size of a CSV/TSV file embedded as a table.
+pub const DEFAULT_TABLE_EMBED_MAX_BYTES: u64 = 256 * 1024;
+
+/// Default maximum number of rows (not counting the header) of an embedded table.
+pub const DEFAULT_TABLE_EMBED_MAX_ROWS: usize = 100;
+
+/// Kinds of attachments that are embedded as HTML5 elements.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaKind {
+    Video,
+    Audio,
+    Pdf,
+}
+
+impl MediaKind {
+    /// Determines the media kind from a file path or URL, based on its extension.
+    ///
+    /// The extensions are the ones Obsidian itself embeds as players.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The file path or URL, optionally with a `#fragment`
+    ///
+    /// # Returns
+    ///
+    /// The media kind, or `None` for any other file type.
+    pub fn from_path(path: &str) -> Option<Self> {
+        let path = path.split('#').next().unwrap_or_default();
+        let extension = path.rsplit_once('.')?.1.to_lowercase();
+        match extension.as_str() {
+            "mp4" | "webm" | "ogv" | "mov" | "mkv" => Some(MediaKind::Video),
+            "mp3" | "wav" | "m4a" | "ogg" | "3gp" | "flac" => Some(MediaKind::Audio),
+            "pdf" => Some(MediaKind::Pdf),
+            _ => None,
+        }
+    }
+}
+
+/// A `[[...]]` or `![[...]]` reference found in a note's source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WikiReference {
+    /// Whether the reference is an embed (`![[...]]`) rather than a link
+    pub is_embed: bool,
+    /// The referenced file, as written
+    pub file: Option<String>,
+    /// The section after `#`, as written (obsidian-export slugifies it in links)
+    pub section: Option<String>,
+    /// The label after `|`
+    pub label: Option<String>,
+}
+
+impl WikiReference {
+    /// Returns the text obsidian-export uses for the generated link.
+    pub fn display(&self) -> String {
+        match (&self.label, &self.file, &self.section) {
+            (Some(label), _, _) => label.clone(),
+            (None, Some(file), Some(section)) => format!("{} > {}", file, section),
+            (None, Some(file), None) => file.clone(),
+            (None, None, Some(section)) => section.clone(),
+            (None, None, None) => String::new(),
+        }
+    }
+}
+
+/// Matches `[[...]]` and `![[...]]` references.
+fn wiki_reference_regex() -> &'static Regex {
+    static REFERENCE_RE: OnceLock<Regex> = OnceLock::new();
+
+    REFERENCE_RE.get_or_init(|| Regex::new(r"(!?)\[\[([^\[\]]+)\]\]").unwrap())
+}
+
+/// Parses the text between the brackets of a wikilink (`Note#Section|Label`).
+fn parse_wiki_reference(is_embed: bool, text: &str) -> WikiReference {
+    let (target, label) = match text.split_once('|') {
+        Some((target, label)) => (target, Some(label.to_string())),
+        None => (text, None),
+    };
+    let (file, section) = match target.split_once('#') {
+        Some((file, section)) => (file, Some(section.trim().to_string())),
+        None => (target, None),
+    };
+    let file = file.trim();
+
+    WikiReference {
+        is_embed,
+        file: (!file.is_empty()).then(|| file.to_string()),
+        section,
+        label,
+    }
+}
+
+/// Finds all wikilink references in a note's source, in document order.
+///
+/// # Arguments
+///
+/// * `source` - The raw markdown of the note
+///
+/// # Returns
+///
+/// The references, parsed the same way obsidian-export parses them.
+pub fn find_wiki_references(source: &str) -> Vec<WikiReference> {
+    wiki_reference_regex()
+        .captures_iter(source)
+        .map(|caps| parse_wiki_reference(!caps[1].is_empty(), &caps[2]))
+        .collect()
+}
+
+/// Replaces every wikilink reference in a markdown source.
+///
+/// # Arguments
+///
+/// * `source` - The raw markdown
+/// * `replace` - Returns the replacement text for a reference
+///
+/// # Returns
+///
+/// The source with all references replaced.
+pub fn replace_wiki_references(
+    source: &str,
+    mut replace: impl FnMut(&WikiReference) -> String,
+) -> String {
+    wiki_reference_regex()
+        .replace_all(source, |caps: &regex::Captures<'_>| {
+            replace(&parse_wiki_reference(!caps[1].is_empty(), &caps[2]))
+        })
+        .into_owned()
+}
+
+/// Shortcodes used instead of HTML elements for embedded media.
+#[derive(Debug, Clone, Default)]
+pub struct MediaShortcodes {
+    /// Called as `{{ name(src="...") }}` for video files
+    pub video: Option<String>,
+    /// Called as `{{ name(src="...") }}` for audio files
+    pub audio: Option<String>,
+    /// Called as `{{ name(src="...", page=N) }}` for PDF files, `page` being optional
+    pub pdf: Option<String>,
+}
+
+/// Renders an embedded media file as an HTML5 element or a call to the configured shortcode.
+///
+/// # Arguments
+///
+/// * `kind` - The kind of media
+/// * `src` - The resolved URL of the file, without fragment
+/// * `section` - The section of the embed (`page=3` for `![[paper.pdf#page=3]]`)
+/// * `shortcodes` - Shortcodes to use instead of HTML elements
+///
+/// # Returns
+///
+/// The HTML or shortcode call.
+pub fn render_media(
+    kind: MediaKind,
+    src: &str,
+    section: Option<&str>,
+    shortcodes: &MediaShortcodes,
+) -> String {
+    let page = section
+        .and_then(|section| section.strip_prefix("page="))
+        .and_then(|page| page.trim().parse::<u32>().ok());
+
+    let shortcode = match kind {
+        MediaKind::Video => &shortcodes.video,
+        MediaKind::Audio => &shortcodes.audio,
+        MediaKind::Pdf => &shortcodes.pdf,
+    };
+    // Sources a shortcode string can't quote fall back to HTML
+    let call = shortcode
+        .as_ref()
+        .zip(quote_shortcode_string(src))
+        .map(|(shortcode, src)| format!("{{{{ {}(src={}", shortcode, src));
+    if let Some(mut call) = call {
+        if let Some(page) = page {
+            call.push_str(&format!(", page={}", page));
+        }
+        call.push_str(") }}");
+        return call;
+    }
+
+    let src_attr = escape_html_attribute(src);
+    match kind {
+        MediaKind::Video => format!(r#"<video controls src="{}"></video>"#, src_attr),
+        MediaKind::Audio => format!(r#"<audio controls src="{}"></audio>"#, src_attr),
+        MediaKind::Pdf => {
+            let file_name = decode_url_path(src.rsplit('/').next().unwrap_or_default());
+            let data = match page {
+                Some(page) => format!("{}#page={}", src_attr, page),
+                None => src_attr.clone(),
+            };
+            format!(
+                r#"<object data="{}" type="application/pdf" width="100%" height="600"><a href="{}">{}</a></object>"#,
+                data,
+                src_attr,
+                escape_html_attribute(&file_name)
+            )
+        }
+    }
+}
+
+/// Infers the fenced code block language of a source-code file from its extension.
+///
+/// # Arguments
+///
+/// * `path` - The file path or URL
+///
+/// # Returns
+///
+/// The language name used for syntax highlighting, or `None` if the file isn't a known
+/// source-code or config file.
+pub fn code_language(path: &str) -> Option<&'static str> {
+    let file_name = path.rsplit('/').next().unwrap_or(path);
+    let extension = file_name.rsplit_once('.')?.1.to_lowercase();
+    let language = match extension.as_str() {
+        "py" => "python",
+        "rs" => "rust",
+        "js" | "mjs" | "cjs" => "javascript",
+        "ts" => "typescript",
+        "jsx" => "jsx",
+        "tsx" => "tsx",
+        "sh" | "bash" | "zsh" => "bash",
+        "fish" => "fish",
+        "ps1" => "powershell",
+        "rb" => "ruby",
+        "go" => "go",
+        "java" => "java",
+        "kt" | "kts" => "kotlin",
+        "scala" => "scala",
+        "swift" => "swift",
+        "c" | "h" => "c",
+        "cpp" | "cc" | "cxx" | "hpp" | "hh" => "cpp",
+        "cs" => "csharp",
+        "php" => "php",
+        "lua" => "lua",
+        "pl" => "perl",
+        "r" => "r",
+        "jl" => "julia",
+        "hs" => "haskell",
+        "ex" | "exs" => "elixir",
+        "erl" => "erlang",
+        "clj" => "clojure",
+        "sql" => "sql",
+        "html" | "htm" => "html",
+        "css" => "css",
+        "scss" => "scss",
+        "xml" => "xml",
+        "json" => "json",
+        "yaml" | "yml" => "yaml",
+        "toml" => "toml",
+        "ini" | "cfg" => "ini",
+        "nix" => "nix",
+        "tf" => "terraform",
+        "dockerfile" => "dockerfile",
+        "makefile" | "mk" => "makefile",
+        _ => return None,
+    };
+    Some(language)
+}
+
+/// Parses a line range from an embed section: `L10-L20`, `L10-20`, `L5` or `L5-`.
+///
+/// # Arguments
+///
+/// * `section` - The section of the embed (`L10-L20` for `![[script.py#L10-L20]]`)
+///
+/// # Returns
+///
+/// The first line and optional last line (both 1-based and inclusive), or `None` if the
+/// section isn't a line range.
+pub fn parse_line_range(section: &str) -> Option<(usize, Option<usize>)> {
+    let parse_line = |line: &str| -> Option<usize> {
+        let line = line.trim();
+        let line = line
+            .strip_prefix('L')
+            .or_else(|| line.strip_prefix('l'))
+            .unwrap_or(line);
+        line.parse().ok().filter(|line| *line > 0)
+    };
+
+    let section = section.trim();
+    if !section.starts_with(['L', 'l']) {
+        return None;
+    }
+
+    match section.split_once('-') {
+        Some((first, "")) => Some((parse_line(first)?, None)),
+        Some((first, last)) => {
+            let (first, last) = (parse_line(first)?, parse_line(last)?);
+            (first <= last).then_some((first, Some(last)))
+        }
+        None => {
+            let line = parse_line(section)?;
+            Some((line, Some(line)))
+        }
+    }
+}
+
+/// Reasons a code or table file can't be embedded.
+#[derive(Debug)]
+pub enum FileEmbedError {
+    /// The file is larger than the configured maximum
+    TooLarge { size: u64, max: u64 },
+    /// The file isn't valid UTF-8 text
+    Binary,
+    /// The file couldn't be read
+    Io(io::Error),
+}
+
+impl fmt::Display for FileEmbedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileEmbedError::TooLarge { size, max } => {
+                write!(f, "file is {} bytes, the maximum is {} bytes", size, max)
+            }
+            FileEmbedError::Binary => write!(f, "file is not a text file"),
+            FileEmbedError::Io(e) => write!(f, "failed to read file: {}", e),
+        }
+    }
+}
+
+/// Reads a text file, refusing files above `max_bytes` and files that aren't UTF-8 text.
+fn read_text_file(path: &Path, max_bytes: u64) -> Result<String, FileEmbedError> {
+    let size = fs::metadata(path).map_err(FileEmbedError::Io)?.len();
+    if size > max_bytes {
+        return Err(FileEmbedError::TooLarge {
+            size,
+            max: max_bytes,
+        });
+    }
+
+    let bytes = fs::read(path).map_err(FileEmbedError::Io)?;
+    if bytes.contains(&0) {
+        return Err(FileEmbedError::Binary);
+    }
+    String::from_utf8(bytes).map_err(|_| FileEmbedError::Binary)
+}
+
+/// Reads a code file for embedding as a fenced code block.
+///
+/// # Arguments
+///
+/// * `path` - The path of the file in the vault
+/// * `section` - The section of the embed, selecting a line range (see [`parse_line_range`])
+/// * `max_bytes` - The maximum size of the file
+///
+/// # Returns
+///
+/// The selected lines, ending with a newline.
+pub fn read_code_embed(
+    path: &Path,
+    section: Option<&str>,
+    max_bytes: u64,
+) -> Result<String, FileEmbedError> {
+    let content = read_text_file(path, max_bytes)?;
+
+    let mut code = match section.and_then(parse_line_range) {
+        Some((first, last)) => content
+            .lines()
+            .skip(first - 1)
+            .take(last.map_or(usize::MAX, |last| last - first + 1))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        None => content.trim_end_matches(['\r', '\n']).to_string(),
+    };
+    code.push('\n');
+    Ok(code)
+}
+
+/// Infers the field delimiter of a delimited data file from its extension.
+///
+/// # Arguments
+///
+/// * `path` - The file path or URL
+///
+/// # Returns
+///
+/// `,` for `.csv` files, a tab for `.tsv` files, `None` otherwise.
+pub fn table_delimiter(path: &str) -> Option<char> {
+    let file_name = path.rsplit('/').next().unwrap_or(path);
+    match file_name.rsplit_once('.')?.1.to_lowercase().as_str() {
+        "csv" => Some(','),
+        "tsv" => Some('\t'),
+        _ => None,
+    }
+}
+
+/// A delimited data file read for embedding as a table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableEmbed {
+    /// The cells of the first row
+    pub header: Vec<String>,
+    /// The rows after the header, at most the configured row limit
+    pub rows: Vec<Vec<String>>,
+    /// The number of rows after the header in the whole file
+    pub total_rows: usize,
+}
+
+impl TableEmbed {
+    /// Whether rows were left out because of the row limit.
+    pub fn is_truncated(&self) -> bool {
+        self.rows.len() < self.total_rows
+    }
+}
+
+/// Parses delimited data (CSV or TSV). Fields may be quoted with `"`, in which case they can
+/// contain delimiters, line breaks and doubled `""` quotes. Blank lines are skipped.
+///
+/// # Arguments
+///
+/// * `content` - The file content
+/// * `delimiter` - The field delimiter
+///
+/// # Returns
+///
+/// The records of the file, each a list of fields.
+pub fn parse_delimited(content: &str, delimiter: char) -> Vec<Vec<String>> {
+    let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    // Whether the current record has any content, so blank lines can be skipped
+    let mut started = false;
+
+    let mut chars = content.chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => in_quotes = false,
+                _ => field.push(c),
+            }
+            continue;
+        }
+
+        match c {
+            '"' if field.is_empty() => {
+                in_quotes = true;
+                started = true;
+            }
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' | '\r' => {
+                if started {
+                    record.push(std::mem::take(&mut field));
+                    records.push(std::mem::take(&mut record));
+                }
+                started = false;
+            }
+            c if c == delimiter => {
+                record.push(std::mem::take(&mut field));
+                started = true;
+            }
+            _ => {
+                field.push(c);
+                started = true;
+            }
+        }
+    }
+    if started {
+        record.push(field);
+        records.push(record);
+    }
+
+    records
+}
+
+/// Reads a CSV or TSV file for embedding as a table. The first row is used as the header
+/// and short rows are padded with empty cells.
+///
+/// # Arguments
+///
+/// * `path` - The path of the file in the vault
+/// * `delimiter` - The field delimiter (see [`table_delimiter`])
+/// * `max_bytes` - The maximum size of the file
+/// * `max_rows` - The maximum number of rows after the header
+///
+/// # Returns
+///
+/// The table, or `None` inside `Ok` if the file has no rows at all.
+pub fn read_table_embed(
+    path: &Path,
+    delimiter: char,
+    max_bytes: u64,
+    max_rows: usize,
+) -> Result<Option<TableEmbed>, FileEmbedError> {
+    let content = read_text_file(path, max_bytes)?;
+    let mut records = parse_delimited(&content, delimiter).into_iter();
+    let Some(mut header) = records.next() else {
+        return Ok(None);
+    };
+
+    let total_rows = records.len();
+    let mut rows: Vec<Vec<String>> = records.take(max_rows).collect();
+
+    let columns = rows.iter().map(Vec::len).fold(header.len(), usize::max);
+    header.resize(columns, String::new());
+    for row in &mut rows {
+        row.resize(columns, String::new());
+    }
+
+    Ok(Some(TableEmbed {
+        header,
+        rows,
+        total_rows,
+    }))
+}
+
+/// What an external URL embedded as an image (`![](https://...)`) should become.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExternalEmbed {
+    /// Zola's built-in `youtube` shortcode
+    YouTube(String),
+    /// Zola's built-in `vimeo` shortcode
+    Vimeo(String),
+    /// Zola's built-in `streamable` shortcode
+    Streamable(String),
+    /// A page Obsidian renders as a player or card but Zola has no shortcode for (tweets,
+    /// channel pages, ...), emitted as a plain link
+    Link,
+}
+
+impl ExternalEmbed {
+    /// Recognizes URLs of known video and social providers.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL of the embedded image
+    ///
+    /// # Returns
+    ///
+    /// The embed to render, or `None` for any other URL, which is left as an image.
+    pub fn from_url(url: &str) -> Option<Self> {
+        let rest = url
+            .strip_prefix("https://")
+            .or_else(|| url.strip_prefix("http://"))?;
+        let (host, path_and_query) = rest.split_once('/').unwrap_or((rest, ""));
+        let host = host.to_lowercase();
+        let host = host
+            .strip_prefix("www.")
+            .or_else(|| host.strip_prefix("m."))
+            .unwrap_or(&host);
+        let without_fragment = path_and_query.split('#').next().unwrap_or_default();
+        let (path, query) = without_fragment
+            .split_once('?')
+            .unwrap_or((without_fragment, ""));
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+
+        let valid_id = |id: &&str| {
+            !id.is_empty()
+                && id
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        };
+
+        match host {
+            "youtube.com" | "youtube-nocookie.com" | "music.youtube.com" => {
+                let id = match segments.as_slice() {
+                    ["watch"] => query.split('&').find_map(|param| param.strip_prefix("v=")),
+                    ["embed" | "shorts" | "live" | "v", id, ..] => Some(*id),
+                    _ => None,
+                };
+                Some(match id.filter(valid_id) {
+                    Some(id) => ExternalEmbed::YouTube(id.to_string()),
+                    None => ExternalEmbed::Link,
+                })
+            }
+            "youtu.be" => Some(match segments.first().copied().filter(valid_id) {
+                Some(id) => ExternalEmbed::YouTube(id.to_string()),
+                None => ExternalEmbed::Link,
+            }),
+            "vimeo.com" | "player.vimeo.com" => {
+                let id = segments
+                    .iter()
+                    .find(|segment| segment.chars().all(|c| c.is_ascii_digit()));
+                Some(match id {
+                    Some(id) => ExternalEmbed::Vimeo(id.to_string()),
+                    None => ExternalEmbed::Link,
+                })
+            }
+            "streamable.com" => Some(match segments.last().copied().filter(valid_id) {
+                Some(id) => ExternalEmbed::Streamable(id.to_string()),
+                None => ExternalEmbed::Link,
+            }),
+            "twitter.com" | "x.com" | "mobile.twitter.com" | "instagram.com" | "tiktok.com"
+            | "twitch.tv" | "dailymotion.com" => Some(ExternalEmbed::Link),
+            _ => None,
+        }
+    }
+
+    /// Renders the Zola shortcode call, or `None` for [`ExternalEmbed::Link`].
+    pub fn shortcode(&self) -> Option<String> {
+        let (name, id) = match self {
+            ExternalEmbed::YouTube(id) => ("youtube", id),
+            ExternalEmbed::Vimeo(id) => ("vimeo", id),
+            ExternalEmbed::Streamable(id) => ("streamable", id),
+            ExternalEmbed::Link => return None,
+        };
+        Some(format!(r#"{{{{ {}(id="{}") }}}}"#, name, id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_media_kind_from_path() {
+        assert_eq!(MediaKind::from_path("talk.mp4"), Some(MediaKind::Video));
+        assert_eq!(
+            MediaKind::from_path("/memos/memo.M4A"),
+            Some(MediaKind::Audio)
+        );
+        assert_eq!(
+            MediaKind::from_path("paper.pdf#page-3"),
+            Some(MediaKind::Pdf)
+        );
+        assert_eq!(MediaKind::from_path("image.png"), None);
+        assert_eq!(MediaKind::from_path("README"), None);
+    }
+
+    #[test]
+    fn test_find_wiki_references() {
+        let references = find_wiki_references(
+            "See [[notes/Talk]] and ![[talk.mp4]] or ![[paper.pdf#page=3|Paper]].",
+        );
+
+        assert_eq!(references.len(), 3);
+        assert!(!references[0].is_embed);
+        assert_eq!(references[0].display(), "notes/Talk");
+        assert!(references[1].is_embed);
+        assert_eq!(references[1].display(), "talk.mp4");
+        assert_eq!(
+            references[2],
+            WikiReference {
+                is_embed: true,
+                file: Some("paper.pdf".to_string()),
+                section: Some("page=3".to_string()),
+                label: Some("Paper".to_string()),
+            }
+        );
+        assert_eq!(
+            find_wiki_references("![[paper.pdf#page=2]]")[0].display(),
+            "paper.pdf > page=2"
+        );
+    }
+
+    #[test]
+    fn test_render_media_html() {
+        let shortcodes = MediaShortcodes::default();
+
+        assert_eq!(
+            render_media(MediaKind::Video, "/talk.mp4", None, &shortcodes),
+            r#"<video controls src="/talk.mp4"></video>"#
+        );
+        assert_eq!(
+            render_media(MediaKind::Audio, "/memo.m4a", None, &shortcodes),
+            r#"<audio controls src="/memo.m4a"></audio>"#
+        );
+        assert_eq!(
+            render_media(
+                MediaKind::Pdf,
+                "/docs/my%20paper.pdf",
+                Some("page=3"),
+                &shortcodes
+            ),
+            r#"<object data="/docs/my%20paper.pdf#page=3" type="application/pdf" width="100%" height="600"><a href="/docs/my%20paper.pdf">my paper.pdf</a></object>"#
+        );
+    }
+
+    #[test]
+    fn test_render_media_shortcodes() {
+        let shortcodes = MediaShortcodes {
+            video: Some("video".to_string()),
+            audio: None,
+            pdf: Some("pdf".to_string()),
+        };
+
+        assert_eq!(
+            render_media(MediaKind::Video, "/talk.mp4", None, &shortcodes),
+            r#"{{ video(src="/talk.mp4") }}"#
+        );
+        assert_eq!(
+            render_media(MediaKind::Pdf, "/paper.pdf", Some("page=12"), &shortcodes),
+            r#"{{ pdf(src="/paper.pdf", page=12) }}"#
+        );
+        assert_eq!(
+            render_media(MediaKind::Audio, "/memo.m4a", None, &shortcodes),
+            r#"<audio controls src="/memo.m4a"></audio>"#
+        );
+        // Sources that can't be quoted fall back to HTML
+        assert_eq!(
+            render_media(MediaKind::Video, r#"/`a`"b"'c'.mp4"#, None, &shortcodes),
+            r#"<video controls src="/`a`&quot;b&quot;'c'.mp4"></video>"#
+        );
+    }
+
+    #[test]
+    fn test_external_embed_from_url() {
+        let youtube = Some(ExternalEmbed::YouTube("dQw4w9WgXcQ".to_string()));
+        assert_eq!(
+            ExternalEmbed::from_url("https://www.youtube.com/watch?v=dQw4w9WgXcQ"),
+            youtube
+        );
+        assert_eq!(
+            ExternalEmbed::from_url("https://m.youtube.com/watch?feature=share&v=dQw4w9WgXcQ&t=42"),
+            youtube
+        );
+        assert_eq!(
+            ExternalEmbed::from_url("https://youtu.be/dQw4w9WgXcQ?t=10"),
+            youtube
+        );
+        assert_eq!(
+            ExternalEmbed::from_url("https://www.youtube.com/shorts/dQw4w9WgXcQ"),
+            youtube
+        );
+        assert_eq!(
+            ExternalEmbed::from_url("https://vimeo.com/76979871"),
+            Some(ExternalEmbed::Vimeo("76979871".to_string()))
+        );
+        assert_eq!(
+            ExternalEmbed::from_url("https://player.vimeo.com/video/76979871"),
+            Some(ExternalEmbed::Vimeo("76979871".to_string()))
+        );
+        assert_eq!(
+            ExternalEmbed::from_url("https://streamable.com/moo"),
+            Some(ExternalEmbed::Streamable("moo".to_string()))
+        );
+
+        // Known providers without a Zola shortcode, or unrecognized URL shapes
+        assert_eq!(
+            ExternalEmbed::from_url("https://twitter.com/user/status/1234"),
+            Some(ExternalEmbed::Link)
+        );
+        assert_eq!(
+            ExternalEmbed::from_url("https://x.com/user/status/1234"),
+            Some(ExternalEmbed::Link)
+        );
+        assert_eq!(
+            ExternalEmbed::from_url("https://www.youtube.com/@channel"),
+            Some(ExternalEmbed::Link)
+        );
+
+        // Regular images are left alone
+        assert_eq!(ExternalEmbed::from_url("https://example.com/cat.png"), None);
+        assert_eq!(ExternalEmbed::from_url("/local.png"), None);
+    }
+
+    #[test]
+    fn test_external_embed_shortcode() {
+        assert_eq!(
+            ExternalEmbed::YouTube("abc".to_string()).shortcode(),
+            Some(r#"{{ youtube(id="abc") }}"#.to_string())
+        );
+        assert_eq!(
+            ExternalEmbed::Vimeo("123".to_string()).shortcode(),
+            Some(r#"{{ vimeo(id="123") }}"#.to_string())
+        );
+        assert_eq!(ExternalEmbed::Link.shortcode(), None);
+    }
+
+    #[test]
+    fn test_code_language() {
+        assert_eq!(code_language("script.py"), Some("python"));
+        assert_eq!(code_language("/src/main.RS"), Some("rust"));
+        assert_eq!(code_language("config.yml"), Some("yaml"));
+        assert_eq!(code_language("notes.txt"), None);
+        assert_eq!(code_language("image.png"), None);
+        assert_eq!(code_language("Makefile"), None);
+    }
+
+    #[test]
+    fn test_parse_line_range() {
+        assert_eq!(parse_line_range("L10-L20"), Some((10, Some(20))));
+        assert_eq!(parse_line_range("L10-20"), Some((10, Some(20))));
+        assert_eq!(parse_line_range("L5"), Some((5, Some(5))));
+        assert_eq!(parse_line_range("l5-"), Some((5, None)));
+        assert_eq!(parse_line_range("L20-L10"), None);
+        assert_eq!(parse_line_range("L0"), None);
+        assert_eq!(parse_line_range("Usage"), None);
+        assert_eq!(parse_line_range("Lines"), None);
+    }
+
+    #[test]
+    fn test_read_code_embed() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let script = dir.path().join("script.py");
+        fs::write(&script, "import os\n\ndef main():\n    pass\n\n").unwrap();
+
+        assert_eq!(
+            read_code_embed(&script, None, DEFAULT_CODE_EMBED_MAX_BYTES).unwrap(),
+            "import os\n\ndef main():\n    pass\n"
+        );
+        assert_eq!(
+            read_code_embed(&script, Some("L3-L4"), DEFAULT_CODE_EMBED_MAX_BYTES).unwrap(),
+            "def main():\n    pass\n"
+        );
+        assert!(matches!(
+            read_code_embed(&script, None, 10),
+            Err(FileEmbedError::TooLarge { max: 10, .. })
+        ));
+
+        let binary = dir.path().join("blob.py");
+        fs::write(&binary, [0x7f, b'E', b'L', b'F', 0, 1]).unwrap();
+        assert!(matches!(
+            read_code_embed(&binary, None, DEFAULT_CODE_EMBED_MAX_BYTES),
+            Err(FileEmbedError::Binary)
+        ));
+    }
+
+    #[test]
+    fn test_replace_wiki_references() {
+        let replaced = replace_wiki_references("See [[Note#Intro|intro]] and ![[img.png]].", |r| {
+            format!("<{}>", r.display())
+        });
+        assert_eq!(replaced, "See <intro> and <img.png>.");
+    }
+
+    #[test]
+    fn test_table_delimiter() {
+        assert_eq!(table_delimiter("data.csv"), Some(','));
+        assert_eq!(table_delimiter("files/Data.TSV"), Some('\t'));
+        assert_eq!(table_delimiter("data.txt"), None);
+    }
+
+    #[test]
+    fn test_parse_delimited() {
+        assert_eq!(
+            parse_delimited(
+                "name,notes\r\n\"Smith, J\",\"said \"\"hi\"\"\"\n\nDoe,\"two\nlines\"",
+                ','
+            ),
+            vec![
+                vec!["name", "notes"],
+                vec!["Smith, J", "said \"hi\""],
+                vec!["Doe", "two\nlines"],
+            ]
+        );
+        assert_eq!(
+            parse_delimited("\u{feff}a\tb\n1\t\n", '\t'),
+            vec![vec!["a", "b"], vec!["1", ""]]
+        );
+        assert!(parse_delimited("\n\n", ',').is_empty());
+    }
+
+    #[test]
+    fn test_read_table_embed() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("data.csv");
+        fs::write(&path, "a,b\n1\n2,3,4\n5,6\n").unwrap();
+
+        let table = read_table_embed(&path, ',', 1024, 2).unwrap().unwrap();
+        assert_eq!(table.header, vec!["a", "b", ""]);
+        assert_eq!(table.rows, vec![vec!["1", "", ""], vec!["2", "3", "4"]]);
+        assert_eq!(table.total_rows, 3);
+        assert!(table.is_truncated());
+
+        assert!(matches!(
+            read_table_embed(&path, ',', 4, 2),
+            Err(FileEmbedError::TooLarge { .. })
+        ));
+
+        fs::write(&path, "").unwrap();
+        assert_eq!(read_table_embed(&path, ',', 1024, 2).unwrap(), None);
+    }
+}
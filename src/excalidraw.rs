@@ -0,0 +1,322 @@
+//! Support for drawings made with the Excalidraw plugin.
+//!
+//! A drawing is a markdown note (`diagram.excalidraw.md`) holding the scene data, which Zola
+//! can't display. The plugin can export an SVG or PNG next to the drawing whenever it is
+//! saved; embeds of the drawing are replaced with that image, and the drawing note itself is
+//! never published.
+
+use crate::postprocessors::output_root;
+use crate::utils::{encode_asset_url_path, is_excalidraw_file, normalize_path};
+use obsidian_export::pulldown_cmark::{CowStr, Event, LinkType, Tag, TagEnd};
+use obsidian_export::{Context, MarkdownEvents, PostprocessorResult};
+use regex::Regex;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Frontmatter key the plugin adds to every drawing.
+const DRAWING_FRONTMATTER_KEY: &str = "excalidraw-plugin";
+
+/// Checks whether the note being processed is an Excalidraw drawing, by its name or by the
+/// frontmatter the plugin adds.
+///
+/// # Arguments
+///
+/// * `context` - The context of the note
+///
+/// # Returns
+///
+/// `true` if the note is a drawing, `false` otherwise.
+pub fn is_excalidraw_drawing(context: &Context) -> bool {
+    is_excalidraw_file(context.current_file())
+        || context.frontmatter.contains_key(DRAWING_FRONTMATTER_KEY)
+}
+
+/// Finds the image the plugin exported for a drawing: `diagram.excalidraw.svg` or
+/// `diagram.svg` (SVG preferred over PNG) next to `diagram.excalidraw.md`.
+///
+/// # Arguments
+///
+/// * `drawing` - The vault-relative path of the drawing
+/// * `source_dir` - The source vault directory
+///
+/// # Returns
+///
+/// The vault-relative path of the image, or `None` if the drawing wasn't exported.
+pub fn find_drawing_image(drawing: &str, source_dir: &Path) -> Option<String> {
+    let base = strip_suffix_ignore_case(drawing, ".md").unwrap_or(drawing);
+    let short_base = strip_suffix_ignore_case(base, ".excalidraw");
+
+    ["svg", "png"]
+        .iter()
+        .flat_map(|extension| {
+            [Some(base), short_base]
+                .into_iter()
+                .flatten()
+                .map(move |base| format!("{}.{}", base, extension))
+        })
+        .find(|candidate| source_dir.join(candidate).is_file())
+}
+
+/// Extracts SVG markup stored in a drawing, either as inline `<svg>` markup or in an `svg`
+/// code block.
+///
+/// # Arguments
+///
+/// * `source` - The raw content of the drawing note
+///
+/// # Returns
+///
+/// The SVG document, or `None` if the drawing holds no SVG.
+pub fn extract_svg(source: &str) -> Option<&str> {
+    static SVG_RE: OnceLock<Regex> = OnceLock::new();
+
+    let svg_re = SVG_RE.get_or_init(|| Regex::new(r"(?si)<svg\b.*?</svg>").unwrap());
+    svg_re.find(source).map(|svg| svg.as_str())
+}
+
+/// Creates an embed postprocessor replacing embedded Excalidraw drawings
+/// (`![[diagram.excalidraw]]`) with their exported image.
+///
+/// When the plugin didn't export the drawing, SVG markup stored in the drawing is written to
+/// the export destination instead. Drawings without either are reported and turned into
+/// italic text, like unresolvable embeds.
+///
+/// # Arguments
+///
+/// * `source_dir` - The path to the source vault directory
+///
+/// # Returns
+///
+/// An embed postprocessor function that can be used with obsidian-export
+pub fn create_excalidraw_embed_postprocessor(
+    source_dir: PathBuf,
+) -> impl Fn(&mut Context, &mut MarkdownEvents<'_>) -> PostprocessorResult {
+    move |context: &mut Context, events: &mut MarkdownEvents<'_>| {
+        if !is_excalidraw_drawing(context) {
+            return PostprocessorResult::Continue;
+        }
+
+        let drawing_file = context.current_file();
+        let drawing = normalize_path(
+            drawing_file
+                .strip_prefix(&source_dir)
+                .unwrap_or(drawing_file),
+        );
+        let name = drawing_name(&drawing);
+
+        let image = find_drawing_image(&drawing, &source_dir)
+            .or_else(|| write_extracted_svg(&drawing, context, &source_dir));
+        *events = match image {
+            Some(image) => {
+                // Images are resolved relative to the note the drawing is embedded in
+                let root_file = context.root_file();
+                let depth = root_file
+                    .strip_prefix(&source_dir)
+                    .unwrap_or(root_file)
+                    .components()
+                    .count()
+                    .saturating_sub(1);
+                let url = encode_asset_url_path(&format!("{}{}", "../".repeat(depth), image));
+                vec![
+                    Event::Start(Tag::Image {
+                        link_type: LinkType::Inline,
+                        dest_url: CowStr::from(url),
+                        title: CowStr::from(""),
+                        id: CowStr::from(""),
+                    }),
+                    Event::Text(CowStr::from(name)),
+                    Event::End(TagEnd::Image),
+                ]
+            }
+            None => {
+                eprintln!(
+                    "Warning: No image found for Excalidraw drawing, enable the plugin's SVG or PNG auto-export\n\tDrawing: '{}'\n\tSource: '{}'\n",
+                    drawing,
+                    context.root_file().display(),
+                );
+                vec![
+                    Event::Start(Tag::Emphasis),
+                    Event::Text(CowStr::from(name)),
+                    Event::End(TagEnd::Emphasis),
+                ]
+            }
+        };
+
+        PostprocessorResult::StopHere
+    }
+}
+
+/// Writes the SVG stored in a drawing next to where the drawing would be exported.
+///
+/// # Returns
+///
+/// The vault-relative path the SVG is published at, or `None` if the drawing holds no SVG.
+fn write_extracted_svg(drawing: &str, context: &Context, source_dir: &Path) -> Option<String> {
+    let source = fs::read_to_string(source_dir.join(drawing)).ok()?;
+    let svg = extract_svg(&source)?;
+
+    let image = format!(
+        "{}.svg",
+        strip_suffix_ignore_case(drawing, ".md").unwrap_or(drawing)
+    );
+    let target = output_root(context, source_dir)?.join(&image);
+    if !target.exists() {
+        let written = target
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::write(&target, svg));
+        if let Err(e) = written {
+            eprintln!(
+                "Warning: Unable to write Excalidraw drawing ({})\n\tDrawing: '{}'\n",
+                e, drawing,
+            );
+            return None;
+        }
+    }
+
+    Some(image)
+}
+
+/// Returns the name of a drawing without its directory and extensions.
+fn drawing_name(drawing: &str) -> String {
+    let file_name = drawing.rsplit('/').next().unwrap_or(drawing);
+    let name = strip_suffix_ignore_case(file_name, ".md").unwrap_or(file_name);
+    strip_suffix_ignore_case(name, ".excalidraw")
+        .unwrap_or(name)
+        .to_string()
+}
+
+fn strip_suffix_ignore_case<'a>(value: &'a str, suffix: &str) -> Option<&'a str> {
+    let split = value.len().checked_sub(suffix.len())?;
+    (value.is_char_boundary(split) && value[split..].eq_ignore_ascii_case(suffix))
+        .then(|| &value[..split])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use obsidian_export::serde_yaml::Value;
+
+    #[test]
+    fn test_is_excalidraw_drawing() {
+        let context = Context::new(
+            PathBuf::from("/vault/diagram.excalidraw.md"),
+            PathBuf::new(),
+        );
+        assert!(is_excalidraw_drawing(&context));
+
+        let mut context = Context::new(PathBuf::from("/vault/Drawing.md"), PathBuf::new());
+        assert!(!is_excalidraw_drawing(&context));
+        context
+            .frontmatter
+            .insert(Value::from(DRAWING_FRONTMATTER_KEY), Value::from("parsed"));
+        assert!(is_excalidraw_drawing(&context));
+    }
+
+    #[test]
+    fn test_find_drawing_image() {
+        let vault = tempfile::TempDir::new().unwrap();
+        fs::create_dir(vault.path().join("art")).unwrap();
+        fs::write(vault.path().join("art/flow.png"), "png").unwrap();
+        fs::write(vault.path().join("art/flow.excalidraw.svg"), "<svg/>").unwrap();
+
+        assert_eq!(
+            find_drawing_image("art/flow.excalidraw.md", vault.path()).as_deref(),
+            Some("art/flow.excalidraw.svg")
+        );
+        fs::remove_file(vault.path().join("art/flow.excalidraw.svg")).unwrap();
+        assert_eq!(
+            find_drawing_image("art/flow.excalidraw.md", vault.path()).as_deref(),
+            Some("art/flow.png")
+        );
+        assert_eq!(
+            find_drawing_image("other.excalidraw.md", vault.path()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_extract_svg() {
+        let source = "---\nexcalidraw-plugin: parsed\n---\n```svg\n<SVG viewBox=\"0 0 1 1\"><rect/></SVG>\n```\n";
+        assert_eq!(
+            extract_svg(source),
+            Some("<SVG viewBox=\"0 0 1 1\"><rect/></SVG>")
+        );
+        assert_eq!(
+            extract_svg("# Drawing\n```compressed-json\nN4Ig\n```"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_embed_postprocessor() {
+        let vault = tempfile::TempDir::new().unwrap();
+        let output = tempfile::TempDir::new().unwrap();
+        fs::create_dir(vault.path().join("notes")).unwrap();
+        fs::write(vault.path().join("notes/page.md"), "![[flow.excalidraw]]").unwrap();
+        fs::write(vault.path().join("flow.excalidraw.md"), "# Drawing").unwrap();
+        fs::write(vault.path().join("flow.excalidraw.svg"), "<svg/>").unwrap();
+        fs::write(
+            vault.path().join("sketch.excalidraw.md"),
+            "Text\n<svg><circle/></svg>\n",
+        )
+        .unwrap();
+        fs::write(vault.path().join("blank.excalidraw.md"), "# Drawing").unwrap();
+
+        let root = Context::new(
+            vault.path().join("notes/page.md"),
+            output.path().join("notes/page.md"),
+        );
+        let postprocessor = create_excalidraw_embed_postprocessor(vault.path().to_path_buf());
+        let embed = |drawing: &str| {
+            let mut context = Context::from_parent(&root, &vault.path().join(drawing));
+            let mut events = vec![Event::Text(CowStr::from("Drawing"))];
+            let result = postprocessor(&mut context, &mut events);
+            assert!(matches!(result, PostprocessorResult::StopHere));
+            events
+        };
+
+        let image = |dest: &str, name: &str| {
+            vec![
+                Event::Start(Tag::Image {
+                    link_type: LinkType::Inline,
+                    dest_url: CowStr::from(dest.to_string()),
+                    title: CowStr::from(""),
+                    id: CowStr::from(""),
+                }),
+                Event::Text(CowStr::from(name.to_string())),
+                Event::End(TagEnd::Image),
+            ]
+        };
+        assert_eq!(
+            embed("flow.excalidraw.md"),
+            image("../flow.excalidraw.svg", "flow")
+        );
+        assert_eq!(
+            embed("sketch.excalidraw.md"),
+            image("../sketch.excalidraw.svg", "sketch")
+        );
+        assert_eq!(
+            fs::read_to_string(output.path().join("sketch.excalidraw.svg")).unwrap(),
+            "<svg><circle/></svg>"
+        );
+        assert_eq!(
+            embed("blank.excalidraw.md"),
+            vec![
+                Event::Start(Tag::Emphasis),
+                Event::Text(CowStr::from("blank")),
+                Event::End(TagEnd::Emphasis),
+            ]
+        );
+
+        // Other notes are left to the regular embedding
+        let mut context = Context::from_parent(&root, &vault.path().join("notes/page.md"));
+        let mut events = vec![Event::Text(CowStr::from("Note"))];
+        assert!(matches!(
+            postprocessor(&mut context, &mut events),
+            PostprocessorResult::Continue
+        ));
+        assert_eq!(events, vec![Event::Text(CowStr::from("Note"))]);
+    }
+}
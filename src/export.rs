@@ -0,0 +1,103 @@
+//! Set up of an [`Exporter`] the way the `export` command does.
+//!
+//! A Zola export takes several postprocessors registered in the right order, plus a pass over
+//! the canvases once obsidian-export is done. [`ZolaExport`] owns the postprocessors, so
+//! library users get the same output as the command line without repeating its setup.
+
+use crate::canvas::{export_canvases, CanvasMode};
+use crate::excalidraw::create_excalidraw_embed_postprocessor;
+use crate::postprocessors::{
+    create_file_embed_postprocessor, create_zola_link_postprocessor_with_options, ZolaOptions,
+};
+use crate::sanitizers::create_plugin_syntax_embed_postprocessor;
+use eyre::Result;
+use obsidian_export::{Exporter, Postprocessor, WalkOptions};
+use std::path::{Path, PathBuf};
+
+/// The postprocessors of a Zola export of a vault.
+pub struct ZolaExport {
+    source: PathBuf,
+    walk_options: WalkOptions<'static>,
+    plugin_syntax: Box<Postprocessor<'static>>,
+    zola_links: Box<Postprocessor<'static>>,
+    excalidraw: Box<Postprocessor<'static>>,
+    file_embeds: Box<Postprocessor<'static>>,
+}
+
+impl ZolaExport {
+    /// Creates the postprocessors of an export of a vault.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Path to the vault
+    /// * `options` - Configuration options for the conversion, its walk options are also used
+    ///   for the export itself
+    pub fn new(source: PathBuf, options: ZolaOptions) -> Self {
+        Self {
+            walk_options: options.walk_options.clone(),
+            plugin_syntax: Box::new(create_plugin_syntax_embed_postprocessor(
+                options.plugin_syntax.clone(),
+            )),
+            excalidraw: Box::new(create_excalidraw_embed_postprocessor(source.clone())),
+            file_embeds: Box::new(create_file_embed_postprocessor(
+                source.clone(),
+                options.clone(),
+            )),
+            zola_links: Box::new(create_zola_link_postprocessor_with_options(
+                source.clone(),
+                options,
+            )),
+            source,
+        }
+    }
+
+    /// Sets up an exporter of the vault with the postprocessors.
+    ///
+    /// Plugin syntax is removed from embedded notes and their embedded files are rendered
+    /// before they are merged into the embedding note, so removals are reported against the
+    /// right file. The frontmatter strategy is left to the caller.
+    ///
+    /// # Arguments
+    ///
+    /// * `destination` - Where the vault, or the note given to [`Exporter::start_at`], is
+    ///   exported
+    ///
+    /// # Returns
+    ///
+    /// The exporter, ready to run
+    pub fn exporter(&self, destination: PathBuf) -> Exporter<'_> {
+        let mut exporter = Exporter::new(self.source.clone(), destination);
+        exporter.walk_options(self.walk_options.clone());
+        exporter.add_embed_postprocessor(&*self.plugin_syntax);
+        exporter.add_postprocessor(&*self.zola_links);
+        exporter.add_embed_postprocessor(&*self.excalidraw);
+        exporter.add_embed_postprocessor(&*self.file_embeds);
+        exporter
+    }
+
+    /// Writes the pages of the vault's canvases, after the exporter has run.
+    ///
+    /// # Arguments
+    ///
+    /// * `destination` - The directory the vault was exported to
+    /// * `mode` - How the canvases are rendered
+    /// * `frontmatter` - Whether pages get frontmatter with the canvas name as title
+    ///
+    /// # Returns
+    ///
+    /// The paths of the generated pages.
+    pub fn export_canvases(
+        &self,
+        destination: &Path,
+        mode: CanvasMode,
+        frontmatter: bool,
+    ) -> Result<Vec<PathBuf>> {
+        export_canvases(
+            &self.source,
+            destination,
+            mode,
+            frontmatter,
+            self.walk_options.clone(),
+        )
+    }
+}
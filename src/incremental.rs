@@ -0,0 +1,1194 @@
+//! Incremental exports.
+//!
+//! Every export writes a manifest to the destination. For each file of the vault, it records
+//! the hash of its content, the files it was exported to and the notes it links to or embeds.
+//! The next export only re-exports the files that changed since, the notes linking to or
+//! embedding them, and the notes with Dataview queries. Exported files are first written to a
+//! staging directory next to the destination, and only the changed ones are moved over once
+//! the whole export succeeded, so a failed export leaves the destination as it was. Unchanged
+//! files are never rewritten.
+
+use eyre::{Result, WrapErr};
+use obsidian_export::{vault_contents, WalkOptions};
+use percent_encoding::percent_decode_str;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+use walkdir::WalkDir;
+
+/// Name of the manifest file, at the root of the destination.
+pub const MANIFEST_FILENAME: &str = ".obsidian-zola-manifest.json";
+
+/// Version of the manifest format, manifests of another version trigger a full export.
+const MANIFEST_VERSION: u32 = 1;
+
+/// Number of files listed when refusing a destination obsidian-zola doesn't manage.
+const LISTED_FILES: usize = 10;
+
+/// What an export produced from the files of a vault.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Version of the manifest format
+    pub version: u32,
+
+    /// Fingerprint of the options of the export, see [`options_fingerprint`]
+    pub options: String,
+
+    /// Entries by vault-relative source path
+    pub entries: BTreeMap<String, ManifestEntry>,
+
+    /// Outputs of earlier exports that are no longer produced, because their source was
+    /// removed or renamed, but are still in the destination
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub orphans: Vec<String>,
+
+    /// Hashes of the files written to the destination, by destination-relative path. Only
+    /// files whose content still matches are deleted when pruning.
+    #[serde(default)]
+    pub output_hashes: BTreeMap<String, String>,
+}
+
+/// A file of the vault, as recorded in the manifest.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Hash of the content of the file, see [`content_hash`]
+    pub hash: String,
+
+    /// Destination-relative paths of the files exported from it
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub outputs: Vec<String>,
+
+    /// Link targets (see [`link_key`]) of the notes and files it links to
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<String>,
+
+    /// Link targets (see [`link_key`]) of the notes and files it embeds
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub embeds: Vec<String>,
+
+    /// Whether it holds Dataview queries, whose results depend on the whole vault
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub dataview: bool,
+}
+
+impl Manifest {
+    /// Loads the manifest of a previous export.
+    ///
+    /// # Arguments
+    ///
+    /// * `destination` - The destination directory of the export
+    ///
+    /// # Returns
+    ///
+    /// The manifest, or `None` if there is none. An unreadable manifest is reported and
+    /// ignored, so the export starts over.
+    pub fn load(destination: &Path) -> Option<Self> {
+        let path = destination.join(MANIFEST_FILENAME);
+        let content = fs::read_to_string(&path).ok()?;
+        match serde_json::from_str(&content) {
+            Ok(manifest) => Some(manifest),
+            Err(e) => {
+                eprintln!(
+                    "Warning: Unable to read the export manifest ({}), exporting everything\n\tSource: '{}'\n",
+                    e,
+                    path.display(),
+                );
+                None
+            }
+        }
+    }
+
+    /// Writes the manifest to the destination.
+    ///
+    /// # Arguments
+    ///
+    /// * `destination` - The destination directory of the export
+    ///
+    /// # Returns
+    ///
+    /// An error if the manifest can't be written.
+    pub fn save(&self, destination: &Path) -> Result<()> {
+        let content =
+            serde_json::to_string_pretty(self).wrap_err("Failed to serialize manifest")?;
+        fs::write(destination.join(MANIFEST_FILENAME), content)
+            .wrap_err("Failed to write export manifest")
+    }
+
+    /// Builds the manifest of an export.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - The fingerprint of the options of the export
+    /// * `sources` - The files of the vault, from [`scan_sources`]
+    /// * `previous` - The manifest of the previous export, if any
+    /// * `stale` - The files exported this time, from [`Manifest::stale_sources`]
+    /// * `outputs` - The hashes of the files exported this time, by destination-relative path
+    ///
+    /// # Returns
+    ///
+    /// The manifest. Files that weren't exported this time keep their previous outputs, and
+    /// previous outputs that no file produces anymore become orphans.
+    pub fn from_export(
+        options: String,
+        mut sources: BTreeMap<String, ManifestEntry>,
+        previous: Option<&Manifest>,
+        stale: &BTreeSet<String>,
+        outputs: &BTreeMap<String, String>,
+    ) -> Self {
+        for output in outputs.keys() {
+            if let Some(owner) = output_owner(output, &sources) {
+                if let Some(entry) = sources.get_mut(&owner) {
+                    entry.outputs.push(output.clone());
+                }
+            }
+        }
+
+        if let Some(previous) = previous {
+            for (path, entry) in sources.iter_mut() {
+                let Some(old) = previous.entries.get(path).filter(|_| !stale.contains(path)) else {
+                    continue;
+                };
+                for output in &old.outputs {
+                    if !entry.outputs.contains(output) {
+                        entry.outputs.push(output.clone());
+                    }
+                }
+            }
+        }
+
+        for entry in sources.values_mut() {
+            entry.outputs.sort();
+        }
+        let orphans = previous.map_or_else(Vec::new, |previous| {
+            let kept: HashSet<&String> =
+                sources.values().flat_map(|entry| &entry.outputs).collect();
+            let orphans: BTreeSet<&String> = previous
+                .entries
+                .values()
+                .flat_map(|entry| &entry.outputs)
+                .chain(&previous.orphans)
+                .filter(|output| !kept.contains(output))
+                .collect();
+            orphans.into_iter().cloned().collect::<Vec<_>>()
+        });
+
+        // Previous hashes stay valid for the outputs that weren't exported again
+        let mut output_hashes = BTreeMap::new();
+        for output in sources
+            .values()
+            .flat_map(|entry| &entry.outputs)
+            .chain(&orphans)
+        {
+            let hash = outputs
+                .get(output)
+                .or_else(|| previous?.output_hashes.get(output));
+            if let Some(hash) = hash {
+                output_hashes.insert(output.clone(), hash.clone());
+            }
+        }
+
+        Self {
+            version: MANIFEST_VERSION,
+            options,
+            entries: sources,
+            orphans,
+            output_hashes,
+        }
+    }
+
+    /// Finds the files of the vault to export again.
+    ///
+    /// A file is stale when it is new, its content changed or one of its outputs is missing
+    /// from the destination. Notes linking to a stale or removed file are stale too, as well
+    /// as the notes embedding a stale note, recursively, and the notes with Dataview queries.
+    /// When the manifest was written by another version or with other options, everything is
+    /// stale.
+    ///
+    /// # Arguments
+    ///
+    /// * `sources` - The files of the vault, from [`scan_sources`]
+    /// * `options` - The fingerprint of the options of this export
+    /// * `destination` - The destination directory of the export
+    ///
+    /// # Returns
+    ///
+    /// The vault-relative paths of the stale files.
+    pub fn stale_sources(
+        &self,
+        sources: &BTreeMap<String, ManifestEntry>,
+        options: &str,
+        destination: &Path,
+    ) -> BTreeSet<String> {
+        if self.version != MANIFEST_VERSION || self.options != options {
+            return sources.keys().cloned().collect();
+        }
+
+        let mut stale = BTreeSet::new();
+        let mut changed = HashSet::new();
+        for (path, source) in sources {
+            let unchanged = self.entries.get(path).is_some_and(|entry| {
+                entry.hash == source.hash
+                    && entry
+                        .outputs
+                        .iter()
+                        .all(|output| destination.join(output).is_file())
+            });
+            if !unchanged {
+                stale.insert(path.clone());
+                changed.insert(link_key(path));
+            }
+        }
+        for path in self.entries.keys() {
+            if !sources.contains_key(path) {
+                changed.insert(link_key(path));
+            }
+        }
+        if changed.is_empty() {
+            return stale;
+        }
+
+        // Links to renamed, removed or new notes render differently
+        for (path, source) in sources {
+            let links_changed = source
+                .links
+                .iter()
+                .chain(&source.embeds)
+                .any(|target| changed.contains(target));
+            if links_changed || source.dataview {
+                stale.insert(path.clone());
+            }
+        }
+
+        // Embedded notes are part of the embedding note, which has to follow them
+        loop {
+            let embedded: HashSet<String> = stale.iter().map(|path| link_key(path)).collect();
+            let embedding: Vec<String> = sources
+                .iter()
+                .filter(|(path, source)| {
+                    !stale.contains(*path)
+                        && source.embeds.iter().any(|target| embedded.contains(target))
+                })
+                .map(|(path, _)| path.clone())
+                .collect();
+            if embedding.is_empty() {
+                return stale;
+            }
+            stale.extend(embedding);
+        }
+    }
+
+    /// Lists the orphaned outputs that pruning would delete: the ones still in the destination
+    /// with the content that was exported. Files edited or written by hand at the same path
+    /// are left out.
+    ///
+    /// # Arguments
+    ///
+    /// * `destination` - The destination directory
+    ///
+    /// # Returns
+    ///
+    /// The destination-relative paths of the files to delete.
+    pub fn prunable(&self, destination: &Path) -> Vec<String> {
+        self.orphans
+            .iter()
+            .filter(|output| {
+                let expected_hash = self.output_hashes.get(*output);
+                is_inside_destination(output)
+                    && fs::read(destination.join(output))
+                        .is_ok_and(|content| Some(&content_hash(&content)) == expected_hash)
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Forgets the orphaned outputs, once the export deletes them or keeps them for good.
+    ///
+    /// The outputs listed by [`Manifest::prunable`] are returned to be deleted, the other
+    /// orphans still in the destination are kept with a warning.
+    ///
+    /// # Arguments
+    ///
+    /// * `destination` - The destination directory
+    ///
+    /// # Returns
+    ///
+    /// The destination-relative paths of the files to delete.
+    pub fn forget_orphans(&mut self, destination: &Path) -> Vec<String> {
+        let prunable = self.prunable(destination);
+        for output in std::mem::take(&mut self.orphans) {
+            self.output_hashes.remove(&output);
+            let path = destination.join(&output);
+            if !prunable.contains(&output) && is_inside_destination(&output) && path.is_file() {
+                eprintln!(
+                    "Warning: Kept a stale file, it changed since it was exported\n\tOutput: '{}'\n",
+                    path.display(),
+                );
+            }
+        }
+        prunable
+    }
+}
+
+/// Checks that a destination-relative path can't point outside of the destination.
+fn is_inside_destination(output: &str) -> bool {
+    Path::new(output)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+}
+
+/// Deletes an exported file, then the directories it leaves empty up to the destination.
+fn remove_output(destination: &Path, path: &Path) -> Result<()> {
+    fs::remove_file(path).wrap_err_with(|| format!("Failed to remove {}", path.display()))?;
+
+    let mut directory = path.parent();
+    while let Some(dir) = directory.filter(|dir| *dir != destination) {
+        if fs::remove_dir(dir).is_err() {
+            break;
+        }
+        directory = dir.parent();
+    }
+    Ok(())
+}
+
+/// Computes the fingerprint of the options of an export, including the version of the tool.
+/// Changing options or upgrading makes the next export a full export.
+///
+/// # Arguments
+///
+/// * `description` - A description of every option that changes the exported files
+///
+/// # Returns
+///
+/// The fingerprint.
+pub fn options_fingerprint(description: &str) -> String {
+    content_hash(format!("{} {}", env!("CARGO_PKG_VERSION"), description).as_bytes())
+}
+
+/// Hashes the content of a file with 64-bit FNV-1a. This only detects changes, it isn't
+/// meant to resist tampering.
+///
+/// # Arguments
+///
+/// * `bytes` - The content to hash
+///
+/// # Returns
+///
+/// The hash, as 16 hexadecimal digits.
+pub fn content_hash(bytes: &[u8]) -> String {
+    let hash = bytes.iter().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("{:016x}", hash)
+}
+
+/// Normalizes a link target or a vault path to the key notes are matched by. Obsidian
+/// resolves links by file name, so the key is the lowercased file name, without the `.md`
+/// extension.
+///
+/// # Arguments
+///
+/// * `target` - A wikilink target, a markdown link destination or a vault-relative path
+///
+/// # Returns
+///
+/// The key of the target.
+pub fn link_key(target: &str) -> String {
+    let decoded = percent_decode_str(target.trim()).decode_utf8_lossy();
+    let name = decoded.rsplit(['/', '\\']).next().unwrap_or_default();
+    let name = name.to_lowercase();
+    match name.strip_suffix(".md") {
+        Some(stem) => stem.to_string(),
+        None => name,
+    }
+}
+
+/// Reads a file of the vault for the manifest: hashes it and, for notes, collects the targets
+/// of its links and embeds.
+///
+/// # Arguments
+///
+/// * `path` - The path of the file
+///
+/// # Returns
+///
+/// The entry of the file, without outputs, or an error if it can't be read.
+pub fn scan_file(path: &Path) -> Result<ManifestEntry> {
+    let content = fs::read(path).wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+    let mut entry = ManifestEntry {
+        hash: content_hash(&content),
+        ..ManifestEntry::default()
+    };
+
+    let is_note = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("md"));
+    if !is_note {
+        return Ok(entry);
+    }
+
+    static WIKILINK_RE: OnceLock<Regex> = OnceLock::new();
+    static MARKDOWN_LINK_RE: OnceLock<Regex> = OnceLock::new();
+
+    // Both capture the `!` of embeds and images, then the target
+    let wikilink_re = WIKILINK_RE.get_or_init(|| Regex::new(r"(!?)\[\[([^\]|#^]+)").unwrap());
+    let markdown_link_re =
+        MARKDOWN_LINK_RE.get_or_init(|| Regex::new(r"(!?)\[[^\]]*\]\(\s*<?([^)>\s#]+)").unwrap());
+
+    let text = String::from_utf8_lossy(&content);
+    let mut links = BTreeSet::new();
+    let mut embeds = BTreeSet::new();
+    for captures in wikilink_re
+        .captures_iter(&text)
+        .chain(markdown_link_re.captures_iter(&text))
+    {
+        let target = &captures[2];
+        if target.contains("://") || target.starts_with("mailto:") {
+            continue;
+        }
+        let targets = if captures[1].is_empty() {
+            &mut links
+        } else {
+            &mut embeds
+        };
+        targets.insert(link_key(target));
+    }
+    entry.links = links.into_iter().collect();
+    entry.embeds = embeds.into_iter().collect();
+    entry.dataview = text.to_lowercase().contains("```dataview");
+    Ok(entry)
+}
+
+/// Reads every file obsidian-export would export, see [`scan_file`].
+///
+/// # Arguments
+///
+/// * `source` - The source vault directory
+/// * `walk_options` - The walk options of the export
+///
+/// # Returns
+///
+/// The entries by vault-relative path, or an error if the vault can't be read.
+pub fn scan_sources(
+    source: &Path,
+    walk_options: WalkOptions<'_>,
+) -> Result<BTreeMap<String, ManifestEntry>> {
+    let files = vault_contents(source, walk_options).wrap_err("Failed to read vault")?;
+    files
+        .iter()
+        .map(|file| Ok((relative_path(source, file)?, scan_file(file)?)))
+        .collect()
+}
+
+/// Reads every file obsidian-export would export like [`scan_sources`], but only the files
+/// that changed since the previous export. The others keep their entries in its manifest.
+///
+/// # Arguments
+///
+/// * `source` - The source vault directory
+/// * `walk_options` - The walk options of the export
+/// * `previous` - The manifest of the previous export
+/// * `changed` - The paths changed since, files or directories. A change of the vault
+///   directory itself, like a watcher asking for a rescan, reads every file again.
+///
+/// # Returns
+///
+/// The entries by vault-relative path, or an error if the vault can't be read.
+pub fn rescan_sources(
+    source: &Path,
+    walk_options: WalkOptions<'_>,
+    previous: &Manifest,
+    changed: &[PathBuf],
+) -> Result<BTreeMap<String, ManifestEntry>> {
+    let files = vault_contents(source, walk_options).wrap_err("Failed to read vault")?;
+    files
+        .iter()
+        .map(|file| {
+            let path = relative_path(source, file)?;
+            let entry = rescan_file(file, &path, previous, changed)?;
+            Ok((path, entry))
+        })
+        .collect()
+}
+
+/// Reads a file of the vault for the manifest unless it is unchanged since the previous
+/// export, see [`rescan_sources`].
+///
+/// # Arguments
+///
+/// * `file` - The path of the file
+/// * `path` - Its vault-relative path
+/// * `previous` - The manifest of the previous export
+/// * `changed` - The paths changed since, files or directories
+///
+/// # Returns
+///
+/// The entry of the file, without outputs, or an error if it can't be read.
+pub fn rescan_file(
+    file: &Path,
+    path: &str,
+    previous: &Manifest,
+    changed: &[PathBuf],
+) -> Result<ManifestEntry> {
+    let unchanged = previous
+        .entries
+        .get(path)
+        .filter(|_| !changed.iter().any(|changed| file.starts_with(changed)));
+    match unchanged {
+        Some(entry) => Ok(ManifestEntry {
+            outputs: Vec::new(),
+            ..entry.clone()
+        }),
+        None => scan_file(file),
+    }
+}
+
+/// Finds the vault file an exported file comes from: the file at the same path, the canvas a
+/// page was generated from, or the Excalidraw drawing an image was extracted from.
+///
+/// # Arguments
+///
+/// * `output` - The destination-relative path of the exported file
+/// * `sources` - The files of the vault
+///
+/// # Returns
+///
+/// The vault-relative path of the source, or `None` if no file of the vault produced it.
+pub fn output_owner(output: &str, sources: &BTreeMap<String, ManifestEntry>) -> Option<String> {
+    let mut candidates = vec![output.to_string()];
+    if let Some(stem) = output.strip_suffix(".md") {
+        candidates.push(format!("{}.canvas", stem));
+    }
+    if let Some(stem) = output.strip_suffix(".svg") {
+        candidates.push(format!("{}.md", stem));
+    }
+    candidates
+        .into_iter()
+        .find(|candidate| sources.contains_key(candidate))
+}
+
+/// A staging directory next to the destination, removed when dropped.
+#[derive(Debug)]
+pub struct Staging {
+    path: PathBuf,
+}
+
+impl Staging {
+    /// Creates an empty staging directory for a destination, replacing the leftovers of an
+    /// interrupted export.
+    ///
+    /// # Arguments
+    ///
+    /// * `destination` - The existing destination directory
+    ///
+    /// # Returns
+    ///
+    /// The staging directory, or an error if it can't be created.
+    pub fn new(destination: &Path) -> Result<Self> {
+        let destination = destination
+            .canonicalize()
+            .wrap_err("Failed to resolve destination directory")?;
+        let path = sibling_path(&destination, "staging");
+
+        if path.exists() {
+            fs::remove_dir_all(&path).wrap_err("Failed to remove previous staging directory")?;
+        }
+        fs::create_dir_all(&path).wrap_err("Failed to create staging directory")?;
+        Ok(Self { path })
+    }
+
+    /// Creates an empty staging directory in the system's temporary directory, for exports
+    /// that must not write next to their destination, like dry runs.
+    ///
+    /// # Returns
+    ///
+    /// The staging directory, or an error if it can't be created.
+    pub fn temporary() -> Result<Self> {
+        // Exports running in parallel in the same process each get their own directory
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "obsidian-zola-staging-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        if path.exists() {
+            fs::remove_dir_all(&path).wrap_err("Failed to remove previous staging directory")?;
+        }
+        fs::create_dir_all(&path).wrap_err("Failed to create staging directory")?;
+        Ok(Self { path })
+    }
+
+    /// Returns the path of the staging directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Moves the changed staged files into the destination, then deletes the removed files.
+    ///
+    /// Only called once the whole vault is exported, so a failed export never touches the
+    /// destination. The destination directory itself stays in place, tools watching it like
+    /// `zola serve` keep seeing its changes, and unchanged files are never rewritten. Each file
+    /// is replaced with a rename, so readers never see a half-written file. The manifest is
+    /// moved last: if the export is interrupted, the next one compares the staged files with
+    /// the destination again.
+    ///
+    /// # Arguments
+    ///
+    /// * `destination` - The destination directory, which the staging directory was created for
+    /// * `changes` - The changed files, from [`compare_staged_files`]
+    /// * `removed` - Destination-relative paths of the files to delete
+    ///
+    /// # Returns
+    ///
+    /// An error if a file can't be moved or deleted.
+    pub fn move_into(
+        self,
+        destination: &Path,
+        changes: &BTreeMap<String, FileChange>,
+        removed: &[String],
+    ) -> Result<()> {
+        let manifest = Some(MANIFEST_FILENAME).filter(|name| self.path.join(name).exists());
+        for relative in changes.keys().map(String::as_str).chain(manifest) {
+            let staged = self.path.join(relative);
+            let target = destination.join(relative);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent).wrap_err("Failed to create destination directory")?;
+            }
+            // A destination that is a mount point isn't on the filesystem of its staging
+            // directory, files are copied there
+            if fs::rename(&staged, &target).is_err() {
+                fs::copy(&staged, &target)
+                    .wrap_err_with(|| format!("Failed to write {}", target.display()))?;
+            }
+        }
+        for output in removed {
+            remove_output(destination, &destination.join(output))?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the path of a hidden directory next to the destination, named after it.
+fn sibling_path(destination: &Path, kind: &str) -> PathBuf {
+    let name = destination
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "content".to_string());
+    destination.with_file_name(format!(".{}.obsidian-zola-{}", name, kind))
+}
+
+impl Drop for Staging {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// How a staged file changes the destination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileChange {
+    /// The file isn't in the destination yet
+    Create,
+    /// The file is in the destination with a different content
+    Overwrite,
+}
+
+/// The staged files, compared with the destination.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StagedFiles {
+    /// Hashes of every staged file, by destination-relative path
+    pub hashes: BTreeMap<String, String>,
+
+    /// The staged files that are new or whose content changed, by destination-relative path
+    pub changes: BTreeMap<String, FileChange>,
+}
+
+/// Compares the staged files with the destination, without changing either.
+///
+/// # Arguments
+///
+/// * `staging` - The staging directory
+/// * `destination` - The destination directory, which doesn't have to exist
+///
+/// # Returns
+///
+/// The staged files and how they differ, or an error if a staged file can't be read.
+pub fn compare_staged_files(staging: &Path, destination: &Path) -> Result<StagedFiles> {
+    let mut staged = StagedFiles::default();
+    for entry in WalkDir::new(staging).sort_by_file_name() {
+        let entry = entry.wrap_err("Failed to read staging directory")?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let relative = relative_path(staging, entry.path())?;
+        let target = destination.join(&relative);
+        let content = fs::read(entry.path())
+            .wrap_err_with(|| format!("Failed to read {}", entry.path().display()))?;
+        staged
+            .hashes
+            .insert(relative.clone(), content_hash(&content));
+
+        let change = match fs::metadata(&target) {
+            Err(_) => FileChange::Create,
+            Ok(metadata)
+                if metadata.is_file()
+                    && metadata.len() == content.len() as u64
+                    && fs::read(&target).is_ok_and(|existing| existing == content) =>
+            {
+                continue
+            }
+            Ok(_) => FileChange::Overwrite,
+        };
+        staged.changes.insert(relative, change);
+    }
+    Ok(staged)
+}
+
+/// Refuses to export to a destination that already has files but no manifest. Its files
+/// weren't written by obsidian-zola, and the export may overwrite some of them.
+///
+/// # Arguments
+///
+/// * `destination` - The destination directory
+/// * `staged` - The staged export, from [`compare_staged_files`]
+///
+/// # Returns
+///
+/// An error listing the files the export would overwrite, if the destination has files but no
+/// manifest.
+pub fn check_destination_ownership(destination: &Path, staged: &StagedFiles) -> Result<()> {
+    let is_empty = fs::read_dir(destination).map_or(true, |mut entries| entries.next().is_none());
+    if is_empty || destination.join(MANIFEST_FILENAME).exists() {
+        return Ok(());
+    }
+
+    let overwritten: Vec<&String> = staged
+        .changes
+        .iter()
+        .filter(|(_, change)| **change == FileChange::Overwrite)
+        .map(|(path, _)| path)
+        .collect();
+    let mut message = format!(
+        "{} has files but no {}, they weren't exported by obsidian-zola",
+        destination.display(),
+        MANIFEST_FILENAME
+    );
+    if overwritten.is_empty() {
+        message.push_str("\nNo file would be overwritten, the existing files would be kept");
+    } else {
+        message.push_str(&format!(
+            "\nThe export would overwrite {} of them:",
+            overwritten.len()
+        ));
+        for path in overwritten.iter().take(LISTED_FILES) {
+            message.push_str(&format!("\n\t{}", path));
+        }
+        if overwritten.len() > LISTED_FILES {
+            message.push_str(&format!(
+                "\n\t... and {} more",
+                overwritten.len() - LISTED_FILES
+            ));
+        }
+    }
+    Err(eyre::eyre!(message))
+}
+
+/// Returns the `/`-separated path of a file relative to a directory, as used in the manifest.
+pub fn relative_path(root: &Path, path: &Path) -> Result<String> {
+    let relative = path
+        .strip_prefix(root)
+        .wrap_err("Failed to get relative path")?;
+    Ok(relative
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(hash: &str, links: &[&str], embeds: &[&str]) -> ManifestEntry {
+        ManifestEntry {
+            hash: hash.to_string(),
+            links: links.iter().map(|link| link.to_string()).collect(),
+            embeds: embeds.iter().map(|embed| embed.to_string()).collect(),
+            ..ManifestEntry::default()
+        }
+    }
+
+    fn stale(manifest: &Manifest, sources: &BTreeMap<String, ManifestEntry>) -> Vec<String> {
+        manifest
+            .stale_sources(sources, "options", Path::new("/nonexistent"))
+            .into_iter()
+            .collect()
+    }
+
+    #[test]
+    fn test_content_hash() {
+        assert_eq!(content_hash(b""), "cbf29ce484222325");
+        assert_eq!(content_hash(b"a"), "af63dc4c8601ec8c");
+        assert_ne!(content_hash(b"# Note\n"), content_hash(b"# Note\n\n"));
+    }
+
+    #[test]
+    fn test_link_key() {
+        assert_eq!(link_key("My Note"), "my note");
+        assert_eq!(link_key("docs/My Note.md"), "my note");
+        assert_eq!(link_key("../docs/My%20Note.md"), "my note");
+        assert_eq!(link_key("images/Photo.PNG"), "photo.png");
+    }
+
+    #[test]
+    fn test_scan_file() {
+        let vault = tempfile::TempDir::new().unwrap();
+        let note = vault.path().join("note.md");
+        fs::write(
+            &note,
+            "[[Other|label]] [[docs/Guide#Intro]] ![[snippet]] ![](img/photo.png)\n\
+             [site](https://example.com) [x](../Third%20Note.md#part)\n\n\
+             ```dataview\nLIST\n```\n",
+        )
+        .unwrap();
+
+        let entry = scan_file(&note).unwrap();
+        assert_eq!(entry.links, vec!["guide", "other", "third note"]);
+        assert_eq!(entry.embeds, vec!["photo.png", "snippet"]);
+        assert!(entry.dataview);
+
+        let image = vault.path().join("photo.png");
+        fs::write(&image, "![[not a note]]").unwrap();
+        let entry = scan_file(&image).unwrap();
+        assert_eq!(entry.hash, content_hash(b"![[not a note]]"));
+        assert!(entry.links.is_empty());
+    }
+
+    #[test]
+    fn test_rescan_sources() {
+        let vault = tempfile::TempDir::new().unwrap();
+        fs::create_dir_all(vault.path().join("docs")).unwrap();
+        fs::write(vault.path().join("a.md"), "[[b]]").unwrap();
+        fs::write(vault.path().join("docs/b.md"), "# B").unwrap();
+        let mut previous = Manifest {
+            entries: scan_sources(vault.path(), WalkOptions::default()).unwrap(),
+            ..Manifest::default()
+        };
+        previous.entries.get_mut("a.md").unwrap().outputs = vec!["a.md".to_string()];
+
+        // Unchanged files keep their entries without being read, even if their content differs
+        fs::write(vault.path().join("a.md"), "[[c]]").unwrap();
+        fs::write(vault.path().join("docs/b.md"), "# Changed").unwrap();
+        fs::write(vault.path().join("new.md"), "# New").unwrap();
+        let changed = [vault.path().join("docs")];
+        let sources =
+            rescan_sources(vault.path(), WalkOptions::default(), &previous, &changed).unwrap();
+        assert_eq!(sources["a.md"].links, vec!["b"]);
+        assert!(sources["a.md"].outputs.is_empty());
+        assert_eq!(sources["docs/b.md"].hash, content_hash(b"# Changed"));
+        assert_eq!(sources["new.md"].hash, content_hash(b"# New"));
+
+        // A change of the vault itself reads everything again
+        let changed = [vault.path().to_path_buf()];
+        let sources =
+            rescan_sources(vault.path(), WalkOptions::default(), &previous, &changed).unwrap();
+        assert_eq!(sources["a.md"].links, vec!["c"]);
+    }
+
+    #[test]
+    fn test_stale_sources() {
+        let mut sources = BTreeMap::new();
+        sources.insert("a.md".to_string(), entry("1", &[], &[]));
+        sources.insert("links-a.md".to_string(), entry("2", &["a"], &[]));
+        sources.insert("embeds-a.md".to_string(), entry("3", &[], &["a"]));
+        sources.insert(
+            "embeds-embed.md".to_string(),
+            entry("4", &[], &["embeds-a"]),
+        );
+        sources.insert("links-embed.md".to_string(), entry("5", &["embeds-a"], &[]));
+        sources.insert("other.md".to_string(), entry("6", &["b"], &[]));
+        let manifest = Manifest::from_export(
+            "options".to_string(),
+            sources.clone(),
+            None,
+            &BTreeSet::new(),
+            &BTreeMap::new(),
+        );
+
+        assert!(stale(&manifest, &sources).is_empty());
+
+        // Linked notes follow, embedding notes follow recursively
+        sources.get_mut("a.md").unwrap().hash = "changed".to_string();
+        assert_eq!(
+            stale(&manifest, &sources),
+            vec!["a.md", "embeds-a.md", "embeds-embed.md", "links-a.md"]
+        );
+
+        // New notes can fix links
+        let mut with_new = manifest.entries.clone();
+        with_new.insert("dir/B.md".to_string(), entry("7", &[], &[]));
+        assert_eq!(stale(&manifest, &with_new), vec!["dir/B.md", "other.md"]);
+
+        // Removed notes break links, Dataview queries are always run again
+        let mut removed = manifest.entries.clone();
+        removed.remove("a.md");
+        removed.get_mut("other.md").unwrap().dataview = true;
+        assert_eq!(
+            stale(&manifest, &removed),
+            vec!["embeds-a.md", "embeds-embed.md", "links-a.md", "other.md"]
+        );
+
+        // Other options or missing outputs
+        let sources = manifest.entries.clone();
+        assert_eq!(
+            manifest
+                .stale_sources(&sources, "other options", Path::new("/nonexistent"))
+                .len(),
+            sources.len()
+        );
+        let mut missing_output = manifest.clone();
+        missing_output.entries.get_mut("a.md").unwrap().outputs = vec!["a.md".to_string()];
+        assert!(stale(&missing_output, &sources).contains(&"a.md".to_string()));
+    }
+
+    #[test]
+    fn test_from_export() {
+        let mut sources = BTreeMap::new();
+        sources.insert("note.md".to_string(), entry("1", &[], &[]));
+        sources.insert("board.canvas".to_string(), entry("2", &[], &[]));
+        sources.insert("flow.excalidraw.md".to_string(), entry("3", &[], &[]));
+        sources.insert("kept.md".to_string(), entry("4", &[], &[]));
+
+        let mut previous = Manifest::default();
+        previous
+            .entries
+            .insert("kept.md".to_string(), entry("4", &[], &[]));
+        previous.entries.get_mut("kept.md").unwrap().outputs = vec!["kept.md".to_string()];
+
+        let stale: BTreeSet<String> = ["note.md", "board.canvas", "flow.excalidraw.md"]
+            .iter()
+            .map(|path| path.to_string())
+            .collect();
+        let outputs: BTreeMap<String, String> =
+            ["board.md", "flow.excalidraw.svg", "note.md", "unknown.txt"]
+                .iter()
+                .map(|output| (output.to_string(), format!("hash of {}", output)))
+                .collect();
+        let manifest =
+            Manifest::from_export("x".to_string(), sources, Some(&previous), &stale, &outputs);
+
+        assert_eq!(manifest.entries["note.md"].outputs, vec!["note.md"]);
+        assert_eq!(manifest.entries["board.canvas"].outputs, vec!["board.md"]);
+        assert_eq!(
+            manifest.entries["flow.excalidraw.md"].outputs,
+            vec!["flow.excalidraw.svg"]
+        );
+        assert_eq!(manifest.entries["kept.md"].outputs, vec!["kept.md"]);
+        assert!(manifest.orphans.is_empty());
+        assert_eq!(manifest.output_hashes["note.md"], "hash of note.md");
+        assert!(!manifest.output_hashes.contains_key("unknown.txt"));
+
+        // Outputs of removed files are orphans until they are deleted
+        let mut previous = manifest.clone();
+        previous.entries.remove("note.md");
+        previous.orphans = vec!["older.md".to_string(), "kept.md".to_string()];
+        let mut sources = previous.entries.clone();
+        sources.remove("kept.md");
+        let after = Manifest::from_export(
+            "x".to_string(),
+            sources,
+            Some(&manifest),
+            &BTreeSet::new(),
+            &BTreeMap::new(),
+        );
+        assert_eq!(after.orphans, vec!["kept.md", "note.md"]);
+        assert_eq!(after.output_hashes["note.md"], "hash of note.md");
+        let after = Manifest::from_export(
+            "x".to_string(),
+            previous.entries.clone(),
+            Some(&previous),
+            &BTreeSet::new(),
+            &BTreeMap::new(),
+        );
+        assert_eq!(after.orphans, vec!["older.md"]);
+
+        let destination = tempfile::TempDir::new().unwrap();
+        manifest.save(destination.path()).unwrap();
+        assert_eq!(Manifest::load(destination.path()), Some(manifest));
+        fs::write(destination.path().join(MANIFEST_FILENAME), "{").unwrap();
+        assert_eq!(Manifest::load(destination.path()), None);
+    }
+
+    #[test]
+    fn test_compare_and_move_staged_files() {
+        let root = tempfile::TempDir::new().unwrap();
+        let destination = root.path().join("content");
+        fs::create_dir_all(destination.join("old")).unwrap();
+        fs::create_dir_all(destination.join("empty")).unwrap();
+        fs::write(destination.join("same.md"), "same").unwrap();
+        fs::write(destination.join("changed.md"), "old").unwrap();
+        fs::write(destination.join("_index.md"), "Written by hand").unwrap();
+        fs::write(destination.join("old/note.md"), "removed").unwrap();
+        fs::write(destination.join(MANIFEST_FILENAME), "{}").unwrap();
+        let before = fs::metadata(destination.join("same.md"))
+            .unwrap()
+            .modified()
+            .unwrap();
+
+        let staging = Staging::new(&destination).unwrap();
+        let staging_dir = staging.path().to_path_buf();
+        assert!(!staging_dir.starts_with(destination.canonicalize().unwrap()));
+        fs::create_dir(staging_dir.join("docs")).unwrap();
+        fs::write(staging_dir.join("same.md"), "same").unwrap();
+        fs::write(staging_dir.join("changed.md"), "new").unwrap();
+        fs::write(staging_dir.join("docs/new.md"), "new").unwrap();
+
+        let staged = compare_staged_files(&staging_dir, &destination).unwrap();
+        assert_eq!(
+            staged.hashes.keys().collect::<Vec<_>>(),
+            vec!["changed.md", "docs/new.md", "same.md"]
+        );
+        assert_eq!(staged.hashes["same.md"], content_hash(b"same"));
+        assert_eq!(
+            staged.changes.clone().into_iter().collect::<Vec<_>>(),
+            vec![
+                ("changed.md".to_string(), FileChange::Overwrite),
+                ("docs/new.md".to_string(), FileChange::Create)
+            ]
+        );
+        // Comparing doesn't touch the destination
+        assert_eq!(
+            fs::read_to_string(destination.join("changed.md")).unwrap(),
+            "old"
+        );
+
+        fs::write(staging_dir.join(MANIFEST_FILENAME), "new manifest").unwrap();
+        staging
+            .move_into(&destination, &staged.changes, &["old/note.md".to_string()])
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(destination.join("changed.md")).unwrap(),
+            "new"
+        );
+        assert!(destination.join("docs/new.md").exists());
+        assert!(destination.join("empty").is_dir());
+        assert!(!destination.join("old").exists());
+        assert_eq!(
+            fs::read_to_string(destination.join("_index.md")).unwrap(),
+            "Written by hand"
+        );
+        assert_eq!(
+            fs::read_to_string(destination.join(MANIFEST_FILENAME)).unwrap(),
+            "new manifest"
+        );
+        let after = fs::metadata(destination.join("same.md"))
+            .unwrap()
+            .modified()
+            .unwrap();
+        assert_eq!(before, after);
+
+        // Nothing is left next to the destination
+        let siblings: Vec<_> = fs::read_dir(root.path()).unwrap().collect();
+        assert_eq!(siblings.len(), 1);
+
+        let staging = Staging::new(&destination).unwrap();
+        let staging_dir = staging.path().to_path_buf();
+        drop(staging);
+        assert!(!staging_dir.exists());
+    }
+
+    #[test]
+    fn test_check_destination_ownership() {
+        let destination = tempfile::TempDir::new().unwrap();
+        let mut staged = StagedFiles::default();
+        for i in 0..12 {
+            staged
+                .changes
+                .insert(format!("note{:02}.md", i), FileChange::Overwrite);
+        }
+        staged
+            .changes
+            .insert("new.md".to_string(), FileChange::Create);
+
+        // Empty destinations and the ones with a manifest are fine
+        assert!(check_destination_ownership(destination.path(), &staged).is_ok());
+        fs::write(destination.path().join("note00.md"), "Written by hand").unwrap();
+        let message = check_destination_ownership(destination.path(), &staged)
+            .unwrap_err()
+            .to_string();
+        assert!(message.contains("The export would overwrite 12 of them:"));
+        assert!(message.contains("\tnote09.md\n\t... and 2 more"));
+        assert!(!message.contains("new.md"));
+
+        let message = check_destination_ownership(destination.path(), &StagedFiles::default())
+            .unwrap_err()
+            .to_string();
+        assert!(message.contains("No file would be overwritten"));
+
+        fs::write(destination.path().join(MANIFEST_FILENAME), "{}").unwrap();
+        assert!(check_destination_ownership(destination.path(), &staged).is_ok());
+    }
+
+    #[test]
+    fn test_forget_orphans() {
+        let root = tempfile::TempDir::new().unwrap();
+        let destination = root.path().join("content");
+        fs::create_dir_all(destination.join("a/b")).unwrap();
+        fs::create_dir_all(destination.join("c")).unwrap();
+        fs::write(destination.join("a/b/note.md"), "exported").unwrap();
+        fs::write(destination.join("c/note.md"), "exported").unwrap();
+        fs::write(destination.join("c/other.md"), "exported").unwrap();
+        fs::write(destination.join("edited.md"), "edited by hand").unwrap();
+        fs::write(destination.join("unknown.md"), "exported").unwrap();
+        fs::write(root.path().join("outside.md"), "exported").unwrap();
+
+        let mut manifest = Manifest::default();
+        for output in [
+            "a/b/note.md",
+            "c/note.md",
+            "edited.md",
+            "unknown.md",
+            "missing.md",
+            "../outside.md",
+        ] {
+            manifest.orphans.push(output.to_string());
+            if output != "unknown.md" {
+                manifest
+                    .output_hashes
+                    .insert(output.to_string(), content_hash(b"exported"));
+            }
+        }
+        manifest
+            .output_hashes
+            .insert("c/other.md".to_string(), content_hash(b"exported"));
+
+        assert_eq!(
+            manifest.prunable(&destination),
+            vec!["a/b/note.md", "c/note.md"]
+        );
+        assert!(destination.join("a/b/note.md").exists());
+        let removed = manifest.forget_orphans(&destination);
+        assert_eq!(removed, vec!["a/b/note.md", "c/note.md"]);
+        assert!(destination.join("a/b/note.md").exists());
+
+        // Moving the export into place deletes them with the directories they leave empty
+        let staging = Staging::new(&destination).unwrap();
+        staging
+            .move_into(&destination, &BTreeMap::new(), &removed)
+            .unwrap();
+        assert!(!destination.join("a").exists());
+        assert!(destination.join("c/other.md").exists());
+        assert!(destination.join("edited.md").exists());
+        assert!(destination.join("unknown.md").exists());
+        assert!(root.path().join("outside.md").exists());
+
+        // Orphans are forgotten, the other outputs are still owned
+        assert!(manifest.orphans.is_empty());
+        assert_eq!(
+            manifest.output_hashes.keys().collect::<Vec<_>>(),
+            vec!["c/other.md"]
+        );
+    }
+}
@@ -17,23 +17,39 @@
 //! ## Usage as Library
 //! 
 //! ```no_run
-//! use obsidian_zola::postprocessors::create_zola_link_postprocessor;
-//! use obsidian_export::Exporter;
+//! use obsidian_zola::canvas::CanvasMode;
+//! use obsidian_zola::export::ZolaExport;
+//! use obsidian_zola::postprocessors::ZolaOptions;
+//! use obsidian_export::FrontmatterStrategy;
 //! use std::path::PathBuf;
 //! 
 //! let vault_path = PathBuf::from("path/to/vault");
-//! let mut exporter = Exporter::new(
-//!     vault_path.clone(),
-//!     PathBuf::from("path/to/zola/content")
-//! );
+//! let content_path = PathBuf::from("path/to/zola/content");
 //! 
-//! let zola_postprocessor = create_zola_link_postprocessor(vault_path);
-//! exporter.add_postprocessor(&zola_postprocessor);
+//! // Links, embeds, Excalidraw drawings and plugin syntax are handled like the CLI does
+//! let export = ZolaExport::new(vault_path, ZolaOptions::default());
+//! let mut exporter = export.exporter(content_path.clone());
+//! exporter.frontmatter_strategy(FrontmatterStrategy::Always);
 //! exporter.run().unwrap();
+//! 
+//! // Canvases are turned into pages once the notes are exported
+//! export.export_canvases(&content_path, CanvasMode::Layout, true).unwrap();
 //! ```
 
+pub mod canvas;
+pub mod check;
+pub mod dataview;
+pub mod embeds;
+pub mod excalidraw;
+pub mod export;
+pub mod incremental;
+pub mod passthrough;
+pub mod plan;
 pub mod postprocessors;
+pub mod sanitizers;
 pub mod utils;
+pub mod vault;
+pub mod watch;
 
 pub use postprocessors::*;
 
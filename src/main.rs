@@ -3,14 +3,34 @@
 //! This tool exports Obsidian notes to Zola static site generator format,
 //! converting wikilinks to Zola's internal link format.
 
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 use eyre::{Result, WrapErr};
-use obsidian_export::{Exporter, FrontmatterStrategy};
-use obsidian_zola::{postprocessors::create_zola_link_postprocessor, utils::validate_directory};
-use std::path::PathBuf;
+use obsidian_export::{Context, FrontmatterStrategy, WalkOptions};
+use obsidian_zola::canvas::CanvasMode;
+use obsidian_zola::check::{check_links, check_vault, DiagnosticKind};
+use obsidian_zola::dataview::InlineFieldMode;
+use obsidian_zola::embeds::{
+    MediaShortcodes, DEFAULT_CODE_EMBED_MAX_BYTES, DEFAULT_TABLE_EMBED_MAX_BYTES,
+    DEFAULT_TABLE_EMBED_MAX_ROWS,
+};
+use obsidian_zola::export::ZolaExport;
+use obsidian_zola::incremental::{
+    self, check_destination_ownership, compare_staged_files, options_fingerprint, rescan_file,
+    rescan_sources, scan_file, scan_sources, FileChange, Manifest, StagedFiles, Staging,
+};
+use obsidian_zola::passthrough::{rewrite_passthrough_links, PassthroughRules};
+use obsidian_zola::plan::{count_internal_links, ExportPlan, PlannedChange};
+use obsidian_zola::sanitizers::PluginSyntax;
+use obsidian_zola::postprocessors::{figure_shortcode_stub, ZolaOptions};
+use obsidian_zola::utils::{is_markdown_file, validate_directory, validate_no_overlap};
+use obsidian_zola::vault::VaultFiles;
+use obsidian_zola::watch::{watch_vault, ChangeFilter};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::{PoisonError, RwLock};
 use std::fs;
 use walkdir::WalkDir;
-use glob::Pattern;
+use ignore::DirEntry;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -23,29 +43,122 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// Export Obsidian vault to Zola format
-    Export {
-        /// Path to the Obsidian vault to export
+    Export(Box<ExportArgs>),
+    
+    /// Check an Obsidian vault for problems that would break its export or the Zola build
+    ///
+    /// Nothing is written. Problems are printed as `file:line: message` lines and make the
+    /// command exit with an error, so it can run as a pre-commit hook.
+    Check {
+        /// Path to the Obsidian vault to check
         #[arg(short, long)]
         source: PathBuf,
         
-        /// Path to the Zola content directory to export to
-        #[arg(short, long)]
-        destination: PathBuf,
-        
-        /// Skip processing frontmatter
-        #[arg(long)]
-        skip_frontmatter: bool,
-        
-        /// Enable verbose output
-        #[arg(short, long)]
-        verbose: bool,
-        
-        /// Patterns for files to copy as-is without processing (can be used multiple times)
+        /// Gitignore-style patterns for files to copy as-is without processing (can be used
+        /// multiple times, added to the vault's `.zola-passthrough` file)
         #[arg(long = "passthrough")]
         passthrough_patterns: Vec<String>,
     },
 }
 
+/// Arguments of the `export` command
+#[derive(Args)]
+struct ExportArgs {
+    /// Path to the Obsidian vault to export
+    #[arg(short, long)]
+    source: PathBuf,
+    
+    /// Path to the Zola content directory to export to
+    #[arg(short, long)]
+    destination: PathBuf,
+    
+    /// Skip processing frontmatter
+    #[arg(long)]
+    skip_frontmatter: bool,
+    
+    /// Enable verbose output
+    #[arg(short, long)]
+    verbose: bool,
+    
+    /// Gitignore-style patterns for files to copy as-is without processing (can be used
+    /// multiple times, added to the vault's `.zola-passthrough` file)
+    #[arg(long = "passthrough")]
+    passthrough_patterns: Vec<String>,
+    
+    /// Rewrite relative markdown links in passthrough files to `@/` links, keeping the
+    /// rest of each file as-is
+    #[arg(long)]
+    passthrough_links: bool,
+    
+    /// Render sized images (`![[img.png|300]]`) through this shortcode instead of `<img>` tags
+    #[arg(long, value_name = "NAME")]
+    image_shortcode: Option<String>,
+    
+    /// Render every image through a figure shortcode (named `figure` unless given)
+    #[arg(long, value_name = "NAME", num_args = 0..=1, default_missing_value = "figure")]
+    figure_shortcode: Option<String>,
+    
+    /// Render embedded videos through this shortcode instead of `<video>` elements
+    #[arg(long, value_name = "NAME")]
+    video_shortcode: Option<String>,
+    
+    /// Render embedded audio through this shortcode instead of `<audio>` elements
+    #[arg(long, value_name = "NAME")]
+    audio_shortcode: Option<String>,
+    
+    /// Render embedded PDFs through this shortcode instead of `<object>` elements
+    #[arg(long, value_name = "NAME")]
+    pdf_shortcode: Option<String>,
+    
+    /// Largest source-code file embedded as a code block (`![[script.py]]`), in bytes
+    #[arg(long, value_name = "BYTES", default_value_t = DEFAULT_CODE_EMBED_MAX_BYTES)]
+    max_code_embed_size: u64,
+    
+    /// Largest CSV/TSV file embedded as a table (`![[data.csv]]`), in bytes
+    #[arg(long, value_name = "BYTES", default_value_t = DEFAULT_TABLE_EMBED_MAX_BYTES)]
+    max_table_embed_size: u64,
+    
+    /// Number of rows shown for embedded tables before linking to the full file
+    #[arg(long, value_name = "ROWS", default_value_t = DEFAULT_TABLE_EMBED_MAX_ROWS)]
+    max_table_rows: usize,
+    
+    /// How `.canvas` files are rendered as pages
+    #[arg(long, value_enum, default_value_t = CanvasMode::Layout)]
+    canvas: CanvasMode,
+    
+    /// How Dataview inline fields (`key:: value`) are rendered in the body, they are
+    /// copied to the `extra` frontmatter either way
+    #[arg(long, value_enum, default_value_t = InlineFieldMode::Keep)]
+    inline_fields: InlineFieldMode,
+    
+    /// Plugin syntax to leave in notes instead of removing it (can be used multiple times)
+    #[arg(long, value_enum, value_name = "SYNTAX")]
+    keep_plugin_syntax: Vec<PluginSyntax>,
+    
+    /// Export every file again instead of only the ones changed since the last export
+    #[arg(long)]
+    full: bool,
+    
+    /// Delete files exported from notes that were removed or renamed since, files
+    /// obsidian-zola didn't write are never deleted
+    #[arg(long)]
+    prune: bool,
+    
+    /// Keep running and export again whenever the vault changes (implies `--prune`)
+    #[arg(long)]
+    watch: bool,
+    
+    /// Print the files the export would create, overwrite or delete, and the links it
+    /// would rewrite, without writing anything
+    #[arg(long, conflicts_with = "watch")]
+    dry_run: bool,
+    
+    /// Export to a destination that has files but wasn't exported to before, overwriting
+    /// the ones the export writes too
+    #[arg(long)]
+    force: bool,
+}
+
 fn main() -> Result<()> {
     // Install color-eyre for better error handling
     color_eyre::install()?;
@@ -53,127 +166,567 @@ fn main() -> Result<()> {
     let cli = Cli::parse();
     
     match cli.command {
-        Commands::Export {
+        Commands::Export(args) => {
+            let ExportArgs {
+                source,
+                destination,
+                skip_frontmatter,
+                verbose,
+                passthrough_patterns,
+                passthrough_links,
+                image_shortcode,
+                figure_shortcode,
+                video_shortcode,
+                audio_shortcode,
+                pdf_shortcode,
+                max_code_embed_size,
+                max_table_embed_size,
+                max_table_rows,
+                canvas,
+                inline_fields,
+                keep_plugin_syntax,
+                full,
+                prune,
+                watch,
+                dry_run,
+                force,
+            } = *args;
+            let zola_options = ZolaOptions {
+                image_size_shortcode: image_shortcode,
+                figure_shortcode,
+                media_shortcodes: MediaShortcodes {
+                    video: video_shortcode,
+                    audio: audio_shortcode,
+                    pdf: pdf_shortcode,
+                },
+                code_embed_max_bytes: max_code_embed_size,
+                table_embed_max_bytes: max_table_embed_size,
+                table_embed_max_rows: max_table_rows,
+                inline_fields,
+                plugin_syntax: PluginSyntax::ALL
+                    .into_iter()
+                    .filter(|syntax| !keep_plugin_syntax.contains(syntax))
+                    .collect(),
+                passthrough_links,
+                // Set by the export from its walk options
+                walk_options: WalkOptions::default(),
+            };
+            let mut options = ExportOptions {
+                skip_frontmatter,
+                verbose,
+                passthrough_patterns,
+                canvas_mode: canvas,
+                full,
+                // Notes renamed or deleted while watching shouldn't linger in the preview
+                prune: prune || watch,
+                dry_run,
+                force,
+                changed: None,
+            };
+            export_vault(
+                source.clone(),
+                destination.clone(),
+                options.clone(),
+                zola_options.clone(),
+            )?;
+            
+            if watch {
+                options.full = false;
+                watch_and_export(source, destination, options, zola_options)?;
+            }
+        }
+        Commands::Check {
             source,
-            destination,
-            skip_frontmatter,
-            verbose,
             passthrough_patterns,
         } => {
-            export_vault(source, destination, skip_frontmatter, verbose, passthrough_patterns)?;
+            if !check(&source, &passthrough_patterns)? {
+                std::process::exit(1);
+            }
         }
     }
     
     Ok(())
 }
 
-fn export_vault(
-    source: PathBuf,
-    destination: PathBuf, 
+/// Checks a vault and prints its problems as `file:line: message` diagnostics.
+///
+/// # Returns
+///
+/// `true` if no problem was found.
+fn check(source: &Path, passthrough_patterns: &[String]) -> Result<bool> {
+    validate_directory(source, "Source vault")?;
+    let passthrough = PassthroughRules::new(source, passthrough_patterns)?;
+    let diagnostics = check_vault(source, &export_walk_options(source, passthrough))?;
+    
+    for diagnostic in &diagnostics {
+        println!(
+            "{}:{}: {}",
+            source.join(&diagnostic.path).display(),
+            diagnostic.line,
+            diagnostic.message
+        );
+    }
+    match diagnostics.len() {
+        0 => println!("✅ No problems found in {}", source.display()),
+        1 => eprintln!("❌ Found 1 problem"),
+        count => eprintln!("❌ Found {} problems", count),
+    }
+    Ok(diagnostics.is_empty())
+}
+
+/// Options of an export that aren't passed on to the postprocessors
+#[derive(Debug, Clone, Default)]
+struct ExportOptions {
     skip_frontmatter: bool,
     verbose: bool,
     passthrough_patterns: Vec<String>,
+    canvas_mode: CanvasMode,
+    /// Ignore the manifest of the previous export and export every file
+    full: bool,
+    /// Delete the files exported from notes that were removed or renamed since the previous
+    /// export
+    prune: bool,
+    /// Print what the export would change instead of writing anything
+    dry_run: bool,
+    /// Export to a destination that has files but no manifest
+    force: bool,
+    /// Paths changed since the previous export, when watching. Only these files are read
+    /// again, the others keep their entries in the manifest.
+    changed: Option<Vec<PathBuf>>,
+}
+
+fn export_vault(
+    source: PathBuf,
+    destination: PathBuf, 
+    options: ExportOptions,
+    zola_options: ZolaOptions,
 ) -> Result<()> {
+    let verbose = options.verbose;
+    let dry_run = options.dry_run;
+    let prune = options.prune;
+    
     if verbose {
         println!("🚀 Starting Obsidian to Zola export...");
         println!("📂 Source: {}", source.display());
         println!("📁 Destination: {}", destination.display());
     }
     
+    let planned = plan_export(&source, &destination, options, zola_options)?;
+    
+    if dry_run {
+        println!("🔍 Dry run, nothing was written to {}", destination.display());
+        print!("{}", planned.plan);
+        return Ok(());
+    }
+    
+    let has_passthrough = planned.has_passthrough;
+    apply_export(planned, &destination, prune, verbose)?;
+    
+    if verbose {
+        println!("✅ Export completed successfully!");
+        println!("🌐 Your Obsidian notes have been converted to Zola format");
+        println!("📋 Internal markdown links are now using Zola's @/ format");
+        if has_passthrough {
+            println!("📄 Passthrough files copied as-is without processing");
+        }
+    } else {
+        println!("Export completed successfully!");
+    }
+    
+    Ok(())
+}
+
+/// An export resolved in a staging directory, ready to be applied to the destination
+struct PlannedExport {
+    /// The exported files, removed once the export is applied or dropped
+    staging: Staging,
+    staged: StagedFiles,
+    /// The manifest to save, its orphans not pruned yet
+    manifest: Manifest,
+    /// Shortcode templates to write, with their content
+    stubs: Vec<(PathBuf, &'static str)>,
+    plan: ExportPlan,
+    has_passthrough: bool,
+    /// Number of vault files exported this time
+    exported: usize,
+    /// Number of files in the vault
+    files: usize,
+}
+
+/// Exports the vault to a staging directory and works out what applying the export changes
+/// in the destination, without writing to it
+fn plan_export(
+    source: &Path,
+    destination: &Path,
+    options: ExportOptions,
+    mut zola_options: ZolaOptions,
+) -> Result<PlannedExport> {
+    let ExportOptions {
+        skip_frontmatter,
+        verbose,
+        passthrough_patterns,
+        canvas_mode,
+        full,
+        prune,
+        dry_run,
+        force,
+        changed,
+    } = options;
+    
     // Validate input paths
-    validate_directory(&source, "Source vault")
+    validate_directory(source, "Source vault")
         .wrap_err("Failed to validate source vault")?;
+    validate_no_overlap(source, destination)
+        .wrap_err("Failed to validate destination directory")?;
     
-    // Create destination directory if it doesn't exist
+    // Create destination directory if it doesn't exist, dry runs plan against an empty one
     if !destination.exists() {
-        if verbose {
-            println!("📁 Creating destination directory...");
+        if !dry_run {
+            if verbose {
+                println!("📁 Creating destination directory...");
+            }
+            std::fs::create_dir_all(destination)
+                .wrap_err("Failed to create destination directory")?;
         }
-        std::fs::create_dir_all(&destination)
-            .wrap_err("Failed to create destination directory")?;
     } else {
-        validate_directory(&destination, "Destination directory")
+        validate_directory(destination, "Destination directory")
             .wrap_err("Failed to validate destination directory")?;
     }
+    
+    // Files are exported to a staging directory first, then only the changed ones are moved
+    // to the destination. Dry runs stage in the temporary directory to leave the site alone.
+    let staging = if dry_run {
+        Staging::temporary()?
+    } else {
+        Staging::new(destination)?
+    };
+    let staging_dir = staging.path().to_path_buf();
 
-    // Handle passthrough files first if any patterns are specified
-    if !passthrough_patterns.is_empty() {
+    // Handle passthrough files first if the vault or the command line has rules
+    let passthrough = PassthroughRules::new(source, &passthrough_patterns)?;
+    let has_passthrough = !passthrough.is_empty();
+    let mut passthrough_files = BTreeSet::new();
+    if has_passthrough {
         if verbose {
             println!("📋 Processing passthrough files...");
         }
-        copy_passthrough_files(&source, &destination, &passthrough_patterns, verbose)?;
-        
-        // Create temporary .export-ignore file to exclude passthrough files from obsidian-export
-        create_temporary_ignore_file(&source, &passthrough_patterns)?;
+        passthrough_files = copy_passthrough_files(
+            source,
+            &staging_dir,
+            &passthrough,
+            zola_options.passthrough_links,
+            verbose,
+        )?;
     }
     
-    // Set up the exporter
-    let mut exporter = Exporter::new(source.clone(), destination.clone());
+    // Find the files changed since the previous export, and the notes depending on them. Files
+    // that became or stopped being passthrough files are exported differently, so the rules
+    // are part of the options.
+    let fingerprint = options_fingerprint(&format!(
+        "{:?} {:?} {} {:?}",
+        zola_options, canvas_mode, skip_frontmatter, passthrough.rules()
+    ));
+    let walk_options = export_walk_options(source, passthrough);
+    let previous = Manifest::load(destination);
+    let unchanged = previous.as_ref().filter(|_| !full).zip(changed.as_deref());
+    let mut sources = match unchanged {
+        Some((manifest, changed)) => {
+            rescan_sources(source, walk_options.clone(), manifest, changed)?
+        }
+        None => scan_sources(source, walk_options.clone())?,
+    };
+    for file in &passthrough_files {
+        let entry = match unchanged {
+            Some((manifest, changed)) => {
+                rescan_file(&source.join(file), file, manifest, changed)?
+            }
+            None => scan_file(&source.join(file))?,
+        };
+        sources.insert(file.clone(), entry);
+    }
+    let mut stale = match previous.as_ref().filter(|_| !full) {
+        Some(manifest) => manifest.stale_sources(&sources, &fingerprint, destination),
+        None => sources.keys().cloned().collect(),
+    };
+    let stale_notes: Vec<&String> = stale
+        .iter()
+        .filter(|path| !passthrough_files.contains(*path))
+        .collect();
+    // Exporting a single note walks the whole vault, past a point one pass is faster
+    let single_pass = full || previous.is_none() || stale_notes.len() * 10 > sources.len();
     
     // Configure frontmatter processing
-    if skip_frontmatter {
-        exporter.frontmatter_strategy(FrontmatterStrategy::Never);
+    let frontmatter_strategy = if skip_frontmatter {
         if verbose {
             println!("⏭️  Skipping frontmatter processing");
         }
+        FrontmatterStrategy::Never
     } else {
-        exporter.frontmatter_strategy(FrontmatterStrategy::Always);
         if verbose {
             println!("📝 Processing frontmatter");
         }
+        FrontmatterStrategy::Always
+    };
+    
+    let mut stubs = Vec::new();
+    if let Some(shortcode) = &zola_options.figure_shortcode {
+        if let Some(stub_path) = missing_shortcode_stub(destination, shortcode) {
+            stubs.push((stub_path, figure_shortcode_stub()));
+        }
     }
     
-    // Add the Zola link postprocessor (no passthrough patterns needed since they're excluded)
-    let zola_postprocessor = create_zola_link_postprocessor(source.clone());
-    exporter.add_postprocessor(&zola_postprocessor);
+    // Add the Zola link postprocessor (no passthrough patterns needed since they're excluded),
+    // links to files the walk leaves out are reported
+    zola_options.walk_options = walk_options.clone();
+    let export = ZolaExport::new(source.to_path_buf(), zola_options);
     if verbose {
         println!("🔗 Added Zola link postprocessor");
     }
     
+    // Set up an exporter, for the whole vault or a single note
+    let new_exporter = |destination: PathBuf| {
+        let mut exporter = export.exporter(destination);
+        exporter.frontmatter_strategy(frontmatter_strategy);
+        exporter
+    };
+    
     // Run the export
     if verbose {
         println!("⚡ Running export...");
     }
     
-    let result = exporter.run();
+    if single_pass {
+        new_exporter(staging_dir.clone()).run().wrap_err("Export failed")?;
+        stale = sources.keys().cloned().collect();
+    } else {
+        for note in stale_notes {
+            let target = staging_dir.join(note);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)
+                    .wrap_err("Failed to create staging directory")?;
+            }
+            new_exporter(target)
+                .start_at(source.join(note))
+                .run()
+                .wrap_err_with(|| format!("Export of {} failed", note))?;
+        }
+    }
     
-    // Clean up temporary ignore file
-    if !passthrough_patterns.is_empty() {
-        cleanup_temporary_ignore_file(&source);
+    // Passthrough canvases are left out by the walk options and stay as-is
+    let canvas_pages = export
+        .export_canvases(&staging_dir, canvas_mode, !skip_frontmatter)
+        .wrap_err("Failed to export canvases")?;
+    if verbose {
+        for page in &canvas_pages {
+            let page = page.strip_prefix(&staging_dir).unwrap_or(page);
+            println!("🗺️  Exported canvas: {}", page.display());
+        }
     }
     
-    result.wrap_err("Export failed")?;
+    // Compare the staged files with the destination, files obsidian-zola didn't write are only
+    // overwritten when asked to
+    let staged = compare_staged_files(&staging_dir, destination)?;
+    let mut refusal = None;
+    if !force {
+        let ownership = check_destination_ownership(destination, &staged);
+        if dry_run {
+            // Dry runs print the refusal with the plan
+            refusal = ownership.err().map(|e| e.to_string());
+        } else {
+            ownership.wrap_err(
+                "Refusing to export to a directory obsidian-zola doesn't manage, use --force to export to it anyway",
+            )?;
+        }
+    }
+    let mut plan = ExportPlan {
+        unchanged: staged.hashes.len() - staged.changes.len(),
+        stubs: stubs.iter().map(|(path, _)| path.clone()).collect(),
+        refusal,
+        ..ExportPlan::default()
+    };
+    for (path, change) in &staged.changes {
+        let planned = match change {
+            _ if passthrough_files.contains(path) => PlannedChange::Passthrough,
+            FileChange::Create => PlannedChange::Create,
+            FileChange::Overwrite => PlannedChange::Overwrite,
+        };
+        plan.changes.insert(path.clone(), planned);
+        
+        if is_markdown_file(path) {
+            let content = fs::read_to_string(staging_dir.join(path)).unwrap_or_default();
+            let links = count_internal_links(&content);
+            if links > 0 {
+                plan.rewritten_links.insert(path.clone(), links);
+            }
+        }
+    }
+    // Broken links are only printed with the plan of a dry run. They are looked for in every
+    // note, even the ones that didn't change.
+    if dry_run {
+        let files = VaultFiles::walk(source, &walk_options)?;
+        for note in files.exported().filter(|path| is_markdown_file(path)) {
+            let content = fs::read_to_string(source.join(note)).unwrap_or_default();
+            plan.broken_links.extend(
+                check_links(note, &content, &files)
+                    .into_iter()
+                    .filter(|link| link.kind != DiagnosticKind::AmbiguousLink),
+            );
+        }
+    }
     
-    if verbose {
-        println!("✅ Export completed successfully!");
-        println!("🌐 Your Obsidian notes have been converted to Zola format");
-        println!("📋 Internal markdown links are now using Zola's @/ format");
-        if !passthrough_patterns.is_empty() {
-            println!("📄 Passthrough files copied as-is without processing");
+    let exported = stale.len();
+    let files = sources.len();
+    let manifest =
+        Manifest::from_export(fingerprint, sources, previous.as_ref(), &stale, &staged.hashes);
+    if prune {
+        for output in manifest.prunable(destination) {
+            plan.changes.insert(output, PlannedChange::Delete);
         }
+    }
+    
+    Ok(PlannedExport {
+        staging,
+        staged,
+        manifest,
+        stubs,
+        plan,
+        has_passthrough,
+        exported,
+        files,
+    })
+}
+
+/// Moves the changed files of a planned export to the destination, prunes it and remembers
+/// what was exported
+fn apply_export(
+    planned: PlannedExport,
+    destination: &Path,
+    prune: bool,
+    verbose: bool,
+) -> Result<()> {
+    let PlannedExport {
+        staging,
+        staged,
+        mut manifest,
+        stubs,
+        exported,
+        files,
+        ..
+    } = planned;
+    
+    // Everything was written to the staging directory, the changed files are only moved to the
+    // destination now that the whole export succeeded
+    let removed = if prune {
+        manifest.forget_orphans(destination)
     } else {
-        println!("Export completed successfully!");
+        Vec::new()
+    };
+    // Like the exported files, the manifest is only rewritten when it changed
+    if Manifest::load(destination).as_ref() != Some(&manifest) {
+        manifest.save(staging.path())?;
+    }
+    staging.move_into(destination, &staged.changes, &removed)?;
+    if verbose {
+        for output in &removed {
+            println!("🗑️  Removed: {}", output);
+        }
+    }
+    for (stub_path, stub) in &stubs {
+        write_shortcode_stub(stub_path, stub, verbose)?;
+    }
+    
+    if verbose {
+        println!(
+            "♻️  Exported {} of {} files, {} changed",
+            exported,
+            files,
+            staged.changes.len()
+        );
     }
     
     Ok(())
 }
 
-/// Copies files matching passthrough patterns as-is to the destination
-fn copy_passthrough_files(
-    source: &PathBuf, 
-    destination: &PathBuf, 
-    patterns: &[String], 
-    verbose: bool
+/// Watches the vault and exports it again after each batch of changes, until interrupted
+fn watch_and_export(
+    source: PathBuf,
+    destination: PathBuf,
+    options: ExportOptions,
+    zola_options: ZolaOptions,
 ) -> Result<()> {
-    // Compile all patterns upfront
-    let compiled_patterns: Result<Vec<Pattern>, _> = patterns
-        .iter()
-        .map(|p| Pattern::new(p))
-        .collect();
+    let source = source.canonicalize().wrap_err("Failed to resolve source vault")?;
+    let destination = destination
+        .canonicalize()
+        .wrap_err("Failed to resolve destination directory")?;
+    
+    // The destination can't be inside the vault, ignoring it is only a safeguard
+    let filter = ChangeFilter::new(source.clone(), vec![destination.clone()]);
+    println!("👀 Watching {} for changes, press Ctrl+C to stop", source.display());
+    watch_vault(&filter, |changes| {
+        if options.verbose {
+            for path in changes {
+                let path = path.strip_prefix(&source).unwrap_or(path);
+                println!("✏️  Changed: {}", path.display());
+            }
+        }
+        // Only the changed files are read again, a rescan reports the vault itself
+        let options = ExportOptions {
+            changed: Some(changes.to_vec()),
+            ..options.clone()
+        };
+        export_vault(
+            source.clone(),
+            destination.clone(),
+            options,
+            zola_options.clone(),
+        )
+    })
+}
+
+/// Returns where the stub template of a shortcode the export relies on goes, in the Zola
+/// site's `templates/shortcodes/` directory (next to the content directory), unless a template
+/// already exists.
+///
+/// The stub is written outside the destination: it isn't part of the staged export moved
+/// into place, nor tracked by the manifest, so it is never pruned. Nothing is written unless
+/// the destination's parent is a Zola site, i.e. has a `config.toml`.
+fn missing_shortcode_stub(destination: &Path, name: &str) -> Option<PathBuf> {
+    let site_root = destination
+        .parent()
+        .filter(|site_root| site_root.join("config.toml").is_file())?;
+    let stub_path = site_root
+        .join("templates")
+        .join("shortcodes")
+        .join(format!("{}.html", name));
+    (!stub_path.exists()).then_some(stub_path)
+}
+
+/// Writes the stub template of a shortcode
+fn write_shortcode_stub(stub_path: &Path, stub: &str, verbose: bool) -> Result<()> {
+    if let Some(parent) = stub_path.parent() {
+        fs::create_dir_all(parent)
+            .wrap_err("Failed to create shortcodes directory")?;
+    }
+    fs::write(stub_path, stub)
+        .wrap_err("Failed to write shortcode stub")?;
     
-    let compiled_patterns = compiled_patterns
-        .wrap_err("Failed to compile glob patterns")?;
+    if verbose {
+        println!("🧩 Wrote shortcode stub: {}", stub_path.display());
+    }
     
+    Ok(())
+}
+
+/// Copies files matching passthrough rules as-is to the destination, optionally rewriting
+/// the link targets of markdown files. Returns the vault-relative paths of the copied files.
+fn copy_passthrough_files(
+    source: &Path, 
+    destination: &Path, 
+    passthrough: &PassthroughRules, 
+    rewrite_links: bool,
+    verbose: bool
+) -> Result<BTreeSet<String>> {
+    let mut copied = BTreeSet::new();
     for entry in WalkDir::new(source) {
         let entry = entry.wrap_err("Failed to read directory entry")?;
         let path = entry.path();
@@ -187,14 +740,8 @@ fn copy_passthrough_files(
         let relative_path = path.strip_prefix(source)
             .wrap_err("Failed to get relative path")?;
         
-        let path_str = relative_path.to_string_lossy();
-        
-        // Check if this file matches any passthrough pattern
-        let should_copy = compiled_patterns.iter().any(|pattern| {
-            pattern.matches(&path_str)
-        });
-        
-        if should_copy {
+        // Check if this file matches the passthrough rules
+        if passthrough.matches(path) {
             let dest_path = destination.join(relative_path);
             
             // Create parent directories if needed
@@ -203,79 +750,70 @@ fn copy_passthrough_files(
                     .wrap_err("Failed to create destination directory")?;
             }
             
-            // Copy the file as-is
-            fs::copy(path, &dest_path)
-                .wrap_err("Failed to copy passthrough file")?;
+            if rewrite_links && is_markdown_file(path) {
+                // Only link targets change, the rest of the file is kept byte-for-byte
+                let content = fs::read_to_string(path)
+                    .wrap_err("Failed to read passthrough file")?;
+                let context = Context::new(path.to_path_buf(), dest_path.clone());
+                fs::write(&dest_path, rewrite_passthrough_links(&content, &context, source))
+                    .wrap_err("Failed to write passthrough file")?;
+            } else {
+                // Copy the file as-is
+                fs::copy(path, &dest_path)
+                    .wrap_err("Failed to copy passthrough file")?;
+            }
             
             if verbose {
                 println!("📄 Copied passthrough: {}", relative_path.display());
             }
+            copied.insert(incremental::relative_path(source, path)?);
         }
     }
     
-    Ok(())
+    Ok(copied)
 }
 
-/// Creates a temporary .export-ignore file to exclude passthrough files from obsidian-export
-fn create_temporary_ignore_file(source: &PathBuf, patterns: &[String]) -> Result<()> {
-    let ignore_file = source.join(".export-ignore");
-    let backup_file = source.join(".export-ignore.backup");
-    
-    // Back up existing .export-ignore if it exists
-    if ignore_file.exists() {
-        fs::copy(&ignore_file, &backup_file)
-            .wrap_err("Failed to backup existing .export-ignore")?;
-        
-        // Read existing content
-        let mut content = fs::read_to_string(&ignore_file)
-            .wrap_err("Failed to read existing .export-ignore")?;
-        
-        // Add passthrough patterns
-        content.push_str("\n# Temporary patterns for passthrough files\n");
-        for pattern in patterns {
-            content.push_str(pattern);
-            content.push('\n');
-        }
-        
-        fs::write(&ignore_file, content)
-            .wrap_err("Failed to update ignore file")?;
-    } else {
-        // Create new .export-ignore with just our patterns
-        let mut content = String::from("# Temporary patterns for passthrough files\n");
-        for pattern in patterns {
-            content.push_str(pattern);
-            content.push('\n');
-        }
-        
-        fs::write(&ignore_file, content)
-            .wrap_err("Failed to create temporary ignore file")?;
+/// Builds the walk options of an export, excluding passthrough files from obsidian-export.
+/// The vault itself is never modified.
+fn export_walk_options(source: &Path, passthrough: PassthroughRules) -> WalkOptions<'static> {
+    let mut walk_options = WalkOptions::default();
+    if !passthrough.is_empty() {
+        PASSTHROUGH_RULES
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(source.to_path_buf(), passthrough);
+        walk_options.filter_fn = Some(&is_not_passthrough);
     }
-    
-    Ok(())
+    walk_options
 }
 
-/// Cleans up the temporary ignore file modifications
-fn cleanup_temporary_ignore_file(source: &PathBuf) {
-    let ignore_file = source.join(".export-ignore");
-    let backup_file = source.join(".export-ignore.backup");
-    
-    if backup_file.exists() {
-        // Restore from backup
-        if let Err(e) = fs::rename(&backup_file, &ignore_file) {
-            eprintln!("Warning: Failed to restore .export-ignore from backup: {}", e);
-        }
-    } else if ignore_file.exists() {
-        // We created the file, so remove it entirely
-        if let Err(e) = fs::remove_file(&ignore_file) {
-            eprintln!("Warning: Failed to remove temporary .export-ignore: {}", e);
-        }
+/// Passthrough rules of the vaults exported by this process, by vault root.
+///
+/// obsidian-export only accepts a `'static` walk filter, so [`is_not_passthrough`] looks the
+/// rules up here instead of capturing them. Each export replaces the rules of its vault, so
+/// rebuilds under `--watch` pick up changed rules without building a new filter.
+static PASSTHROUGH_RULES: RwLock<BTreeMap<PathBuf, PassthroughRules>> =
+    RwLock::new(BTreeMap::new());
+
+/// Walk filter excluding passthrough files from obsidian-export, see [`PASSTHROUGH_RULES`].
+fn is_not_passthrough(entry: &DirEntry) -> bool {
+    if entry.file_type().is_some_and(|file_type| file_type.is_dir()) {
+        return true;
     }
+    let rules = PASSTHROUGH_RULES.read().unwrap_or_else(PoisonError::into_inner);
+    // The innermost vault wins in case vaults are nested
+    rules
+        .iter()
+        .filter(|(root, _)| entry.path().starts_with(root))
+        .max_by_key(|(root, _)| root.as_os_str().len())
+        .is_none_or(|(_, passthrough)| !passthrough.matches(entry.path()))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use tempfile::TempDir;
+    use std::collections::BTreeMap;
     use std::fs;
     
     #[test]
@@ -291,9 +829,8 @@ mod tests {
         let result = export_vault(
             temp_source.path().to_path_buf(),
             dest_path.clone(),
-            false,
-            false,
-            Vec::new(),
+            ExportOptions::default(),
+            ZolaOptions::default(),
         );
         
         assert!(result.is_ok());
@@ -308,11 +845,575 @@ mod tests {
         let result = export_vault(
             invalid_source,
             temp_dest.path().to_path_buf(),
-            false,
-            false,
-            Vec::new(),
+            ExportOptions::default(),
+            ZolaOptions::default(),
+        );
+        
+        assert!(result.is_err());
+    }
+    
+    /// Reads every file of a directory, hidden ones included
+    fn snapshot(dir: &Path) -> Vec<(PathBuf, Vec<u8>)> {
+        let mut files: Vec<_> = WalkDir::new(dir)
+            .into_iter()
+            .map(|entry| entry.unwrap())
+            .filter(|entry| entry.file_type().is_file())
+            .map(|entry| {
+                let relative = entry.path().strip_prefix(dir).unwrap().to_path_buf();
+                (relative, fs::read(entry.path()).unwrap())
+            })
+            .collect();
+        files.sort();
+        files
+    }
+    
+    #[test]
+    fn test_export_vault_excludes_passthrough_files_in_memory() {
+        let temp_source = TempDir::new().unwrap();
+        let temp_dest = TempDir::new().unwrap();
+        fs::create_dir(temp_source.path().join("templates")).unwrap();
+        fs::write(temp_source.path().join("templates/daily.md"), "[[note]]").unwrap();
+        fs::write(temp_source.path().join("note.md"), "[[templates/daily]]").unwrap();
+        fs::write(temp_source.path().join(".export-ignore"), "private.md\n").unwrap();
+        fs::write(temp_source.path().join("private.md"), "secret").unwrap();
+        let before = snapshot(temp_source.path());
+        
+        export_vault(
+            temp_source.path().to_path_buf(),
+            temp_dest.path().to_path_buf(),
+            ExportOptions {
+                skip_frontmatter: true,
+                passthrough_patterns: vec!["templates/*".to_string()],
+                ..ExportOptions::default()
+            },
+            ZolaOptions::default(),
+        )
+        .unwrap();
+        
+        assert_eq!(snapshot(temp_source.path()), before);
+        assert_eq!(
+            fs::read_to_string(temp_dest.path().join("templates/daily.md")).unwrap(),
+            "[[note]]"
+        );
+        assert!(temp_dest.path().join("note.md").exists());
+        assert!(!temp_dest.path().join("private.md").exists());
+    }
+    
+    #[test]
+    fn test_export_vault_reads_passthrough_file() {
+        let temp_source = TempDir::new().unwrap();
+        let temp_dest = TempDir::new().unwrap();
+        fs::create_dir_all(temp_source.path().join("templates/weekly")).unwrap();
+        fs::write(temp_source.path().join("templates/weekly/review.md"), "[[note]]").unwrap();
+        fs::write(temp_source.path().join("templates/index.md"), "[[note]]").unwrap();
+        fs::write(temp_source.path().join("note.md"), "# Note").unwrap();
+        fs::write(
+            temp_source.path().join(".zola-passthrough"),
+            "templates\n!templates/index.md\n",
+        )
+        .unwrap();
+        
+        export_vault(
+            temp_source.path().to_path_buf(),
+            temp_dest.path().to_path_buf(),
+            ExportOptions {
+                skip_frontmatter: true,
+                ..ExportOptions::default()
+            },
+            ZolaOptions::default(),
+        )
+        .unwrap();
+        
+        assert_eq!(
+            fs::read_to_string(temp_dest.path().join("templates/weekly/review.md")).unwrap(),
+            "[[note]]"
+        );
+        assert_eq!(
+            fs::read_to_string(temp_dest.path().join("templates/index.md")).unwrap(),
+            "[note](@/note.md)\n"
+        );
+        assert!(!temp_dest.path().join(".zola-passthrough").exists());
+    }
+    
+    #[test]
+    fn test_export_vault_converts_former_passthrough_files() {
+        let temp_source = TempDir::new().unwrap();
+        let temp_dest = TempDir::new().unwrap();
+        fs::create_dir(temp_source.path().join("raw")).unwrap();
+        fs::write(temp_source.path().join("raw/a.md"), "See [[b]]").unwrap();
+        fs::write(temp_source.path().join("b.md"), "# B").unwrap();
+        let export = |passthrough_patterns| {
+            let options = ExportOptions {
+                passthrough_patterns,
+                ..ExportOptions::default()
+            };
+            export_vault(
+                temp_source.path().to_path_buf(),
+                temp_dest.path().to_path_buf(),
+                options,
+                ZolaOptions::default(),
+            )
+            .unwrap();
+        };
+        
+        export(vec!["raw/**".to_string()]);
+        assert_eq!(
+            fs::read_to_string(temp_dest.path().join("raw/a.md")).unwrap(),
+            "See [[b]]"
+        );
+        
+        // Dropping the rule converts the file, though it didn't change
+        export(Vec::new());
+        let converted = fs::read_to_string(temp_dest.path().join("raw/a.md")).unwrap();
+        assert!(converted.contains("(@/b.md)"));
+    }
+    
+    #[test]
+    fn test_export_vault_rewrites_passthrough_links() {
+        let temp_source = TempDir::new().unwrap();
+        let temp_dest = TempDir::new().unwrap();
+        fs::create_dir(temp_source.path().join("pages")).unwrap();
+        let page = "+++\ntitle = \"About\"\n+++\n\n{{ hero() }}\n\nSee   [the note](../note.md).\n";
+        fs::write(temp_source.path().join("pages/about.md"), page).unwrap();
+        fs::write(temp_source.path().join("pages/data.json"), "[1](../note.md)").unwrap();
+        fs::write(temp_source.path().join("note.md"), "# Note").unwrap();
+        
+        let zola_options = ZolaOptions {
+            passthrough_links: true,
+            ..ZolaOptions::default()
+        };
+        export_vault(
+            temp_source.path().to_path_buf(),
+            temp_dest.path().to_path_buf(),
+            ExportOptions {
+                skip_frontmatter: true,
+                passthrough_patterns: vec!["pages/".to_string()],
+                ..ExportOptions::default()
+            },
+            zola_options,
+        )
+        .unwrap();
+        
+        assert_eq!(
+            fs::read_to_string(temp_dest.path().join("pages/about.md")).unwrap(),
+            page.replace("../note.md", "@/note.md")
+        );
+        assert_eq!(
+            fs::read_to_string(temp_dest.path().join("pages/data.json")).unwrap(),
+            "[1](../note.md)"
+        );
+        assert_eq!(
+            fs::read_to_string(temp_source.path().join("pages/about.md")).unwrap(),
+            page
+        );
+    }
+    
+    #[test]
+    fn test_export_vault_only_rewrites_changed_files() {
+        let temp_source = TempDir::new().unwrap();
+        let temp_dest = TempDir::new().unwrap();
+        // Enough notes for changed notes to be exported one by one
+        for i in 0..30 {
+            fs::write(temp_source.path().join(format!("note{}.md", i)), "# Note").unwrap();
+        }
+        fs::write(temp_source.path().join("linker.md"), "[[target]]").unwrap();
+        fs::write(temp_source.path().join("target.md"), "# Target").unwrap();
+        let export = || {
+            export_vault(
+                temp_source.path().to_path_buf(),
+                temp_dest.path().to_path_buf(),
+                ExportOptions::default(),
+                ZolaOptions::default(),
+            )
+            .unwrap();
+        };
+        let modified = |name: &str| {
+            fs::metadata(temp_dest.path().join(name))
+                .unwrap()
+                .modified()
+                .unwrap()
+        };
+        
+        export();
+        let manifest = Manifest::load(temp_dest.path()).unwrap();
+        assert_eq!(manifest.entries["linker.md"].links, vec!["target"]);
+        assert_eq!(manifest.entries["target.md"].outputs, vec!["target.md"]);
+        let note_modified = modified("note0.md");
+        let linker_modified = modified("linker.md");
+        
+        // The linking note is exported again but its output didn't change
+        fs::write(temp_source.path().join("target.md"), "# New target").unwrap();
+        export();
+        assert!(fs::read_to_string(temp_dest.path().join("target.md"))
+            .unwrap()
+            .ends_with("# New target\n"));
+        assert_eq!(modified("note0.md"), note_modified);
+        assert_eq!(modified("linker.md"), linker_modified);
+        
+        // The destination directory itself stays in place, for `zola serve` watching it
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            let inode = || fs::metadata(temp_dest.path()).unwrap().ino();
+            let before = inode();
+            export();
+            fs::write(temp_source.path().join("target.md"), "# Newer target").unwrap();
+            export();
+            assert_eq!(inode(), before);
+        }
+        
+        // Removed outputs are exported again
+        fs::remove_file(temp_dest.path().join("note3.md")).unwrap();
+        export();
+        assert!(temp_dest.path().join("note3.md").exists());
+    }
+    
+    #[test]
+    fn test_export_vault_only_reads_changed_paths() {
+        let temp_source = TempDir::new().unwrap();
+        let temp_dest = TempDir::new().unwrap();
+        fs::write(temp_source.path().join("note1.md"), "# Note 1").unwrap();
+        fs::write(temp_source.path().join("note2.md"), "# Note 2").unwrap();
+        // Enough notes that exporting one doesn't fall back to a single pass
+        for i in 3..12 {
+            fs::write(temp_source.path().join(format!("note{}.md", i)), "# Note").unwrap();
+        }
+        let export = |changed: Option<Vec<PathBuf>>| {
+            let options = ExportOptions {
+                changed,
+                ..ExportOptions::default()
+            };
+            export_vault(
+                temp_source.path().to_path_buf(),
+                temp_dest.path().to_path_buf(),
+                options,
+                ZolaOptions::default(),
+            )
+            .unwrap();
+        };
+        
+        export(None);
+        fs::write(temp_source.path().join("note1.md"), "# Edited 1").unwrap();
+        fs::write(temp_source.path().join("note2.md"), "# Edited 2").unwrap();
+        
+        // Files outside of the batch aren't read again
+        export(Some(vec![temp_source.path().join("note1.md")]));
+        let note1 = fs::read_to_string(temp_dest.path().join("note1.md")).unwrap();
+        let note2 = fs::read_to_string(temp_dest.path().join("note2.md")).unwrap();
+        assert!(note1.contains("Edited 1"));
+        assert!(note2.contains("Note 2"));
+        
+        // A rescan reports the vault itself
+        export(Some(vec![temp_source.path().to_path_buf()]));
+        let note2 = fs::read_to_string(temp_dest.path().join("note2.md")).unwrap();
+        assert!(note2.contains("Edited 2"));
+    }
+    
+    #[test]
+    fn test_export_vault_prunes_removed_notes() {
+        let temp_source = TempDir::new().unwrap();
+        let temp_dest = TempDir::new().unwrap();
+        fs::create_dir(temp_source.path().join("old")).unwrap();
+        fs::write(temp_source.path().join("old/note.md"), "# Note").unwrap();
+        fs::write(temp_source.path().join("kept.md"), "# Kept").unwrap();
+        let export = |prune| {
+            let options = ExportOptions {
+                prune,
+                ..ExportOptions::default()
+            };
+            export_vault(
+                temp_source.path().to_path_buf(),
+                temp_dest.path().to_path_buf(),
+                options,
+                ZolaOptions::default(),
+            )
+            .unwrap();
+        };
+        
+        export(true);
+        fs::write(temp_dest.path().join("_index.md"), "Written by hand").unwrap();
+        fs::create_dir(temp_source.path().join("new")).unwrap();
+        fs::rename(
+            temp_source.path().join("old/note.md"),
+            temp_source.path().join("new/note.md"),
+        )
+        .unwrap();
+        
+        // Without pruning, the old output stays
+        export(false);
+        assert!(temp_dest.path().join("old/note.md").exists());
+        
+        // Exported files edited by hand are kept too
+        fs::remove_file(temp_source.path().join("kept.md")).unwrap();
+        fs::write(temp_dest.path().join("kept.md"), "Edited by hand").unwrap();
+        
+        export(true);
+        assert!(!temp_dest.path().join("old").exists());
+        assert!(temp_dest.path().join("new/note.md").exists());
+        assert!(temp_dest.path().join("kept.md").exists());
+        assert!(temp_dest.path().join("_index.md").exists());
+        let manifest = Manifest::load(temp_dest.path()).unwrap();
+        assert!(manifest.orphans.is_empty());
+        assert!(!manifest.output_hashes.contains_key("kept.md"));
+    }
+    
+    #[test]
+    fn test_dry_run_writes_nothing() {
+        let temp_source = TempDir::new().unwrap();
+        let temp_site = TempDir::new().unwrap();
+        let dest_path = temp_site.path().join("content");
+        fs::create_dir(temp_source.path().join("pages")).unwrap();
+        fs::write(temp_source.path().join("pages/raw.md"), "Raw").unwrap();
+        fs::write(temp_source.path().join("note.md"), "[[other]] [[missing]]").unwrap();
+        fs::write(temp_source.path().join("other.md"), "# Other").unwrap();
+        fs::write(temp_source.path().join("old.md"), "# Old").unwrap();
+        fs::write(temp_site.path().join("config.toml"), "").unwrap();
+        let options = |dry_run| ExportOptions {
+            passthrough_patterns: vec!["pages/".to_string()],
+            prune: true,
+            dry_run,
+            ..ExportOptions::default()
+        };
+        let zola_options = ZolaOptions {
+            figure_shortcode: Some("figure".to_string()),
+            ..ZolaOptions::default()
+        };
+
+        // Nothing is created, not even the destination
+        let planned = plan_export(
+            temp_source.path(),
+            &dest_path,
+            options(true),
+            zola_options.clone(),
+        )
+        .unwrap();
+        assert_eq!(planned.plan.changes["note.md"], PlannedChange::Create);
+        assert_eq!(planned.plan.changes["pages/raw.md"], PlannedChange::Passthrough);
+        assert_eq!(planned.plan.rewritten_links["note.md"], 1);
+        assert_eq!(planned.plan.broken_links.len(), 1);
+        assert_eq!(
+            planned.plan.broken_links[0].to_string(),
+            "note.md:1: broken link 'missing'"
+        );
+        assert_eq!(planned.plan.stubs.len(), 1);
+        drop(planned);
+        export_vault(
+            temp_source.path().to_path_buf(),
+            dest_path.clone(),
+            options(true),
+            zola_options.clone(),
+        )
+        .unwrap();
+        assert_eq!(snapshot(temp_site.path()), vec![(PathBuf::from("config.toml"), Vec::new())]);
+
+        // Changes to an existing export are planned but not applied
+        export_vault(
+            temp_source.path().to_path_buf(),
+            dest_path.clone(),
+            options(false),
+            zola_options.clone(),
+        )
+        .unwrap();
+        fs::remove_file(temp_source.path().join("old.md")).unwrap();
+        fs::write(temp_source.path().join("other.md"), "# Changed").unwrap();
+        let before = snapshot(temp_site.path());
+
+        let planned = plan_export(
+            temp_source.path(),
+            &dest_path,
+            options(true),
+            zola_options.clone(),
+        )
+        .unwrap();
+        assert_eq!(
+            planned.plan.changes,
+            BTreeMap::from([
+                ("old.md".to_string(), PlannedChange::Delete),
+                ("other.md".to_string(), PlannedChange::Overwrite),
+            ])
+        );
+        assert!(planned.plan.stubs.is_empty());
+        drop(planned);
+        export_vault(
+            temp_source.path().to_path_buf(),
+            dest_path.clone(),
+            options(true),
+            zola_options,
+        )
+        .unwrap();
+        assert_eq!(snapshot(temp_site.path()), before);
+    }
+
+    #[test]
+    fn test_export_vault_refuses_unmanaged_destination() {
+        let temp_source = TempDir::new().unwrap();
+        let temp_dest = TempDir::new().unwrap();
+        fs::write(temp_source.path().join("index.md"), "# Notes").unwrap();
+        fs::write(temp_dest.path().join("index.md"), "Written by hand").unwrap();
+        let export = |source: &Path, destination: &Path, force| {
+            export_vault(
+                source.to_path_buf(),
+                destination.to_path_buf(),
+                ExportOptions {
+                    force,
+                    ..ExportOptions::default()
+                },
+                ZolaOptions::default(),
+            )
+        };
+        
+        // Dry runs plan the export and report the refusal
+        let planned = plan_export(
+            temp_source.path(),
+            temp_dest.path(),
+            ExportOptions {
+                dry_run: true,
+                ..ExportOptions::default()
+            },
+            ZolaOptions::default(),
+        )
+        .unwrap();
+        assert!(planned.plan.to_string().contains("The export would overwrite 1 of them:\n\tindex.md"));
+        drop(planned);
+        
+        let error = export(temp_source.path(), temp_dest.path(), false).unwrap_err();
+        assert!(format!("{:#}", error).contains("The export would overwrite 1 of them:\n\tindex.md"));
+        assert_eq!(
+            fs::read_to_string(temp_dest.path().join("index.md")).unwrap(),
+            "Written by hand"
+        );
+        
+        // Once forced, the destination is managed
+        export(temp_source.path(), temp_dest.path(), true).unwrap();
+        assert!(temp_dest.path().join(incremental::MANIFEST_FILENAME).exists());
+        export(temp_source.path(), temp_dest.path(), false).unwrap();
+        
+        // Overlapping directories are refused even when forced
+        let inside = temp_source.path().join("public");
+        assert!(export(temp_source.path(), &inside, true).is_err());
+        assert!(!inside.exists());
+        assert!(export(temp_source.path(), temp_source.path(), true).is_err());
+    }
+    
+    #[test]
+    fn test_check_reports_problems() {
+        let temp_source = TempDir::new().unwrap();
+        fs::create_dir(temp_source.path().join("templates")).unwrap();
+        fs::write(temp_source.path().join("templates/daily.md"), "[[missing]]").unwrap();
+        fs::write(temp_source.path().join("note.md"), "---\ntitle: Note\n---\n[[other]]").unwrap();
+        fs::write(temp_source.path().join("other.md"), "# Other").unwrap();
+        let before = snapshot(temp_source.path());
+        
+        // Passthrough files aren't checked
+        assert!(check(temp_source.path(), &["templates/".to_string()]).unwrap());
+        assert!(!check(temp_source.path(), &[]).unwrap());
+        assert!(check(&temp_source.path().join("missing"), &[]).is_err());
+        assert_eq!(snapshot(temp_source.path()), before);
+    }
+    
+    #[test]
+    fn test_failed_export_leaves_vault_untouched() {
+        let temp_source = TempDir::new().unwrap();
+        let temp_dest = TempDir::new().unwrap();
+        fs::create_dir(temp_source.path().join("templates")).unwrap();
+        fs::write(temp_source.path().join("templates/daily.md"), "<% tp.date.now() %>").unwrap();
+        fs::write(temp_source.path().join(".export-ignore"), "# Mine\nprivate/\n").unwrap();
+        fs::write(temp_source.path().join("note.md"), "# Note").unwrap();
+        // Invalid frontmatter makes obsidian-export fail
+        fs::write(temp_source.path().join("broken.md"), "---\ntitle: [unclosed\n---\nText").unwrap();
+        let before = snapshot(temp_source.path());
+        
+        let result = export_vault(
+            temp_source.path().to_path_buf(),
+            temp_dest.path().to_path_buf(),
+            ExportOptions {
+                passthrough_patterns: vec!["templates/*".to_string()],
+                ..ExportOptions::default()
+            },
+            ZolaOptions::default(),
         );
         
         assert!(result.is_err());
+        assert_eq!(snapshot(temp_source.path()), before);
+    }
+    
+    #[test]
+    fn test_failed_export_leaves_destination_intact() {
+        let temp_source = TempDir::new().unwrap();
+        let temp_site = TempDir::new().unwrap();
+        let dest_path = temp_site.path().join("content");
+        fs::write(temp_source.path().join("note.md"), "# Note").unwrap();
+        fs::write(temp_source.path().join("other.md"), "# Other").unwrap();
+        let export = || {
+            export_vault(
+                temp_source.path().to_path_buf(),
+                dest_path.clone(),
+                ExportOptions {
+                    full: true,
+                    prune: true,
+                    ..ExportOptions::default()
+                },
+                ZolaOptions::default(),
+            )
+        };
+
+        export().unwrap();
+        fs::write(dest_path.join("_index.md"), "Written by hand").unwrap();
+        let before = snapshot(temp_site.path());
+
+        // Changes made before the failing note are thrown away with it
+        fs::write(temp_source.path().join("note.md"), "# Changed").unwrap();
+        fs::remove_file(temp_source.path().join("other.md")).unwrap();
+        fs::write(temp_source.path().join("broken.md"), "---\ntitle: [unclosed\n---\nText").unwrap();
+        assert!(export().is_err());
+        assert_eq!(snapshot(temp_site.path()), before);
+
+        fs::remove_file(temp_source.path().join("broken.md")).unwrap();
+        export().unwrap();
+        assert!(fs::read_to_string(dest_path.join("note.md"))
+            .unwrap()
+            .ends_with("# Changed\n"));
+        assert!(!dest_path.join("other.md").exists());
+        assert!(dest_path.join("_index.md").exists());
+        assert_eq!(fs::read_dir(temp_site.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_export_vault_writes_figure_shortcode_stub() {
+        let temp_source = TempDir::new().unwrap();
+        let temp_site = TempDir::new().unwrap();
+        let dest_path = temp_site.path().join("content");
+        fs::write(temp_source.path().join("test.md"), "![Alt](img.png)").unwrap();
+        
+        let zola_options = ZolaOptions {
+            figure_shortcode: Some("figure".to_string()),
+            ..Default::default()
+        };
+        let export = || {
+            export_vault(
+                temp_source.path().to_path_buf(),
+                dest_path.clone(),
+                ExportOptions::default(),
+                zola_options.clone(),
+            )
+            .unwrap()
+        };
+        
+        // Only Zola sites get a stub
+        export();
+        assert!(!temp_site.path().join("templates").exists());
+        
+        fs::write(temp_site.path().join("config.toml"), "").unwrap();
+        export();
+        let stub_path = temp_site.path().join("templates/shortcodes/figure.html");
+        assert_eq!(fs::read_to_string(&stub_path).unwrap(), figure_shortcode_stub());
+        assert!(fs::read_to_string(dest_path.join("test.md"))
+            .unwrap()
+            .contains(r#"{{ figure(src="/img.png", alt="Alt", caption="Alt") }}"#));
+        
+        // An existing template is never overwritten
+        fs::write(&stub_path, "custom").unwrap();
+        assert_eq!(missing_shortcode_stub(&dest_path, "figure"), None);
+        assert_eq!(fs::read_to_string(&stub_path).unwrap(), "custom");
     }
 }
@@ -0,0 +1,362 @@
+//! Rules for files copied as-is instead of being converted (passthrough files).
+//!
+//! Rules follow the gitignore syntax, like `.export-ignore`: `**` matches nested
+//! directories, `!pattern` negates an earlier rule, a bare name (`templates`) matches a
+//! directory and everything in it, and a trailing `/` restricts a rule to directories. Rules
+//! come from the `.zola-passthrough` file at the root of the vault, followed by the ones given
+//! on the command line, so command-line rules take precedence.
+//!
+//! Passthrough files are copied byte-for-byte, unless link rewriting is enabled: then the
+//! targets of relative markdown links are patched in place and the rest of the file is left
+//! untouched.
+
+use crate::postprocessors::convert_to_zola_link_with_context;
+use eyre::{Result, WrapErr};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use obsidian_export::pulldown_cmark::{Event, LinkType, Options, Parser, Tag, TagEnd};
+use obsidian_export::Context;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+/// Name of the file listing passthrough rules, at the root of the vault.
+pub const PASSTHROUGH_FILENAME: &str = ".zola-passthrough";
+
+/// Decides which files of a vault are passthrough files.
+#[derive(Debug, Clone)]
+pub struct PassthroughRules {
+    matcher: Gitignore,
+    /// The rules as written, the `.zola-passthrough` file's first
+    rules: Vec<String>,
+}
+
+impl PassthroughRules {
+    /// Loads the rules of a vault.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The source vault directory, rules are relative to it
+    /// * `patterns` - Additional rules, applied after the `.zola-passthrough` file
+    ///
+    /// # Returns
+    ///
+    /// The rules, or an error if the file can't be read or a rule is invalid.
+    pub fn new(source: &Path, patterns: &[String]) -> Result<Self> {
+        let mut builder = GitignoreBuilder::new(source);
+        let mut rules = Vec::new();
+
+        let rules_file = source.join(PASSTHROUGH_FILENAME);
+        if rules_file.is_file() {
+            let message = || {
+                format!(
+                    "Failed to read passthrough rules from {}",
+                    rules_file.display()
+                )
+            };
+            if let Some(e) = builder.add(&rules_file) {
+                return Err(e).wrap_err_with(message);
+            }
+            let content = fs::read_to_string(&rules_file).wrap_err_with(message)?;
+            rules.extend(content.lines().map(String::from));
+        }
+        for pattern in patterns {
+            builder
+                .add_line(None, pattern)
+                .wrap_err_with(|| format!("Invalid passthrough pattern '{}'", pattern))?;
+        }
+        rules.extend(patterns.iter().cloned());
+
+        let matcher = builder
+            .build()
+            .wrap_err("Failed to compile passthrough patterns")?;
+        Ok(Self { matcher, rules })
+    }
+
+    /// Returns the rules as written, the lines of the `.zola-passthrough` file followed by the
+    /// command-line rules. Exports compare them to tell whether the rules changed.
+    pub fn rules(&self) -> &[String] {
+        &self.rules
+    }
+
+    /// Returns `true` when there are no rules, so no file is a passthrough file.
+    pub fn is_empty(&self) -> bool {
+        self.matcher.is_empty()
+    }
+
+    /// Checks whether a file is a passthrough file, either matched itself or inside a matched
+    /// directory. A negated rule matching the file itself wins over its directories.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the file, inside the vault
+    ///
+    /// # Returns
+    ///
+    /// `true` if the file should be copied as-is.
+    pub fn matches(&self, path: &Path) -> bool {
+        self.matcher
+            .matched_path_or_any_parents(path, false)
+            .is_ignore()
+    }
+}
+
+/// Rewrites the link targets of a passthrough markdown file to Zola links, leaving the rest
+/// of the file byte-for-byte identical.
+///
+/// Inline links (`[text](other.md)`) and reference definitions (`[ref]: other.md`) are
+/// located with the parser's source offsets and only their destination is replaced, using
+/// [`convert_to_zola_link_with_context`]. Images, autolinks, wikilinks and a leading
+/// `+++`/`---` frontmatter block are never changed.
+///
+/// # Arguments
+///
+/// * `content` - The content of the passthrough file
+/// * `context` - The context of the file, its current file being the path inside the vault
+/// * `source_dir` - The source vault directory
+///
+/// # Returns
+///
+/// The content with patched link targets.
+pub fn rewrite_passthrough_links(content: &str, context: &Context, source_dir: &Path) -> String {
+    let body_start = frontmatter_end(content);
+    let body = &content[body_start..];
+
+    let mut patches: Vec<(Range<usize>, String)> = Vec::new();
+    let mut patch = |span: Option<(Range<usize>, bool)>, url: &str| {
+        let Some((range, angle_brackets)) = span else {
+            return;
+        };
+        let converted = convert_to_zola_link_with_context(url, context, source_dir);
+        if converted == url {
+            return;
+        }
+        let needs_brackets = converted.contains([' ', '(', ')', '<', '>']);
+        let replacement = if needs_brackets && !angle_brackets {
+            format!("<{}>", converted)
+        } else {
+            converted
+        };
+        patches.push((
+            body_start + range.start..body_start + range.end,
+            replacement,
+        ));
+    };
+
+    // Links can't nest, but images can sit inside them, keep one entry per open link
+    let mut links: Vec<(Option<String>, Range<usize>, usize)> = Vec::new();
+    let mut events = Parser::new_ext(body, Options::ENABLE_TABLES | Options::ENABLE_FOOTNOTES)
+        .into_offset_iter();
+    for (event, range) in &mut events {
+        match event {
+            Event::Start(Tag::Link {
+                link_type,
+                dest_url,
+                ..
+            }) => {
+                let url = (link_type == LinkType::Inline).then(|| dest_url.into_string());
+                links.push((url, range.clone(), range.start + 1));
+            }
+            Event::End(TagEnd::Link) => {
+                if let Some((Some(url), range, text_end)) = links.pop() {
+                    let span = body[text_end..range.end].find("](").and_then(|offset| {
+                        destination_span(&body[..range.end], text_end + offset + 2)
+                    });
+                    patch(span, &url);
+                }
+            }
+            _ => {
+                if let Some((_, _, text_end)) = links.last_mut() {
+                    *text_end = (*text_end).max(range.end);
+                }
+            }
+        }
+    }
+    for (_, definition) in events.reference_definitions().iter() {
+        let span = body[definition.span.clone()].find("]:").and_then(|offset| {
+            destination_span(
+                &body[..definition.span.end],
+                definition.span.start + offset + 2,
+            )
+        });
+        patch(span, &definition.dest);
+    }
+
+    // Apply from the end so earlier offsets stay valid
+    patches.sort_by_key(|(range, _)| std::cmp::Reverse(range.start));
+    let mut rewritten = content.to_string();
+    for (range, replacement) in patches {
+        rewritten.replace_range(range, &replacement);
+    }
+    rewritten
+}
+
+/// Returns the offset right after a leading `+++` (TOML) or `---` (YAML) frontmatter block.
+fn frontmatter_end(content: &str) -> usize {
+    let mut lines = content.split_inclusive('\n');
+    let Some(delimiter) = lines.next().map(str::trim_end) else {
+        return 0;
+    };
+    if delimiter != "+++" && delimiter != "---" {
+        return 0;
+    }
+
+    let mut offset = content.find('\n').map_or(content.len(), |end| end + 1);
+    for line in lines {
+        offset += line.len();
+        if line.trim_end() == delimiter {
+            return offset;
+        }
+    }
+    // Unclosed block, this isn't frontmatter
+    0
+}
+
+/// Finds the destination of a link in the source, starting after `](` or `]:`.
+///
+/// # Returns
+///
+/// The byte range of the destination, without angle brackets, and whether it was written
+/// between angle brackets. `None` if there is no destination.
+fn destination_span(source: &str, start: usize) -> Option<(Range<usize>, bool)> {
+    let rest = &source[start..];
+    let start = start + (rest.len() - rest.trim_start().len());
+    let rest = &source[start..];
+
+    if let Some(inner) = rest.strip_prefix('<') {
+        let end = inner.find('>')?;
+        return Some((start + 1..start + 1 + end, true));
+    }
+
+    let mut depth = 0usize;
+    let mut chars = rest.char_indices();
+    let mut end = rest.len();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '(' => depth += 1,
+            ')' if depth == 0 => {
+                end = index;
+                break;
+            }
+            ')' => depth -= 1,
+            c if c.is_whitespace() => {
+                end = index;
+                break;
+            }
+            _ => {}
+        }
+    }
+    (end > 0).then(|| (start..start + end, false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn rules(patterns: &[&str]) -> PassthroughRules {
+        let patterns: Vec<String> = patterns.iter().map(|p| p.to_string()).collect();
+        PassthroughRules::new(Path::new("/vault"), &patterns).unwrap()
+    }
+
+    fn matches(rules: &PassthroughRules, path: &str) -> bool {
+        rules.matches(&PathBuf::from("/vault").join(path))
+    }
+
+    #[test]
+    fn test_nested_files() {
+        let rules = rules(&["templates/*", "*.template.md"]);
+        assert!(matches(&rules, "templates/daily.md"));
+        assert!(matches(&rules, "templates/weekly/review.md"));
+        assert!(matches(&rules, "notes/meeting.template.md"));
+        assert!(!matches(&rules, "notes/templates/daily.md"));
+        assert!(!matches(&rules, "notes/meeting.md"));
+    }
+
+    #[test]
+    fn test_directories() {
+        let rules = rules(&["drafts", "raw/"]);
+        assert!(matches(&rules, "drafts/idea.md"));
+        assert!(matches(&rules, "notes/drafts/idea.md"));
+        assert!(matches(&rules, "raw/data/export.md"));
+        assert!(!matches(&rules, "raw.md"));
+    }
+
+    #[test]
+    fn test_negation() {
+        let rules = rules(&["templates/", "!templates/keep.md", "**/*.raw.md"]);
+        assert!(matches(&rules, "templates/daily.md"));
+        assert!(!matches(&rules, "templates/keep.md"));
+        assert!(matches(&rules, "a/b/c.raw.md"));
+    }
+
+    #[test]
+    fn test_rules_file() {
+        let vault = tempfile::TempDir::new().unwrap();
+        fs::write(
+            vault.path().join(PASSTHROUGH_FILENAME),
+            "# Copied as-is\ntemplates/\n",
+        )
+        .unwrap();
+
+        let rules = PassthroughRules::new(vault.path(), &[]).unwrap();
+        assert!(!rules.is_empty());
+        assert!(rules.matches(&vault.path().join("templates/daily.md")));
+
+        // Command-line rules come last and win
+        let rules =
+            PassthroughRules::new(vault.path(), &["!templates/daily.md".to_string()]).unwrap();
+        assert!(!rules.matches(&vault.path().join("templates/daily.md")));
+        assert!(rules.matches(&vault.path().join("templates/weekly.md")));
+        assert_eq!(
+            rules.rules(),
+            ["# Copied as-is", "templates/", "!templates/daily.md"]
+        );
+
+        assert!(PassthroughRules::new(&vault.path().join("none"), &[])
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_rewrite_passthrough_links() {
+        let vault = tempfile::TempDir::new().unwrap();
+        fs::create_dir_all(vault.path().join("notes")).unwrap();
+        fs::create_dir_all(vault.path().join("docs")).unwrap();
+        fs::write(vault.path().join("notes/other.md"), "").unwrap();
+        fs::write(vault.path().join("notes/My Note.md"), "").unwrap();
+        fs::write(vault.path().join("docs/guide.md"), "").unwrap();
+
+        let content = "+++\ntitle = \"Raw\"\ndescription = \"[x](other.md)\"\n+++\n\n\
+{{ callout(kind=\"note\") }}\n\n\
+See  [the *other* note](other.md#part \"Title\"), [guide](<../docs/guide.md>)\n\
+and [spaced](My%20Note.md), [site](https://example.com), ![img](other.md).\n\n\
+`[code](other.md)` [ref][] [[other]]\n\n\
+[ref]:   ../docs/guide.md  \"Guide\"\n";
+        let expected = "+++\ntitle = \"Raw\"\ndescription = \"[x](other.md)\"\n+++\n\n\
+{{ callout(kind=\"note\") }}\n\n\
+See  [the *other* note](@/notes/other.md#part \"Title\"), [guide](<@/docs/guide.md>)\n\
+and [spaced](<@/notes/My Note.md>), [site](https://example.com), ![img](other.md).\n\n\
+`[code](other.md)` [ref][] [[other]]\n\n\
+[ref]:   @/docs/guide.md  \"Guide\"\n";
+
+        let context = Context::new(
+            vault.path().join("notes/page.md"),
+            PathBuf::from("/site/content/notes/page.md"),
+        );
+        assert_eq!(
+            rewrite_passthrough_links(content, &context, vault.path()),
+            expected
+        );
+
+        // Files without links to rewrite are returned unchanged
+        let plain = "---\ntitle: x\n---\n\n* [a](https://example.com)\n";
+        assert_eq!(
+            rewrite_passthrough_links(plain, &context, vault.path()),
+            plain
+        );
+    }
+}
@@ -0,0 +1,176 @@
+//! Export plans.
+//!
+//! An export is planned before the destination is touched: the vault is exported to a staging
+//! directory, which is then compared with the destination. The plan lists the files the export
+//! creates, overwrites, copies as passthrough files or deletes, and sums up the links it
+//! rewrote to Zola links and the ones it couldn't resolve. Applying the plan moves the changed
+//! files over, a dry run prints it instead.
+
+use crate::check::Diagnostic;
+use obsidian_export::pulldown_cmark::{Event, Options, Parser, Tag};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+
+/// What an export does to a file of the destination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PlannedChange {
+    /// An exported file that isn't in the destination yet
+    Create,
+    /// An exported file whose content changed
+    Overwrite,
+    /// A passthrough file that is new or changed
+    Passthrough,
+    /// An orphaned output deleted when pruning
+    Delete,
+}
+
+impl PlannedChange {
+    fn label(self) -> &'static str {
+        match self {
+            Self::Create => "create",
+            Self::Overwrite => "overwrite",
+            Self::Passthrough => "passthrough",
+            Self::Delete => "delete",
+        }
+    }
+}
+
+/// The changes an export makes to the destination.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExportPlan {
+    /// Changed files, by destination-relative path
+    pub changes: BTreeMap<String, PlannedChange>,
+
+    /// Number of exported files identical to the ones in the destination
+    pub unchanged: usize,
+
+    /// Shortcode templates written next to the destination
+    pub stubs: Vec<PathBuf>,
+
+    /// Number of links rewritten to `@/` links, by destination-relative path of the changed
+    /// files holding them
+    pub rewritten_links: BTreeMap<String, usize>,
+
+    /// Links of the notes of the vault that point to no file of the vault, see
+    /// [`check_links`](crate::check::check_links)
+    pub broken_links: Vec<Diagnostic>,
+
+    /// Why the export would be refused unless forced, see
+    /// [`check_destination_ownership`](crate::incremental::check_destination_ownership)
+    pub refusal: Option<String>,
+}
+
+impl ExportPlan {
+    /// Returns `true` when the export leaves the destination as it is.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty() && self.stubs.is_empty()
+    }
+}
+
+impl fmt::Display for ExportPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(refusal) = &self.refusal {
+            writeln!(f, "Refusing to export without --force: {}", refusal)?;
+        }
+        for (path, change) in &self.changes {
+            write!(f, "  {:<12} {}", change.label(), path)?;
+            match self.rewritten_links.get(path) {
+                Some(&links) => writeln!(f, " ({} rewritten)", plural(links, "link"))?,
+                None => writeln!(f)?,
+            }
+        }
+        for stub in &self.stubs {
+            writeln!(f, "  {:<12} {}", "stub", stub.display())?;
+        }
+        writeln!(f, "{} unchanged", plural(self.unchanged, "file"))?;
+
+        writeln!(
+            f,
+            "{} rewritten to Zola links in {}",
+            plural(self.rewritten_links.values().sum(), "link"),
+            plural(self.rewritten_links.len(), "file")
+        )?;
+        writeln!(f, "{}", plural(self.broken_links.len(), "broken link"))?;
+        for link in &self.broken_links {
+            writeln!(f, "  {}", link)?;
+        }
+        Ok(())
+    }
+}
+
+/// Formats a count with its noun, in the plural unless there is one.
+fn plural(count: usize, noun: &str) -> String {
+    if count == 1 {
+        format!("1 {}", noun)
+    } else {
+        format!("{} {}s", count, noun)
+    }
+}
+
+/// Counts the `@/` links of an exported markdown file.
+///
+/// # Arguments
+///
+/// * `markdown` - The content of the exported file
+///
+/// # Returns
+///
+/// The number of links to other pages of the site.
+pub fn count_internal_links(markdown: &str) -> usize {
+    Parser::new_ext(markdown, Options::ENABLE_TABLES | Options::ENABLE_FOOTNOTES)
+        .filter(|event| {
+            matches!(event, Event::Start(Tag::Link { dest_url, .. }) if dest_url.starts_with("@/"))
+        })
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::check::DiagnosticKind;
+
+    #[test]
+    fn test_count_internal_links() {
+        let markdown = "[a](@/a.md) [b](@/docs/b.md#part) [site](https://example.com)\n\n\
+![img](/img.png) `[code](@/c.md)`\n";
+        assert_eq!(count_internal_links(markdown), 2);
+        assert_eq!(count_internal_links("No links"), 0);
+    }
+
+    #[test]
+    fn test_display_plan() {
+        let plan = ExportPlan {
+            changes: BTreeMap::from([
+                ("a.md".to_string(), PlannedChange::Create),
+                ("old.md".to_string(), PlannedChange::Delete),
+            ]),
+            unchanged: 3,
+            stubs: vec![PathBuf::from("/site/templates/shortcodes/figure.html")],
+            rewritten_links: BTreeMap::from([("a.md".to_string(), 2)]),
+            broken_links: vec![Diagnostic {
+                path: "a.md".to_string(),
+                line: 3,
+                kind: DiagnosticKind::BrokenLink,
+                message: "broken link 'missing'".to_string(),
+            }],
+            refusal: Some("content has files but no manifest\n\ta.md".to_string()),
+        };
+        assert_eq!(
+            plan.to_string(),
+            concat!(
+                "Refusing to export without --force: content has files but no manifest\n",
+                "\ta.md\n",
+                "  create       a.md (2 links rewritten)\n",
+                "  delete       old.md\n",
+                "  stub         /site/templates/shortcodes/figure.html\n",
+                "3 files unchanged\n",
+                "2 links rewritten to Zola links in 1 file\n",
+                "1 broken link\n",
+                "  a.md:3: broken link 'missing'\n",
+            )
+        );
+        assert!(!plan.is_empty());
+        assert!(ExportPlan::default().is_empty());
+    }
+}
@@ -1,14 +1,111 @@
 //! Postprocessors for converting Obsidian exports to Zola format.
 
-use obsidian_export::pulldown_cmark::{CowStr, Event, Tag};
-use obsidian_export::{Context, MarkdownEvents, PostprocessorResult};
+use crate::canvas::canvas_page_path;
+use crate::dataview::{inline_field_value, parse_inline_field, InlineFieldMode, Query, VaultIndex};
+use crate::embeds::{
+    code_language, find_wiki_references, read_code_embed, read_table_embed, render_media,
+    table_delimiter, ExternalEmbed, FileEmbedError, MediaKind, MediaShortcodes, TableEmbed,
+    WikiReference, DEFAULT_CODE_EMBED_MAX_BYTES, DEFAULT_TABLE_EMBED_MAX_BYTES,
+    DEFAULT_TABLE_EMBED_MAX_ROWS,
+};
+use crate::excalidraw::{find_drawing_image, is_excalidraw_drawing};
+use crate::sanitizers::{sanitize_plugin_syntax, PluginSyntax};
+use crate::utils::{
+    decode_url_path, encode_asset_url_path, encode_internal_link_path, escape_html_attribute,
+    is_canvas_file, is_excalidraw_file, is_markdown_file, quote_shortcode_string,
+    unescape_html_attribute,
+};
+use crate::vault::VaultFiles;
+use obsidian_export::pulldown_cmark::{
+    Alignment, CodeBlockKind, CowStr, Event, LinkType, Options, Parser, Tag, TagEnd,
+};
+use obsidian_export::serde_yaml::{self, Mapping};
+use obsidian_export::{Context, MarkdownEvents, PostprocessorResult, WalkOptions};
+use regex::{Captures, Regex};
+use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Options controlling how [`create_zola_link_postprocessor_with_options`] renders content.
+#[derive(Debug, Clone)]
+pub struct ZolaOptions {
+    /// Name of the shortcode used for sized images (`![[img.png|300]]`).
+    ///
+    /// The shortcode is called with `src`, `alt` and `width` arguments, plus `height` when
+    /// given, so its template can call Zola's `resize_image`. When `None`, an HTML `<img>` tag
+    /// with `width` and `height` attributes is emitted instead.
+    pub image_size_shortcode: Option<String>,
+
+    /// Name of the shortcode every embedded image is rendered through, e.g. `figure`.
+    ///
+    /// The shortcode is called with `src` and `alt` arguments, a `caption` taken from the
+    /// image title or alt text, and `width`/`height` for sized images. See
+    /// [`figure_shortcode_stub`] for a template it expects. Takes precedence over
+    /// `image_size_shortcode`.
+    pub figure_shortcode: Option<String>,
+
+    /// Shortcodes used for embedded video, audio and PDF files instead of `<video>`,
+    /// `<audio>` and `<object>` elements.
+    pub media_shortcodes: MediaShortcodes,
+
+    /// Maximum size in bytes of a source-code file embedded as a fenced code block
+    /// (`![[script.py]]`, `![[script.py#L10-L20]]`). Larger files stay links.
+    pub code_embed_max_bytes: u64,
+
+    /// Maximum size in bytes of a CSV/TSV file embedded as a table (`![[data.csv]]`).
+    /// Larger files stay download links.
+    pub table_embed_max_bytes: u64,
+
+    /// Maximum number of rows shown for an embedded table, a link to the full file follows
+    /// truncated tables.
+    pub table_embed_max_rows: usize,
+
+    /// How Dataview inline fields (`key:: value` lines) are rendered in the body. They are
+    /// copied to the `extra` frontmatter either way.
+    pub inline_fields: InlineFieldMode,
+
+    /// Plugin syntax removed from notes (Templater commands, dataviewjs blocks, ...), all of
+    /// it by default. See [`PluginSyntax`].
+    pub plugin_syntax: Vec<PluginSyntax>,
+
+    /// Rewrite relative markdown links inside passthrough files instead of copying them
+    /// byte-for-byte. See [`crate::passthrough::rewrite_passthrough_links`].
+    pub passthrough_links: bool,
+
+    /// The walk options of the export. Links to attachments the walk leaves out, e.g.
+    /// through `.export-ignore`, are reported instead of being rewritten, see
+    /// [`VaultFiles::walk`].
+    pub walk_options: WalkOptions<'static>,
+}
+
+impl Default for ZolaOptions {
+    fn default() -> Self {
+        Self {
+            image_size_shortcode: None,
+            figure_shortcode: None,
+            media_shortcodes: MediaShortcodes::default(),
+            code_embed_max_bytes: DEFAULT_CODE_EMBED_MAX_BYTES,
+            table_embed_max_bytes: DEFAULT_TABLE_EMBED_MAX_BYTES,
+            table_embed_max_rows: DEFAULT_TABLE_EMBED_MAX_ROWS,
+            inline_fields: InlineFieldMode::default(),
+            plugin_syntax: PluginSyntax::ALL.to_vec(),
+            passthrough_links: false,
+            walk_options: WalkOptions::default(),
+        }
+    }
+}
 
 /// Creates a postprocessor that converts markdown links to Zola's internal link format.
 ///
 /// This function returns a postprocessor closure that has access to the source vault directory
 /// for proper path resolution.
 ///
+/// Links and images that can't be resolved (for example because they point outside of the
+/// vault, or at an attachment left out of the export) are reported and turned into italic
+/// text, the same way obsidian-export handles unresolvable wikilinks.
+///
+/// Excalidraw drawings are skipped, see [`crate::excalidraw`].
+///
 /// # Arguments
 ///
 /// * `source_dir` - The path to the source vault directory
@@ -19,7 +116,51 @@ use std::path::{Path, PathBuf};
 pub fn create_zola_link_postprocessor(
     source_dir: PathBuf,
 ) -> impl Fn(&mut Context, &mut MarkdownEvents<'_>) -> PostprocessorResult {
+    create_zola_link_postprocessor_with_options(source_dir, ZolaOptions::default())
+}
+
+/// Creates a postprocessor like [`create_zola_link_postprocessor`], rendering content
+/// according to the given [`ZolaOptions`].
+///
+/// # Arguments
+///
+/// * `source_dir` - The path to the source vault directory
+/// * `options` - Rendering options
+///
+/// # Returns
+///
+/// A postprocessor function that can be used with obsidian-export
+pub fn create_zola_link_postprocessor_with_options(
+    source_dir: PathBuf,
+    options: ZolaOptions,
+) -> impl Fn(&mut Context, &mut MarkdownEvents<'_>) -> PostprocessorResult {
+    // Built on the first Dataview query, shared by all notes of the export
+    let vault_index = OnceLock::new();
+    // Listed on the first link to an attachment or Dataview query
+    let vault_files = OnceLock::new();
+
     move |context: &mut Context, events: &mut MarkdownEvents<'_>| {
+        // Drawings are published as images where they are embedded, never as pages
+        if is_excalidraw_drawing(context) {
+            return PostprocessorResult::StopAndSkipNote;
+        }
+
+        sanitize_plugin_syntax(events, context, &options.plugin_syntax);
+        extract_inline_fields(events, context, &source_dir, options.inline_fields);
+        render_dataview_queries(
+            events,
+            context,
+            &source_dir,
+            &options,
+            &vault_index,
+            &vault_files,
+        );
+        render_file_embeds(events, context, &source_dir, &options, &vault_files);
+
+        // One entry per open link or image, `true` when it was replaced by emphasis. Images
+        // nest inside links, so each end tag is matched with its own start tag.
+        let mut broken_links: Vec<bool> = Vec::new();
+
         for event in events.iter_mut() {
             match event {
                 Event::Start(Tag::Link {
@@ -28,14 +169,36 @@ pub fn create_zola_link_postprocessor(
                     title,
                     id,
                 }) => {
-                    let new_dest =
-                        convert_to_zola_link_with_context(dest_url.as_ref(), context, &source_dir);
-                    *event = Event::Start(Tag::Link {
-                        link_type: *link_type,
-                        dest_url: CowStr::Boxed(new_dest.into_boxed_str()),
-                        title: title.clone(),
-                        id: id.clone(),
-                    });
+                    let converted = if is_excluded_attachment(
+                        dest_url.as_ref(),
+                        context,
+                        &source_dir,
+                        &options,
+                        &vault_files,
+                    ) {
+                        None
+                    } else {
+                        try_convert_to_zola_link_with_context(
+                            dest_url.as_ref(),
+                            context,
+                            &source_dir,
+                        )
+                    };
+                    match converted {
+                        Some(new_dest) => {
+                            *event = Event::Start(Tag::Link {
+                                link_type: *link_type,
+                                dest_url: CowStr::Boxed(new_dest.into_boxed_str()),
+                                title: title.clone(),
+                                id: id.clone(),
+                            });
+                            broken_links.push(false);
+                        }
+                        None => {
+                            *event = Event::Start(Tag::Emphasis);
+                            broken_links.push(true);
+                        }
+                    }
                 }
                 Event::Start(Tag::Image {
                     link_type,
@@ -43,207 +206,2476 @@ pub fn create_zola_link_postprocessor(
                     title,
                     id,
                 }) => {
-                    let new_dest =
-                        convert_to_zola_image_with_context(dest_url.as_ref(), context, &source_dir);
-                    *event = Event::Start(Tag::Image {
-                        link_type: *link_type,
-                        dest_url: CowStr::Boxed(new_dest.into_boxed_str()),
-                        title: title.clone(),
-                        id: id.clone(),
-                    });
+                    let converted = if is_excluded_attachment(
+                        dest_url.as_ref(),
+                        context,
+                        &source_dir,
+                        &options,
+                        &vault_files,
+                    ) {
+                        None
+                    } else {
+                        try_convert_to_zola_image_with_context(
+                            dest_url.as_ref(),
+                            context,
+                            &source_dir,
+                        )
+                    };
+                    match converted {
+                        Some(new_dest) => {
+                            *event = Event::Start(Tag::Image {
+                                link_type: *link_type,
+                                dest_url: CowStr::Boxed(new_dest.into_boxed_str()),
+                                title: title.clone(),
+                                id: id.clone(),
+                            });
+                            broken_links.push(false);
+                        }
+                        None => {
+                            *event = Event::Start(Tag::Emphasis);
+                            broken_links.push(true);
+                        }
+                    }
+                }
+                // The guard pops the entry of every end tag
+                Event::End(TagEnd::Link | TagEnd::Image) if broken_links.pop() == Some(true) => {
+                    *event = Event::End(TagEnd::Emphasis);
+                }
+                Event::Html(html) => {
+                    if let Some(new_html) = rewrite_html_links(html, context, &source_dir) {
+                        *event = Event::Html(CowStr::Boxed(new_html.into_boxed_str()));
+                    }
+                }
+                Event::InlineHtml(html) => {
+                    if let Some(new_html) = rewrite_html_links(html, context, &source_dir) {
+                        *event = Event::InlineHtml(CowStr::Boxed(new_html.into_boxed_str()));
+                    }
                 }
                 _ => {}
             }
         }
 
+        render_images(events, &options);
+
         PostprocessorResult::Continue
     }
 }
 
-/// Converts a markdown link URL to Zola's internal link format with proper path resolution.
+/// Creates an embed postprocessor rendering embedded files of embedded notes, see
+/// [`render_file_embeds`].
+///
+/// Embeds are found by reading the source of the note, so they have to be rendered before an
+/// embedded note is merged into the note embedding it. Add it after
+/// [`crate::excalidraw::create_excalidraw_embed_postprocessor`], using the same options as
+/// [`create_zola_link_postprocessor_with_options`].
 ///
 /// # Arguments
 ///
-/// * `url` - The original URL from the markdown link
-/// * `context` - The context containing information about the current file
-/// * `source_dir` - The source vault directory for proper path resolution
+/// * `source_dir` - The path to the source vault directory
+/// * `options` - Rendering options
 ///
 /// # Returns
 ///
-/// A string with the converted URL. Internal .md links are properly resolved relative
-/// to the current file's location and converted to `@/` format.
-pub fn convert_to_zola_link_with_context(
-    url: &str,
+/// An embed postprocessor function that can be used with obsidian-export
+pub fn create_file_embed_postprocessor(
+    source_dir: PathBuf,
+    options: ZolaOptions,
+) -> impl Fn(&mut Context, &mut MarkdownEvents<'_>) -> PostprocessorResult {
+    let vault_files = OnceLock::new();
+
+    move |context: &mut Context, events: &mut MarkdownEvents<'_>| {
+        if !is_excalidraw_drawing(context) {
+            render_file_embeds(events, context, &source_dir, &options, &vault_files);
+        }
+        PostprocessorResult::Continue
+    }
+}
+
+/// File types that are rendered in place of an embed.
+enum EmbedKind {
+    Media(MediaKind),
+    Code(&'static str),
+    Table(char),
+}
+
+/// Renders embedded files: video, audio and PDF files as HTML5 elements or shortcode calls,
+/// and source-code files as fenced code blocks.
+///
+/// Markdown images pointing at such files (`![](talk.mp4)`) are always embeds. Wikilink
+/// embeds arrive as plain links, so links are matched in order against the note's wikilink
+/// references to find out which ones were written as `![[...]]`. Embedded notes are
+/// handled by [`create_file_embed_postprocessor`] before they are merged into the note
+/// embedding them, the links left over are converted so they aren't matched again.
+///
+/// External images pointing at known video providers become Zola's built-in `youtube`,
+/// `vimeo` and `streamable` shortcodes, other known providers become plain links.
+///
+/// This runs before link conversion, embeds that can't be rendered are left for it.
+fn render_file_embeds(
+    events: &mut MarkdownEvents<'_>,
     context: &Context,
     source_dir: &Path,
-) -> String {
-    // Don't process external URLs (http/https/ftp/mailto etc.)
-    if url.contains("://") || url.starts_with("mailto:") {
-        return url.to_string();
-    }
+    options: &ZolaOptions,
+    vault_files: &OnceLock<VaultFiles>,
+) {
+    // Read lazily, most notes don't link to embeddable files at all
+    let mut references: Option<Vec<WikiReference>> = None;
+    let mut split_paragraphs = false;
 
-    // Check if this is a markdown file (with or without fragment)
-    let (path_part, fragment) = if let Some(fragment_pos) = url.find('#') {
-        (&url[..fragment_pos], Some(&url[fragment_pos..]))
-    } else {
-        (url, None)
-    };
+    let mut i = 0;
+    while i < events.len() {
+        let (dest_url, is_image) = match &events[i] {
+            Event::Start(Tag::Image { dest_url, .. }) => (dest_url.to_string(), true),
+            Event::Start(Tag::Link { dest_url, .. }) => (dest_url.to_string(), false),
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+
+        if is_image {
+            if let Some(embed) = ExternalEmbed::from_url(&dest_url) {
+                i = render_external_embed(events, i, &embed);
+                continue;
+            }
+        }
 
-    let is_markdown = path_part.ends_with(".md");
+        let (path_part, fragment) = split_fragment(&dest_url);
+        let kind = if let Some(kind) = MediaKind::from_path(path_part) {
+            EmbedKind::Media(kind)
+        } else if let Some(language) = code_language(path_part) {
+            EmbedKind::Code(language)
+        } else if let Some(delimiter) = table_delimiter(path_part) {
+            EmbedKind::Table(delimiter)
+        } else {
+            i += 1;
+            continue;
+        };
 
-    if is_markdown {
-        // Get the current file's path
-        let current_file_path = context.current_file();
+        let Some(end) = events[i..]
+            .iter()
+            .position(|event| matches!(event, Event::End(TagEnd::Image | TagEnd::Link)))
+            .map(|offset| i + offset)
+        else {
+            break;
+        };
 
-        // Strip the source directory from the current file path to get vault-relative path
-        let relative_current_file = current_file_path
-            .strip_prefix(source_dir)
-            .unwrap_or(current_file_path);
+        // Missing and excluded files are reported by the link conversion
+        let Some(attachment) =
+            resolve_attachment_path(&dest_url, context, source_dir).filter(|attachment| {
+                published_files(vault_files, source_dir, options).is_published(attachment)
+            })
+        else {
+            i = end + 1;
+            continue;
+        };
 
-        let current_dir = relative_current_file
-            .parent()
-            .unwrap_or_else(|| Path::new(""));
+        let section = if is_image {
+            fragment.map(|fragment| fragment[1..].to_string())
+        } else {
+            let text: String = events[i + 1..end]
+                .iter()
+                .filter_map(|event| match event {
+                    Event::Text(text) => Some(text.as_ref()),
+                    _ => None,
+                })
+                .collect();
+            let decoded_path = decode_url_path(path_part).to_lowercase();
 
-        // Resolve the relative path from the current file's directory
-        let resolved_path = resolve_relative_path(current_dir, path_part);
+            let references = references.get_or_insert_with(|| {
+                fs::read_to_string(context.current_file())
+                    .map(|source| find_wiki_references(&source))
+                    .unwrap_or_default()
+            });
+            // Each reference accounts for exactly one link
+            let position = references.iter().position(|reference| {
+                reference.display() == text
+                    && reference
+                        .file
+                        .as_ref()
+                        .is_some_and(|file| decoded_path.ends_with(&file.to_lowercase()))
+            });
+            match position.map(|position| references.remove(position)) {
+                Some(reference) if reference.is_embed => reference.section,
+                _ => {
+                    convert_attachment_link(&mut events[i], &attachment, fragment);
+                    i = end + 1;
+                    continue;
+                }
+            }
+        };
 
-        let fragment_suffix = fragment.unwrap_or("");
-        format!("@/{}{}", resolved_path, fragment_suffix)
-    } else {
-        url.to_string()
+        // Code and tables are blocks, they can't go where only inline content is allowed
+        let placement = block_placement(events, i);
+
+        match kind {
+            EmbedKind::Media(kind) => {
+                let rendered = render_media(
+                    kind,
+                    &asset_url(&attachment),
+                    section.as_deref(),
+                    &options.media_shortcodes,
+                );
+                events.splice(
+                    i..=end,
+                    [Event::InlineHtml(CowStr::Boxed(rendered.into_boxed_str()))],
+                );
+                i += 1;
+            }
+            EmbedKind::Code(_) | EmbedKind::Table(_) if placement.is_none() => {
+                eprintln!(
+                    "Warning: Unable to embed file in a heading, list item or table cell, linking \
+                     to it instead\n\tReference: '{}'\n\tSource: '{}'\n",
+                    attachment,
+                    context.current_file().display(),
+                );
+                convert_attachment_link(&mut events[i], &attachment, fragment);
+                i = end + 1;
+            }
+            EmbedKind::Code(language) => {
+                match read_code_embed(
+                    &source_dir.join(&attachment),
+                    section.as_deref(),
+                    options.code_embed_max_bytes,
+                ) {
+                    Ok(code) => {
+                        let block = code_block_events(language, code);
+                        i = splice_block(events, i, end, placement == Some(true), block);
+                        split_paragraphs = true;
+                    }
+                    Err(e) => {
+                        eprintln!(
+                            "Warning: Unable to embed code file ({})\n\tReference: '{}'\n\t\
+                             Source: '{}'\n",
+                            e,
+                            attachment,
+                            context.current_file().display(),
+                        );
+                        i = end + 1;
+                    }
+                }
+            }
+            EmbedKind::Table(delimiter) => {
+                match read_table_embed(
+                    &source_dir.join(&attachment),
+                    delimiter,
+                    options.table_embed_max_bytes,
+                    options.table_embed_max_rows,
+                ) {
+                    Ok(Some(table)) => {
+                        let block = table_events(&table, path_part, &attachment);
+                        i = splice_block(events, i, end, placement == Some(true), block);
+                        split_paragraphs = true;
+                    }
+                    // Empty and large files stay download links
+                    Ok(None) | Err(FileEmbedError::TooLarge { .. }) => i = end + 1,
+                    Err(e) => {
+                        eprintln!(
+                            "Warning: Unable to embed table ({})\n\tReference: '{}'\n\t\
+                             Source: '{}'\n",
+                            e,
+                            attachment,
+                            context.current_file().display(),
+                        );
+                        i = end + 1;
+                    }
+                }
+            }
+        }
+    }
+
+    if split_paragraphs {
+        remove_empty_paragraphs(events);
     }
 }
 
-/// Converts an image URL to Zola's internal link format with proper path resolution.
+/// Points a link at the published URL of an attachment.
 ///
-/// # Arguments
+/// Links of embedded notes are converted right away, so the note embedding them doesn't match
+/// them against its own wikilink references again.
+fn convert_attachment_link(event: &mut Event<'_>, attachment: &str, fragment: Option<&str>) {
+    if let Event::Start(Tag::Link { dest_url, .. }) = event {
+        let url = format!("{}{}", asset_url(attachment), fragment.unwrap_or(""));
+        *dest_url = CowStr::Boxed(url.into_boxed_str());
+    }
+}
+
+/// Builds the events of a fenced code block for an embedded source-code file.
 ///
-/// * `url` - The original URL from the image
-/// * `context` - The context containing information about the current file
-/// * `source_dir` - The source vault directory for proper path resolution
+/// obsidian-export fences every code block with four backticks. Code containing as many
+/// backticks in a row is written as raw markdown instead, with a fence longer than any of them.
+fn code_block_events(language: &'static str, code: String) -> Vec<Event<'static>> {
+    let longest_run = code.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    if longest_run < 4 {
+        return vec![
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(CowStr::from(
+                language,
+            )))),
+            Event::Text(CowStr::Boxed(code.into_boxed_str())),
+            Event::End(TagEnd::CodeBlock),
+        ];
+    }
+
+    let fence = "`".repeat(longest_run + 1);
+    let mut markdown = format!("{}{}\n{}", fence, language, code);
+    if !code.ends_with('\n') {
+        markdown.push('\n');
+    }
+    markdown.push_str(&fence);
+    markdown.push('\n');
+    vec![
+        Event::Start(Tag::HtmlBlock),
+        Event::Html(CowStr::Boxed(markdown.into_boxed_str())),
+        Event::End(TagEnd::HtmlBlock),
+    ]
+}
+
+/// Finds out whether block-level events can replace the inline events starting at `start`.
+/// Headings, table cells, tight list items and inline markup only hold inline content.
 ///
 /// # Returns
 ///
-/// A string with the converted URL. Internal images are properly resolved relative
-/// to the current file's location and converted to absolute path format.
-pub fn convert_to_zola_image_with_context(
-    url: &str,
-    context: &Context,
-    source_dir: &Path,
-) -> String {
-    // Don't process external URLs (http/https/ftp/mailto etc.)
-    if url.contains("://") || url.starts_with("mailto:") || url.starts_with("data:") {
-        return url.to_string();
+/// `Some(true)` inside a paragraph, `Some(false)` outside of any block, `None` when the
+/// events are inside anything else.
+fn block_placement(events: &[Event<'_>], start: usize) -> Option<bool> {
+    let mut depth = 0usize;
+    for event in events[..start].iter().rev() {
+        match event {
+            Event::End(_) => depth += 1,
+            Event::Start(Tag::Paragraph) if depth == 0 => return Some(true),
+            Event::Start(_) if depth == 0 => return None,
+            Event::Start(_) => depth -= 1,
+            _ => {}
+        }
     }
+    Some(false)
+}
 
-    // Get the current file's path
-    let current_file_path = context.current_file();
+/// Builds the table events for an embedded CSV/TSV file. A truncated table is followed by
+/// a note linking to the full file, `dest_url` is that link's destination before conversion.
+fn table_events(table: &TableEmbed, dest_url: &str, attachment: &str) -> Vec<Event<'static>> {
+    let cell = |text: &str| {
+        // Table cells can't span lines
+        let text = text.split(['\r', '\n']).collect::<Vec<_>>().join(" ");
+        [
+            Event::Start(Tag::TableCell),
+            Event::Text(CowStr::Boxed(text.into_boxed_str())),
+            Event::End(TagEnd::TableCell),
+        ]
+    };
 
-    // Strip the source directory from the current file path to get vault-relative path
-    let relative_current_file = current_file_path
-        .strip_prefix(source_dir)
-        .unwrap_or(current_file_path);
+    let mut events = vec![Event::Start(Tag::Table(vec![
+        Alignment::None;
+        table.header.len()
+    ]))];
+    events.push(Event::Start(Tag::TableHead));
+    events.extend(table.header.iter().flat_map(|text| cell(text)));
+    events.push(Event::End(TagEnd::TableHead));
+    for row in &table.rows {
+        events.push(Event::Start(Tag::TableRow));
+        events.extend(row.iter().flat_map(|text| cell(text)));
+        events.push(Event::End(TagEnd::TableRow));
+    }
+    events.push(Event::End(TagEnd::Table));
 
-    let current_dir = relative_current_file
-        .parent()
-        .unwrap_or_else(|| Path::new(""));
+    if table.is_truncated() {
+        let file_name = attachment.rsplit('/').next().unwrap_or(attachment);
+        events.extend([
+            Event::Start(Tag::Paragraph),
+            Event::Text(CowStr::Boxed(
+                format!(
+                    "Showing the first {} of {} rows. ",
+                    table.rows.len(),
+                    table.total_rows
+                )
+                .into_boxed_str(),
+            )),
+            Event::Start(Tag::Link {
+                link_type: LinkType::Inline,
+                dest_url: CowStr::Boxed(dest_url.into()),
+                title: CowStr::Borrowed(""),
+                id: CowStr::Borrowed(""),
+            }),
+            Event::Text(CowStr::Boxed(
+                format!("Download {}", file_name).into_boxed_str(),
+            )),
+            Event::End(TagEnd::Link),
+            Event::End(TagEnd::Paragraph),
+        ]);
+    }
 
-    // Resolve the relative path from the current file's directory
-    let resolved_path = resolve_relative_path(current_dir, url);
+    events
+}
 
-    // Special handling for static/ paths - convert to root-relative without the "static/" prefix
-    if resolved_path.starts_with("static/") {
-        format!("/{}", &resolved_path[7..]) // Remove "static/" and add leading "/"
+/// A Dataview inline field line (`key:: value`) of a paragraph.
+struct InlineField<'a> {
+    /// The events of the line are `start..end`, line breaks excluded
+    start: usize,
+    end: usize,
+    key: String,
+    /// The events of the value, without the `key::` prefix
+    value: Vec<Event<'a>>,
+}
+
+/// Copies Dataview inline fields (`key:: value` lines) to the `extra` frontmatter, with
+/// wikilink values converted to `@/` links, then keeps, strips or renders them as a
+/// definition list in the body depending on `mode`.
+///
+/// This runs before link conversion, links kept in the body are converted with the others.
+fn extract_inline_fields(
+    events: &mut MarkdownEvents<'_>,
+    context: &mut Context,
+    source_dir: &Path,
+    mode: InlineFieldMode,
+) {
+    let fields = find_inline_fields(events);
+    if fields.is_empty() {
+        return;
+    }
+
+    let values = fields
+        .iter()
+        .map(|field| {
+            (
+                serde_yaml::Value::from(field.key.clone()),
+                inline_field_yaml(&field.value, context, source_dir),
+            )
+        })
+        .collect::<Vec<_>>();
+    let extra = context
+        .frontmatter
+        .entry(serde_yaml::Value::from("extra"))
+        .or_insert_with(|| serde_yaml::Value::Mapping(Mapping::new()));
+    if let serde_yaml::Value::Mapping(extra) = extra {
+        for (key, value) in values {
+            // Repeated fields become lists, like in Dataview
+            match extra.get_mut(&key) {
+                Some(serde_yaml::Value::Sequence(existing)) => existing.push(value),
+                Some(existing) => {
+                    *existing = serde_yaml::Value::Sequence(vec![existing.clone(), value])
+                }
+                None => {
+                    extra.insert(key, value);
+                }
+            }
+        }
     } else {
-        // For all other images, convert to absolute path with leading slash
-        format!("/{}", resolved_path)
+        eprintln!(
+            "Warning: The 'extra' frontmatter field is not a table, inline fields are not \
+             copied to it\n\tSource: '{}'\n",
+            context.current_file().display(),
+        );
+    }
+
+    match mode {
+        InlineFieldMode::Keep => return,
+        InlineFieldMode::Strip => {
+            for field in fields.iter().rev() {
+                let (start, end) = if matches!(
+                    events.get(field.end),
+                    Some(Event::SoftBreak | Event::HardBreak)
+                ) {
+                    (field.start, field.end + 1)
+                } else {
+                    (field.start, field.end)
+                };
+                events.drain(start..end);
+            }
+        }
+        InlineFieldMode::List => {
+            // Consecutive lines become a single list
+            let mut groups: Vec<Vec<InlineField<'_>>> = Vec::new();
+            for field in fields {
+                match groups.last_mut() {
+                    Some(group) if group.last().is_some_and(|last| last.end + 1 == field.start) => {
+                        group.push(field)
+                    }
+                    _ => groups.push(vec![field]),
+                }
+            }
+
+            for group in groups.into_iter().rev() {
+                let start = group[0].start;
+                let end = group[group.len() - 1].end - 1;
+                let mut block = vec![Event::Start(Tag::DefinitionList)];
+                for field in group {
+                    block.push(Event::Start(Tag::DefinitionListTitle));
+                    block.push(Event::Text(CowStr::from(field.key)));
+                    block.push(Event::End(TagEnd::DefinitionListTitle));
+                    block.push(Event::Start(Tag::DefinitionListDefinition));
+                    block.extend(field.value);
+                    block.push(Event::End(TagEnd::DefinitionListDefinition));
+                }
+                block.push(Event::End(TagEnd::DefinitionList));
+                // Inline fields are only looked for in paragraphs
+                splice_block(events, start, end, true, block);
+            }
+        }
     }
+    remove_empty_paragraphs(events);
 }
 
-/// Resolves a relative path from a given current directory to an absolute path
-/// relative to the content root.
-///
-/// # Arguments
+/// Finds the inline field lines of the paragraphs, split at line breaks outside of other
+/// inline elements.
+fn find_inline_fields<'a>(events: &MarkdownEvents<'a>) -> Vec<InlineField<'a>> {
+    let mut fields = Vec::new();
+    let mut in_paragraph = false;
+    let mut depth = 0;
+    let mut line_start = 0;
+
+    for (i, event) in events.iter().enumerate() {
+        match event {
+            Event::Start(Tag::Paragraph) => {
+                in_paragraph = true;
+                depth = 0;
+                line_start = i + 1;
+            }
+            Event::End(TagEnd::Paragraph) => {
+                fields.extend(parse_inline_field_line(events, line_start, i));
+                in_paragraph = false;
+            }
+            Event::SoftBreak | Event::HardBreak if in_paragraph && depth == 0 => {
+                fields.extend(parse_inline_field_line(events, line_start, i));
+                line_start = i + 1;
+            }
+            Event::Start(_) if in_paragraph => depth += 1,
+            Event::End(_) if in_paragraph => depth -= 1,
+            _ => {}
+        }
+    }
+
+    fields
+}
+
+/// Parses the events `start..end` as an inline field. The key may be bold or italic.
+fn parse_inline_field_line<'a>(
+    events: &MarkdownEvents<'a>,
+    start: usize,
+    end: usize,
+) -> Option<InlineField<'a>> {
+    let mut key = String::new();
+    let mut depth = 0;
+    for i in start..end {
+        match &events[i] {
+            Event::Text(text) => {
+                let Some(split) = text.find("::") else {
+                    key.push_str(text);
+                    continue;
+                };
+                // Formatting around the key has to be closed before the value
+                if depth != 0 {
+                    return None;
+                }
+                key.push_str(&text[..split]);
+                let line = format!("{}::", key);
+                let (key, _) = parse_inline_field(&line)?;
+
+                let mut value = Vec::new();
+                let rest = text[split + 2..].trim_start();
+                if !rest.is_empty() {
+                    value.push(Event::Text(CowStr::from(rest.to_string())));
+                }
+                value.extend(events[i + 1..end].iter().cloned());
+                return Some(InlineField {
+                    start,
+                    end,
+                    key: key.to_string(),
+                    value,
+                });
+            }
+            Event::Start(Tag::Strong | Tag::Emphasis) => depth += 1,
+            Event::End(TagEnd::Strong | TagEnd::Emphasis) => depth -= 1,
+            _ => return None,
+        }
+    }
+    None
+}
+
+/// Converts the events of an inline field value to a frontmatter value. Values made of
+/// links only become their `@/` paths, a list for several links.
+fn inline_field_yaml(
+    value: &[Event<'_>],
+    context: &Context,
+    source_dir: &Path,
+) -> serde_yaml::Value {
+    let mut text = String::new();
+    let mut links = Vec::new();
+    let mut links_only = true;
+    let mut in_link = false;
+
+    for event in value {
+        match event {
+            Event::Start(Tag::Link { dest_url, .. }) => {
+                in_link = true;
+                match try_convert_to_zola_link_with_context(dest_url, context, source_dir) {
+                    Some(link) => links.push(serde_yaml::Value::from(link)),
+                    None => links_only = false,
+                }
+            }
+            Event::End(TagEnd::Link) => in_link = false,
+            Event::Text(t) | Event::Code(t) => {
+                text.push_str(t);
+                if !in_link && !t.chars().all(|c| c == ',' || c.is_whitespace()) {
+                    links_only = false;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    match links.len() {
+        0 => inline_field_value(text.trim()),
+        _ if !links_only => inline_field_value(text.trim()),
+        1 => links.remove(0),
+        _ => serde_yaml::Value::Sequence(links),
+    }
+}
+
+/// Replaces ```` ```dataview ```` code blocks with the markdown their query evaluates to.
 ///
-/// * `current_dir` - The current file's directory relative to content root
-/// * `relative_path` - The relative path to resolve
+/// Note links are generated relative to the note, like obsidian-export's own links, so this
+/// runs before link conversion. Only the notes the export converts are queried. Queries the
+/// evaluator doesn't support are reported and left as code blocks.
+fn render_dataview_queries(
+    events: &mut MarkdownEvents<'_>,
+    context: &Context,
+    source_dir: &Path,
+    options: &ZolaOptions,
+    vault_index: &OnceLock<VaultIndex>,
+    vault_files: &OnceLock<VaultFiles>,
+) {
+    let mut i = 0;
+    while i < events.len() {
+        let is_dataview = matches!(
+            &events[i],
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(language)))
+                if language.trim().eq_ignore_ascii_case("dataview")
+        );
+        let Some(end) = is_dataview
+            .then(|| {
+                events[i..]
+                    .iter()
+                    .position(|event| matches!(event, Event::End(TagEnd::CodeBlock)))
+            })
+            .flatten()
+            .map(|offset| i + offset)
+        else {
+            i += 1;
+            continue;
+        };
+
+        let source: String = events[i + 1..end]
+            .iter()
+            .filter_map(|event| match event {
+                Event::Text(text) => Some(text.as_ref()),
+                _ => None,
+            })
+            .collect();
+        let query = match Query::parse(&source) {
+            Ok(query) => query,
+            Err(e) => {
+                eprintln!(
+                    "Warning: Unsupported Dataview query ({}), left as a code block\n\tQuery: \
+                     '{}'\n\tSource: '{}'\n",
+                    e,
+                    source.trim().replace('\n', " "),
+                    context.current_file().display(),
+                );
+                i = end + 1;
+                continue;
+            }
+        };
+
+        let index = vault_index.get_or_init(|| {
+            VaultIndex::build(
+                source_dir,
+                published_files(vault_files, source_dir, options),
+            )
+        });
+        let markdown = query.render(index, current_vault_dir(context, source_dir));
+        let block: Vec<Event<'static>> = Parser::new_ext(&markdown, Options::ENABLE_TABLES)
+            .map(Event::into_static)
+            .collect();
+        let inserted = block.len();
+        events.splice(i..=end, block);
+        i += inserted;
+    }
+}
+
+/// Replaces the inline events `start..=end` with block-level events. When they are part of
+/// a paragraph, the paragraph is closed before the block and reopened after it. See
+/// [`block_placement`] for where blocks can go.
 ///
 /// # Returns
 ///
-/// An absolute path relative to the content root (without leading slash)
-fn resolve_relative_path(current_dir: &std::path::Path, relative_path: &str) -> String {
-    // Join the current directory with the relative path
-    let joined = current_dir.join(relative_path);
+/// The index of the first event after the inserted block.
+fn splice_block<'a>(
+    events: &mut MarkdownEvents<'a>,
+    start: usize,
+    end: usize,
+    in_paragraph: bool,
+    block: Vec<Event<'a>>,
+) -> usize {
+    let mut replacement = Vec::with_capacity(block.len() + 2);
+    if in_paragraph {
+        replacement.push(Event::End(TagEnd::Paragraph));
+    }
+    replacement.extend(block);
+    if in_paragraph {
+        replacement.push(Event::Start(Tag::Paragraph));
+    }
 
-    // Normalize the path by resolving . and .. components
-    let mut components = Vec::new();
+    let inserted = replacement.len();
+    events.splice(start..=end, replacement);
+    start + inserted
+}
 
-    for component in joined.components() {
-        match component {
-            std::path::Component::Normal(name) => {
-                components.push(name.to_string_lossy().to_string());
+/// Removes the empty paragraphs, dangling line breaks and trailing whitespace left behind
+/// by [`splice_block`] or by removing lines from a paragraph.
+pub(crate) fn remove_empty_paragraphs(events: &mut MarkdownEvents<'_>) {
+    let mut i = 0;
+    while i + 1 < events.len() {
+        match (&events[i], &events[i + 1]) {
+            (Event::SoftBreak | Event::HardBreak, Event::SoftBreak | Event::HardBreak) => {
+                events.remove(i + 1);
             }
-            std::path::Component::ParentDir => {
-                // Remove the last component if possible (go up one directory)
-                components.pop();
+            (Event::SoftBreak | Event::HardBreak, Event::Text(text)) if text.trim().is_empty() => {
+                events.remove(i + 1);
             }
-            std::path::Component::CurDir => {
-                // Ignore current directory references
+            (Event::Start(Tag::Paragraph), Event::SoftBreak | Event::HardBreak) => {
+                events.remove(i + 1);
             }
-            _ => {
-                // Keep other components as-is
-                components.push(component.as_os_str().to_string_lossy().to_string());
+            (Event::SoftBreak | Event::HardBreak, Event::End(TagEnd::Paragraph)) => {
+                events.remove(i);
+                i = i.saturating_sub(1);
+            }
+            (Event::Text(text), Event::End(TagEnd::Paragraph))
+                if text.ends_with(char::is_whitespace) =>
+            {
+                let trimmed = text.trim_end().to_string();
+                if trimmed.is_empty() {
+                    events.remove(i);
+                    i = i.saturating_sub(1);
+                } else {
+                    events[i] = Event::Text(CowStr::Boxed(trimmed.into_boxed_str()));
+                }
             }
+            (Event::Start(Tag::Paragraph), Event::End(TagEnd::Paragraph)) => {
+                events.drain(i..=i + 1);
+                i = i.saturating_sub(1);
+            }
+            _ => i += 1,
         }
     }
+}
+
+/// Replaces the image starting at `start` with the rendered external embed.
+///
+/// # Returns
+///
+/// The index of the first event after the replaced image.
+fn render_external_embed(
+    events: &mut MarkdownEvents<'_>,
+    start: usize,
+    embed: &ExternalEmbed,
+) -> usize {
+    let Some(end) = events[start..]
+        .iter()
+        .position(|event| matches!(event, Event::End(TagEnd::Image)))
+        .map(|offset| start + offset)
+    else {
+        return events.len();
+    };
 
-    components.join("/")
+    match embed.shortcode() {
+        Some(shortcode) => {
+            events.splice(
+                start..=end,
+                [Event::InlineHtml(CowStr::Boxed(shortcode.into_boxed_str()))],
+            );
+            start + 1
+        }
+        None => {
+            // Keep the alt text as link text, falling back to the URL itself
+            if let Event::Start(Tag::Image {
+                link_type,
+                dest_url,
+                title,
+                id,
+            }) = &events[start]
+            {
+                let url = dest_url.clone();
+                events[start] = Event::Start(Tag::Link {
+                    link_type: *link_type,
+                    dest_url: dest_url.clone(),
+                    title: title.clone(),
+                    id: id.clone(),
+                });
+                if end == start + 1 {
+                    events.insert(end, Event::Text(url));
+                    events[end + 1] = Event::End(TagEnd::Link);
+                    return end + 2;
+                }
+            }
+            events[end] = Event::End(TagEnd::Link);
+            end + 1
+        }
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Image dimensions given through Obsidian's `|300` or `|300x200` syntax.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageSize {
+    pub width: u32,
+    pub height: Option<u32>,
+}
 
-    #[test]
-    fn test_resolve_relative_path() {
-        use std::path::Path;
+/// Splits Obsidian's image size syntax off an image's alt text.
+///
+/// `300` and `300x200` set the size on their own, `Alt|300` keeps `Alt` as the alt text.
+///
+/// # Arguments
+///
+/// * `alt` - The alt text as produced by obsidian-export
+///
+/// # Returns
+///
+/// The remaining alt text and the parsed size, if any.
+pub fn parse_image_size(alt: &str) -> (&str, Option<ImageSize>) {
+    let (alt_text, size_part) = match alt.rsplit_once('|') {
+        Some((alt_text, size_part)) => (alt_text, size_part),
+        None => ("", alt),
+    };
 
-        // Test from root directory
-        assert_eq!(
-            resolve_relative_path(Path::new(""), "example.md"),
-            "example.md"
-        );
-        assert_eq!(
-            resolve_relative_path(Path::new(""), "folder/note.md"),
-            "folder/note.md"
-        );
+    let (width, height) = match size_part.trim().split_once('x') {
+        Some((width, height)) => (width, Some(height)),
+        None => (size_part.trim(), None),
+    };
 
-        // Test from subdirectory
-        assert_eq!(
-            resolve_relative_path(Path::new("folder"), "../index.md"),
-            "index.md"
-        );
-        assert_eq!(
-            resolve_relative_path(Path::new("folder"), "note.md"),
-            "folder/note.md"
-        );
-        assert_eq!(
-            resolve_relative_path(Path::new("deep/nested"), "../../root.md"),
-            "root.md"
-        );
-        assert_eq!(
-            resolve_relative_path(Path::new("deep/nested"), "../other.md"),
-            "deep/other.md"
-        );
+    let is_number = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    if !is_number(width) || !height.is_none_or(is_number) {
+        return (alt, None);
+    }
 
-        // Test complex paths
-        assert_eq!(
-            resolve_relative_path(Path::new("a/b/c"), "../../d/file.md"),
-            "a/d/file.md"
+    match (width.parse(), height.map(str::parse).transpose()) {
+        (Ok(width), Ok(height)) => (alt_text, Some(ImageSize { width, height })),
+        _ => (alt, None),
+    }
+}
+
+/// Renders images according to the options: as a call to the figure shortcode when one is
+/// configured, otherwise images whose alt text carries a size are replaced with an HTML
+/// `<img>` tag or a call to the image size shortcode. Other images are left untouched.
+fn render_images(events: &mut MarkdownEvents<'_>, options: &ZolaOptions) {
+    let mut i = 0;
+    while i < events.len() {
+        let Event::Start(Tag::Image {
+            dest_url, title, ..
+        }) = &events[i]
+        else {
+            i += 1;
+            continue;
+        };
+
+        let Some(end) = events[i..]
+            .iter()
+            .position(|event| matches!(event, Event::End(TagEnd::Image)))
+            .map(|offset| i + offset)
+        else {
+            break;
+        };
+
+        let alt: String = events[i + 1..end]
+            .iter()
+            .filter_map(|event| match event {
+                Event::Text(text) | Event::Code(text) => Some(text.as_ref()),
+                _ => None,
+            })
+            .collect();
+
+        let (alt_text, size) = parse_image_size(&alt);
+
+        // Like obsidian-export, fall back to the file name when there is no alt text left
+        let file_name = decode_url_path(dest_url.rsplit('/').next().unwrap_or_default());
+        let alt_text = if alt_text.is_empty() {
+            file_name.clone()
+        } else {
+            alt_text.to_string()
+        };
+
+        let rendered = match (&options.figure_shortcode, size) {
+            (Some(shortcode), _) => {
+                // A bare file name (`![[img.png]]`) makes for a poor caption
+                let caption = if !title.is_empty() {
+                    title.as_ref()
+                } else if alt_text != file_name {
+                    alt_text.as_str()
+                } else {
+                    ""
+                };
+                render_figure_shortcode(shortcode, dest_url, &alt_text, caption, size)
+                    .unwrap_or_else(|| render_figure_tag(dest_url, &alt_text, caption, size))
+            }
+            (None, Some(size)) => options
+                .image_size_shortcode
+                .as_ref()
+                .and_then(|shortcode| render_image_shortcode(shortcode, dest_url, &alt_text, size))
+                .unwrap_or_else(|| render_sized_img_tag(dest_url, &alt_text, title, size)),
+            (None, None) => {
+                i = end + 1;
+                continue;
+            }
+        };
+        events.splice(
+            i..=end,
+            [Event::InlineHtml(CowStr::Boxed(rendered.into_boxed_str()))],
+        );
+        i += 1;
+    }
+}
+
+/// Renders a sized image as an HTML `<img>` tag.
+fn render_sized_img_tag(src: &str, alt: &str, title: &str, size: ImageSize) -> String {
+    let mut tag = format!(
+        r#"<img src="{}" alt="{}" width="{}""#,
+        escape_html_attribute(src),
+        escape_html_attribute(alt),
+        size.width
+    );
+    if let Some(height) = size.height {
+        tag.push_str(&format!(r#" height="{}""#, height));
+    }
+    if !title.is_empty() {
+        tag.push_str(&format!(r#" title="{}""#, escape_html_attribute(title)));
+    }
+    tag.push('>');
+    tag
+}
+
+/// Renders an image as an HTML `<figure>` like the one of [`figure_shortcode_stub`], for
+/// values a shortcode call can't quote.
+fn render_figure_tag(src: &str, alt: &str, caption: &str, size: Option<ImageSize>) -> String {
+    let mut figure = format!(
+        r#"<figure><img src="{}" alt="{}""#,
+        escape_html_attribute(src),
+        escape_html_attribute(alt)
+    );
+    if let Some(size) = size {
+        figure.push_str(&format!(r#" width="{}""#, size.width));
+        if let Some(height) = size.height {
+            figure.push_str(&format!(r#" height="{}""#, height));
+        }
+    }
+    figure.push('>');
+    if !caption.is_empty() {
+        figure.push_str(&format!(
+            "<figcaption>{}</figcaption>",
+            escape_html_attribute(caption)
+        ));
+    }
+    figure.push_str("</figure>");
+    figure
+}
+
+/// Renders a sized image as a call to a Zola shortcode.
+///
+/// # Returns
+///
+/// The shortcode call, or `None` if the source or alt text can't be quoted, see
+/// [`quote_shortcode_string`].
+fn render_image_shortcode(
+    shortcode: &str,
+    src: &str,
+    alt: &str,
+    size: ImageSize,
+) -> Option<String> {
+    let mut call = format!(
+        "{{{{ {}(src={}, alt={}, width={}",
+        shortcode,
+        quote_shortcode_string(src)?,
+        quote_shortcode_string(alt)?,
+        size.width
+    );
+    if let Some(height) = size.height {
+        call.push_str(&format!(", height={}", height));
+    }
+    call.push_str(") }}");
+    Some(call)
+}
+
+/// Renders an image as a call to the figure shortcode.
+///
+/// # Returns
+///
+/// The shortcode call, or `None` if the source, alt text or caption can't be quoted, see
+/// [`quote_shortcode_string`].
+fn render_figure_shortcode(
+    shortcode: &str,
+    src: &str,
+    alt: &str,
+    caption: &str,
+    size: Option<ImageSize>,
+) -> Option<String> {
+    let mut call = format!(
+        "{{{{ {}(src={}, alt={}",
+        shortcode,
+        quote_shortcode_string(src)?,
+        quote_shortcode_string(alt)?
+    );
+    if !caption.is_empty() {
+        call.push_str(&format!(", caption={}", quote_shortcode_string(caption)?));
+    }
+    if let Some(size) = size {
+        call.push_str(&format!(", width={}", size.width));
+        if let Some(height) = size.height {
+            call.push_str(&format!(", height={}", height));
+        }
+    }
+    call.push_str(") }}");
+    Some(call)
+}
+
+/// Returns a Zola shortcode template matching the calls emitted for
+/// [`ZolaOptions::figure_shortcode`], meant to be saved as `templates/shortcodes/<name>.html`.
+pub fn figure_shortcode_stub() -> &'static str {
+    r#"{#
+  Generated by obsidian-zola. Every embedded image is rendered through this shortcode:
+
+    {{ figure(src="/path/to/image.png", alt="Alt text", caption="Caption", width=300, height=200) }}
+
+  - src: absolute URL of the image
+  - alt: alt text, falling back to the file name
+  - caption: optional, the image title or its alt text
+  - width, height: optional, from Obsidian's `|300` / `|300x200` size syntax
+#}
+<figure>
+  <img src="{{ src }}" alt="{{ alt }}"
+    {%- if width %} width="{{ width }}"{% endif %}
+    {%- if height %} height="{{ height }}"{% endif %}>
+  {% if caption %}<figcaption>{{ caption }}</figcaption>{% endif %}
+</figure>
+"#
+}
+
+/// Converts a markdown link URL to Zola's internal link format with proper path resolution.
+///
+/// # Arguments
+///
+/// * `url` - The original URL from the markdown link
+/// * `context` - The context containing information about the current file
+/// * `source_dir` - The source vault directory for proper path resolution
+///
+/// # Returns
+///
+/// A string with the converted URL. Internal markdown links (`.md`, `.markdown`) are
+/// percent-decoded, resolved relative to the current file's location and converted to
+/// `@/` format. Links to other files in the vault (PDFs, archives, audio, ...) are
+/// converted to absolute paths the same way images are. Broken links are returned unchanged,
+/// see [`try_convert_to_zola_link_with_context`].
+pub fn convert_to_zola_link_with_context(
+    url: &str,
+    context: &Context,
+    source_dir: &Path,
+) -> String {
+    try_convert_to_zola_link_with_context(url, context, source_dir)
+        .unwrap_or_else(|| url.to_string())
+}
+
+/// Converts a markdown link URL to Zola's internal link format, reporting broken links.
+///
+/// # Arguments
+///
+/// * `url` - The original URL from the markdown link
+/// * `context` - The context containing information about the current file
+/// * `source_dir` - The source vault directory for proper path resolution
+///
+/// # Returns
+///
+/// The converted URL, or `None` if the link is broken because it climbs above the vault
+/// root. A warning naming the link and its source file is printed in that case.
+pub fn try_convert_to_zola_link_with_context(
+    url: &str,
+    context: &Context,
+    source_dir: &Path,
+) -> Option<String> {
+    // Don't process external URLs (http/https/ftp/mailto etc.)
+    if url.contains("://") || url.starts_with("mailto:") {
+        return Some(url.to_string());
+    }
+
+    // Check if this is a markdown file (with or without fragment)
+    let (path_part, fragment) = split_fragment(url);
+    let fragment_suffix = fragment.unwrap_or("");
+
+    // obsidian-export percent-encodes generated links, decode before resolving against the vault
+    let decoded_path = decode_url_path(path_part);
+
+    // Root-relative links already point at a site path
+    if decoded_path.starts_with('/') {
+        return Some(url.to_string());
+    }
+
+    // Resolve the relative path from the current file's directory
+    let current_dir = current_vault_dir(context, source_dir);
+    let Some(resolved_path) = resolve_relative_path(current_dir, &decoded_path) else {
+        warn_link_outside_vault(url, context);
+        return None;
+    };
+
+    if is_excalidraw_file(&decoded_path) {
+        // Drawings aren't published, link to their exported image instead
+        match find_drawing_image(&resolved_path, source_dir) {
+            Some(image) => Some(asset_url(&image)),
+            None => {
+                eprintln!(
+                    "Warning: No image found for Excalidraw drawing, enable the plugin's SVG or \
+                     PNG auto-export\n\tDrawing: '{}'\n\tSource: '{}'\n",
+                    resolved_path,
+                    context.current_file().display(),
+                );
+                None
+            }
+        }
+    } else if is_markdown_file(&decoded_path) {
+        Some(format!(
+            "@/{}{}",
+            encode_internal_link_path(&resolved_path),
+            fragment_suffix
+        ))
+    } else if is_canvas_file(&decoded_path) && source_dir.join(&resolved_path).is_file() {
+        // Canvases are exported as pages next to the notes
+        Some(format!(
+            "@/{}{}",
+            encode_internal_link_path(&canvas_page_path(&resolved_path)),
+            fragment_suffix
+        ))
+    } else if !resolved_path.is_empty() && source_dir.join(&resolved_path).is_file() {
+        // Attachments (PDFs, archives, ...) are published like images
+        Some(format!("{}{}", asset_url(&resolved_path), fragment_suffix))
+    } else {
+        Some(url.to_string())
+    }
+}
+
+/// Converts an image URL to Zola's internal link format with proper path resolution.
+///
+/// # Arguments
+///
+/// * `url` - The original URL from the image
+/// * `context` - The context containing information about the current file
+/// * `source_dir` - The source vault directory for proper path resolution
+///
+/// # Returns
+///
+/// A string with the converted URL. Internal images are properly resolved relative
+/// to the current file's location and converted to absolute path format. Broken image
+/// paths are returned unchanged, see [`try_convert_to_zola_image_with_context`].
+pub fn convert_to_zola_image_with_context(
+    url: &str,
+    context: &Context,
+    source_dir: &Path,
+) -> String {
+    try_convert_to_zola_image_with_context(url, context, source_dir)
+        .unwrap_or_else(|| url.to_string())
+}
+
+/// Converts an image URL to an absolute site path, reporting broken image paths.
+///
+/// # Arguments
+///
+/// * `url` - The original URL from the image
+/// * `context` - The context containing information about the current file
+/// * `source_dir` - The source vault directory for proper path resolution
+///
+/// # Returns
+///
+/// The converted URL, or `None` if the path climbs above the vault root. A warning naming
+/// the image and its source file is printed in that case.
+pub fn try_convert_to_zola_image_with_context(
+    url: &str,
+    context: &Context,
+    source_dir: &Path,
+) -> Option<String> {
+    // Don't process external URLs (http/https/ftp/mailto etc.)
+    if url.contains("://") || url.starts_with("mailto:") || url.starts_with("data:") {
+        return Some(url.to_string());
+    }
+
+    // Resolve the decoded path from the current file's directory
+    let current_dir = current_vault_dir(context, source_dir);
+    match resolve_relative_path(current_dir, &decode_url_path(url)) {
+        Some(resolved_path) => Some(asset_url(&resolved_path)),
+        None => {
+            warn_link_outside_vault(url, context);
+            None
+        }
+    }
+}
+
+/// Prints a warning about a link that climbs above the vault root.
+fn warn_link_outside_vault(url: &str, context: &Context) {
+    eprintln!(
+        "Warning: Link points outside of the vault\n\tReference: '{}'\n\tSource: '{}'\n",
+        url,
+        context.current_file().display(),
+    );
+}
+
+/// Resolves a link target to a non-markdown file (attachment) inside the vault.
+///
+/// # Arguments
+///
+/// * `url` - The original URL from the link or image
+/// * `context` - The context containing information about the current file
+/// * `source_dir` - The source vault directory for proper path resolution
+///
+/// # Returns
+///
+/// The vault-relative path of the attachment, or `None` if the URL is external, points at a
+/// markdown note or canvas, or doesn't match a file in the vault.
+pub fn resolve_attachment_path(url: &str, context: &Context, source_dir: &Path) -> Option<String> {
+    if url.contains("://") || url.starts_with("mailto:") || url.starts_with("data:") {
+        return None;
+    }
+
+    let (path_part, _) = split_fragment(url);
+    let decoded_path = decode_url_path(path_part);
+    if decoded_path.is_empty()
+        || decoded_path.starts_with('/')
+        || is_markdown_file(&decoded_path)
+        || is_canvas_file(&decoded_path)
+    {
+        return None;
+    }
+
+    let resolved_path =
+        resolve_relative_path(current_vault_dir(context, source_dir), &decoded_path)?;
+    if source_dir.join(&resolved_path).is_file() {
+        Some(resolved_path)
+    } else {
+        None
+    }
+}
+
+/// Returns the files published by the export, listing them on first use.
+fn published_files<'v>(
+    vault_files: &'v OnceLock<VaultFiles>,
+    source_dir: &Path,
+    options: &ZolaOptions,
+) -> &'v VaultFiles {
+    vault_files.get_or_init(|| {
+        VaultFiles::walk(source_dir, &options.walk_options).unwrap_or_else(|e| {
+            eprintln!("Warning: Failed to list the files of the vault: {}", e);
+            VaultFiles::default()
+        })
+    })
+}
+
+/// Checks whether a link points at an attachment of the vault the export leaves out, e.g.
+/// through `.export-ignore`, reporting it if so.
+fn is_excluded_attachment(
+    url: &str,
+    context: &Context,
+    source_dir: &Path,
+    options: &ZolaOptions,
+    vault_files: &OnceLock<VaultFiles>,
+) -> bool {
+    match resolve_attachment_path(url, context, source_dir) {
+        Some(attachment)
+            if !published_files(vault_files, source_dir, options).is_published(&attachment) =>
+        {
+            eprintln!(
+                "Warning: Attachment is excluded from the export\n\tReference: '{}'\n\tSource: \
+                 '{}'\n",
+                url,
+                context.current_file().display(),
+            );
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Splits a URL into its path and `#fragment` parts.
+pub(crate) fn split_fragment(url: &str) -> (&str, Option<&str>) {
+    match url.find('#') {
+        Some(fragment_pos) => (&url[..fragment_pos], Some(&url[fragment_pos..])),
+        None => (url, None),
+    }
+}
+
+/// Returns the directory links are relative to, the root note's directory relative to the
+/// vault root. obsidian-export makes links of embedded notes relative to the root note too.
+fn current_vault_dir<'c>(context: &'c Context, source_dir: &Path) -> &'c Path {
+    // Strip the source directory from the root file path to get vault-relative path
+    let current_file_path = context.root_file();
+    let relative_current_file = current_file_path
+        .strip_prefix(source_dir)
+        .unwrap_or(current_file_path);
+
+    relative_current_file
+        .parent()
+        .unwrap_or_else(|| Path::new(""))
+}
+
+/// Returns the export destination root, derived from where the current note is written to.
+pub(crate) fn output_root(context: &Context, source_dir: &Path) -> Option<PathBuf> {
+    let relative_root_file = context.root_file().strip_prefix(source_dir).ok()?;
+    context
+        .destination
+        .ancestors()
+        .nth(relative_root_file.components().count())
+        .map(Path::to_path_buf)
+}
+
+/// Converts a resolved vault-relative asset path to the absolute URL it is published at.
+fn asset_url(resolved_path: &str) -> String {
+    // Special handling for static/ paths - convert to root-relative without the "static/" prefix
+    let site_path = match resolved_path.strip_prefix("static/") {
+        Some(stripped) => stripped,
+        None => resolved_path,
+    };
+
+    // Convert to absolute path with leading slash, re-encoding it as a URL
+    format!("/{}", encode_asset_url_path(site_path))
+}
+
+/// Rewrites `href` attributes of `<a>` tags and `src` attributes of `<img>` tags found in a
+/// raw HTML fragment, using the same resolution as markdown links and images.
+///
+/// Only the attribute values are replaced; all other markup is left untouched. Fragment-only
+/// and root-relative values (`#section`, `/logo.png`) are already valid in Zola and are kept.
+/// Values are decoded before resolving them, and rewritten values are double-quoted.
+///
+/// # Returns
+///
+/// `Some` with the rewritten HTML if any attribute changed, `None` otherwise.
+fn rewrite_html_links(html: &str, context: &Context, source_dir: &Path) -> Option<String> {
+    static TAG_RE: OnceLock<Regex> = OnceLock::new();
+    static ATTR_RE: OnceLock<Regex> = OnceLock::new();
+
+    let tag_re = TAG_RE.get_or_init(|| Regex::new(r"(?i)<(a|img)\b[^>]*>").unwrap());
+    let attr_re = ATTR_RE.get_or_init(|| {
+        Regex::new(r#"(?i)(\s)(href|src)(\s*=\s*)("([^"]*)"|'([^']*)'|([^\s"'=<>`]+))"#).unwrap()
+    });
+
+    let mut changed = false;
+    let rewritten = tag_re.replace_all(html, |tag_caps: &Captures| {
+        let is_image = tag_caps[1].eq_ignore_ascii_case("img");
+        let target_attr = if is_image { "src" } else { "href" };
+
+        attr_re
+            .replace_all(&tag_caps[0], |attr_caps: &Captures| {
+                let value = attr_caps
+                    .get(5)
+                    .or_else(|| attr_caps.get(6))
+                    .or_else(|| attr_caps.get(7))
+                    .map_or("", |m| m.as_str());
+                let value = unescape_html_attribute(value);
+                let value = value.as_str();
+
+                if !attr_caps[2].eq_ignore_ascii_case(target_attr)
+                    || value.is_empty()
+                    || value.starts_with('#')
+                    || value.starts_with('/')
+                {
+                    return attr_caps[0].to_string();
+                }
+
+                let new_value = if is_image {
+                    convert_to_zola_image_with_context(value, context, source_dir)
+                } else {
+                    convert_to_zola_link_with_context(value, context, source_dir)
+                };
+                if new_value == value {
+                    return attr_caps[0].to_string();
+                }
+
+                changed = true;
+                // The new value may contain spaces or quotes, so it is always quoted
+                format!(
+                    "{}{}{}\"{}\"",
+                    &attr_caps[1],
+                    &attr_caps[2],
+                    &attr_caps[3],
+                    escape_html_attribute(&new_value)
+                )
+            })
+            .into_owned()
+    });
+
+    if changed {
+        Some(rewritten.into_owned())
+    } else {
+        None
+    }
+}
+
+/// Resolves a relative path from a given current directory to an absolute path
+/// relative to the content root.
+///
+/// # Arguments
+///
+/// * `current_dir` - The current file's directory relative to content root
+/// * `relative_path` - The relative path to resolve
+///
+/// # Returns
+///
+/// An absolute path relative to the content root (without leading slash), or `None` if the
+/// path climbs above the content root.
+pub(crate) fn resolve_relative_path(
+    current_dir: &std::path::Path,
+    relative_path: &str,
+) -> Option<String> {
+    // Join the current directory with the relative path
+    let joined = current_dir.join(relative_path);
+
+    // Normalize the path by resolving . and .. components
+    let mut components = Vec::new();
+
+    for component in joined.components() {
+        match component {
+            std::path::Component::Normal(name) => {
+                components.push(name.to_string_lossy().to_string());
+            }
+            std::path::Component::ParentDir => {
+                // Go up one directory, there is nothing above the content root
+                components.pop()?;
+            }
+            std::path::Component::CurDir => {
+                // Ignore current directory references
+            }
+            _ => {
+                // Keep other components as-is
+                components.push(component.as_os_str().to_string_lossy().to_string());
+            }
+        }
+    }
+
+    Some(components.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_relative_path() {
+        use std::path::Path;
+
+        // Test from root directory
+        assert_eq!(
+            resolve_relative_path(Path::new(""), "example.md"),
+            Some("example.md".to_string())
+        );
+        assert_eq!(
+            resolve_relative_path(Path::new(""), "folder/note.md"),
+            Some("folder/note.md".to_string())
+        );
+
+        // Test from subdirectory
+        assert_eq!(
+            resolve_relative_path(Path::new("folder"), "../index.md"),
+            Some("index.md".to_string())
+        );
+        assert_eq!(
+            resolve_relative_path(Path::new("folder"), "note.md"),
+            Some("folder/note.md".to_string())
+        );
+        assert_eq!(
+            resolve_relative_path(Path::new("deep/nested"), "../../root.md"),
+            Some("root.md".to_string())
+        );
+        assert_eq!(
+            resolve_relative_path(Path::new("deep/nested"), "../other.md"),
+            Some("deep/other.md".to_string())
+        );
+
+        // Test complex paths
+        assert_eq!(
+            resolve_relative_path(Path::new("a/b/c"), "../../d/file.md"),
+            Some("a/d/file.md".to_string())
         );
         assert_eq!(
             resolve_relative_path(Path::new("folder"), "./current.md"),
-            "folder/current.md"
+            Some("folder/current.md".to_string())
+        );
+
+        // Test paths climbing above the content root
+        assert_eq!(resolve_relative_path(Path::new(""), "../outside.md"), None);
+        assert_eq!(
+            resolve_relative_path(Path::new("folder"), "../../outside.md"),
+            None
+        );
+        assert_eq!(resolve_relative_path(Path::new(""), "a/../../b.md"), None);
+    }
+
+    fn test_context(file: &str) -> Context {
+        Context::new(
+            PathBuf::from("/vault").join(file),
+            PathBuf::from("/out").join(file),
+        )
+    }
+
+    #[test]
+    fn test_convert_link_decodes_and_resolves_special_characters() {
+        let context = test_context("docs/page.md");
+        let source_dir = Path::new("/vault");
+
+        assert_eq!(
+            convert_to_zola_link_with_context("My%20Note.md", &context, source_dir),
+            "@/docs/My Note.md"
+        );
+        assert_eq!(
+            convert_to_zola_link_with_context("../Caf%C3%A9%20Notes.md#menu", &context, source_dir),
+            "@/Café Notes.md#menu"
+        );
+        assert_eq!(
+            convert_to_zola_link_with_context("../Note%20%28draft%29.md", &context, source_dir),
+            "@/Note (draft).md"
+        );
+        assert_eq!(
+            convert_to_zola_link_with_context("../%E6%97%A5%E8%A8%98.md", &context, source_dir),
+            "@/日記.md"
+        );
+    }
+
+    #[test]
+    fn test_convert_link_supports_markdown_extensions() {
+        let context = test_context("page.md");
+        let source_dir = Path::new("/vault");
+
+        assert_eq!(
+            convert_to_zola_link_with_context("notes/long.markdown", &context, source_dir),
+            "@/notes/long.markdown"
+        );
+        assert_eq!(
+            convert_to_zola_link_with_context("README.MD", &context, source_dir),
+            "@/README.MD"
+        );
+        assert_eq!(
+            convert_to_zola_link_with_context("notes.md.backup", &context, source_dir),
+            "notes.md.backup"
+        );
+    }
+
+    #[test]
+    fn test_convert_image_reencodes_paths() {
+        let context = test_context("docs/page.md");
+        let source_dir = Path::new("/vault");
+
+        assert_eq!(
+            convert_to_zola_image_with_context("My%20Image.png", &context, source_dir),
+            "/docs/My%20Image.png"
+        );
+        assert_eq!(
+            convert_to_zola_image_with_context("../static/chart (v2).png", &context, source_dir),
+            "/chart%20%28v2%29.png"
+        );
+    }
+
+    #[test]
+    fn test_convert_link_rewrites_attachments() {
+        let vault = tempfile::TempDir::new().unwrap();
+        fs::create_dir_all(vault.path().join("files")).unwrap();
+        fs::write(vault.path().join("files/data.zip"), "zip").unwrap();
+        fs::write(vault.path().join("paper one.pdf"), "pdf").unwrap();
+
+        let context = Context::new(vault.path().join("docs/page.md"), PathBuf::from("/out"));
+
+        assert_eq!(
+            convert_to_zola_link_with_context("../files/data.zip", &context, vault.path()),
+            "/files/data.zip"
+        );
+        assert_eq!(
+            convert_to_zola_link_with_context("../paper%20one.pdf#page=3", &context, vault.path()),
+            "/paper%20one.pdf#page=3"
+        );
+        // Unknown targets and non-file schemes are left alone
+        assert_eq!(
+            convert_to_zola_link_with_context("missing.pdf", &context, vault.path()),
+            "missing.pdf"
+        );
+        assert_eq!(
+            convert_to_zola_link_with_context("tel:123", &context, vault.path()),
+            "tel:123"
+        );
+    }
+
+    #[test]
+    fn test_convert_link_points_canvases_at_pages() {
+        let vault = tempfile::TempDir::new().unwrap();
+        fs::create_dir_all(vault.path().join("boards")).unwrap();
+        fs::write(vault.path().join("boards/Road Map.canvas"), "{}").unwrap();
+
+        let context = Context::new(vault.path().join("docs/page.md"), PathBuf::from("/out"));
+
+        assert_eq!(
+            convert_to_zola_link_with_context(
+                "../boards/Road%20Map.canvas",
+                &context,
+                vault.path()
+            ),
+            "@/boards/Road Map.md"
+        );
+        assert_eq!(
+            resolve_attachment_path("../boards/Road%20Map.canvas", &context, vault.path()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_excalidraw_drawings() {
+        let vault = tempfile::TempDir::new().unwrap();
+        fs::write(vault.path().join("flow.excalidraw.md"), "# Drawing").unwrap();
+        fs::write(vault.path().join("flow.excalidraw.png"), "png").unwrap();
+        fs::write(vault.path().join("blank.excalidraw.md"), "# Drawing").unwrap();
+
+        let context = Context::new(vault.path().join("page.md"), PathBuf::from("/out/page.md"));
+        assert_eq!(
+            try_convert_to_zola_link_with_context("flow.excalidraw.md", &context, vault.path()),
+            Some("/flow.excalidraw.png".to_string())
+        );
+        assert_eq!(
+            try_convert_to_zola_link_with_context("blank.excalidraw.md", &context, vault.path()),
+            None
+        );
+
+        // The drawing itself is never exported as a page
+        let mut context = Context::new(
+            vault.path().join("flow.excalidraw.md"),
+            PathBuf::from("/out/flow.excalidraw.md"),
+        );
+        let postprocessor = create_zola_link_postprocessor(vault.path().to_path_buf());
+        assert!(matches!(
+            postprocessor(&mut context, &mut vec![]),
+            PostprocessorResult::StopAndSkipNote
+        ));
+    }
+
+    #[test]
+    fn test_postprocessor_reports_excluded_attachments() {
+        let vault = tempfile::TempDir::new().unwrap();
+        let output = tempfile::TempDir::new().unwrap();
+        fs::create_dir_all(vault.path().join("private")).unwrap();
+        fs::write(vault.path().join("private/report.pdf"), "pdf").unwrap();
+        fs::write(vault.path().join("summary.pdf"), "pdf").unwrap();
+        fs::write(vault.path().join(".export-ignore"), "private/\n").unwrap();
+
+        let mut context = Context::new(
+            vault.path().join("notes/page.md"),
+            output.path().join("notes/page.md"),
+        );
+        let link = |dest_url: &'static str| {
+            [
+                Event::Start(Tag::Link {
+                    link_type: LinkType::Inline,
+                    dest_url: CowStr::from(dest_url),
+                    title: CowStr::from(""),
+                    id: CowStr::from(""),
+                }),
+                Event::Text(CowStr::from("report")),
+                Event::End(TagEnd::Link),
+            ]
+        };
+        let mut events: Vec<Event> = link("../private/report.pdf")
+            .into_iter()
+            .chain(link("../summary.pdf"))
+            .collect();
+
+        let postprocessor = create_zola_link_postprocessor(vault.path().to_path_buf());
+        postprocessor(&mut context, &mut events);
+
+        assert_eq!(events[0], Event::Start(Tag::Emphasis));
+        assert_eq!(events[2], Event::End(TagEnd::Emphasis));
+        assert!(matches!(
+            &events[3],
+            Event::Start(Tag::Link { dest_url, .. }) if dest_url.as_ref() == "/summary.pdf"
+        ));
+        // Nothing is written next to the export
+        assert!(!output.path().join("private").exists());
+    }
+
+    #[test]
+    fn test_links_outside_vault_are_reported() {
+        let context = test_context("docs/page.md");
+        let source_dir = Path::new("/vault");
+
+        assert_eq!(
+            try_convert_to_zola_link_with_context("../../outside.md", &context, source_dir),
+            None
+        );
+        assert_eq!(
+            try_convert_to_zola_image_with_context("../../../img.png", &context, source_dir),
+            None
+        );
+        // The lenient variants leave the URL untouched instead of clamping it
+        assert_eq!(
+            convert_to_zola_link_with_context("../../outside.md", &context, source_dir),
+            "../../outside.md"
+        );
+    }
+
+    #[test]
+    fn test_postprocessor_turns_broken_links_into_emphasis() {
+        let mut context = test_context("page.md");
+        let mut events = vec![
+            Event::Start(Tag::Link {
+                link_type: LinkType::Inline,
+                dest_url: CowStr::from("../outside.md"),
+                title: CowStr::from(""),
+                id: CowStr::from(""),
+            }),
+            Event::Text(CowStr::from("outside")),
+            Event::End(TagEnd::Link),
+            Event::Start(Tag::Link {
+                link_type: LinkType::Inline,
+                dest_url: CowStr::from("inside.md"),
+                title: CowStr::from(""),
+                id: CowStr::from(""),
+            }),
+            Event::Text(CowStr::from("inside")),
+            Event::End(TagEnd::Link),
+        ];
+
+        let postprocessor = create_zola_link_postprocessor(PathBuf::from("/vault"));
+        postprocessor(&mut context, &mut events);
+
+        assert_eq!(events[0], Event::Start(Tag::Emphasis));
+        assert_eq!(events[2], Event::End(TagEnd::Emphasis));
+        assert!(matches!(
+            &events[3],
+            Event::Start(Tag::Link { dest_url, .. }) if dest_url.as_ref() == "@/inside.md"
+        ));
+        assert_eq!(events[5], Event::End(TagEnd::Link));
+    }
+
+    #[test]
+    fn test_postprocessor_broken_link_around_image() {
+        let mut context = test_context("page.md");
+        let mut events: MarkdownEvents =
+            Parser::new("[![img](ok.png)](../outside.md) after *x* ![ok.png|300](ok.png)")
+                .collect();
+
+        let postprocessor = create_zola_link_postprocessor(PathBuf::from("/vault"));
+        postprocessor(&mut context, &mut events);
+
+        assert_eq!(
+            events,
+            vec![
+                Event::Start(Tag::Paragraph),
+                Event::Start(Tag::Emphasis),
+                Event::Start(Tag::Image {
+                    link_type: LinkType::Inline,
+                    dest_url: CowStr::from("/ok.png"),
+                    title: CowStr::from(""),
+                    id: CowStr::from(""),
+                }),
+                Event::Text(CowStr::from("img")),
+                Event::End(TagEnd::Image),
+                Event::End(TagEnd::Emphasis),
+                Event::Text(CowStr::from(" after ")),
+                Event::Start(Tag::Emphasis),
+                Event::Text(CowStr::from("x")),
+                Event::End(TagEnd::Emphasis),
+                Event::Text(CowStr::from(" ")),
+                Event::InlineHtml(CowStr::from(
+                    r#"<img src="/ok.png" alt="ok.png" width="300">"#
+                )),
+                Event::End(TagEnd::Paragraph),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_image_size() {
+        assert_eq!(
+            parse_image_size("300"),
+            (
+                "",
+                Some(ImageSize {
+                    width: 300,
+                    height: None
+                })
+            )
+        );
+        assert_eq!(
+            parse_image_size("300x200"),
+            (
+                "",
+                Some(ImageSize {
+                    width: 300,
+                    height: Some(200)
+                })
+            )
+        );
+        assert_eq!(
+            parse_image_size("A chart|640"),
+            (
+                "A chart",
+                Some(ImageSize {
+                    width: 640,
+                    height: None
+                })
+            )
+        );
+        assert_eq!(parse_image_size("A chart"), ("A chart", None));
+        assert_eq!(parse_image_size("Top 10 x 5"), ("Top 10 x 5", None));
+        assert_eq!(parse_image_size("a|b"), ("a|b", None));
+        assert_eq!(parse_image_size("300x"), ("300x", None));
+    }
+
+    fn image_events(dest: &str, alt: &str) -> MarkdownEvents<'static> {
+        vec![
+            Event::Start(Tag::Paragraph),
+            Event::Start(Tag::Image {
+                link_type: LinkType::Inline,
+                dest_url: CowStr::from(dest.to_string()),
+                title: CowStr::from(""),
+                id: CowStr::from(""),
+            }),
+            Event::Text(CowStr::from(alt.to_string())),
+            Event::End(TagEnd::Image),
+            Event::End(TagEnd::Paragraph),
+        ]
+    }
+
+    #[test]
+    fn test_sized_images_as_html() {
+        let mut events = image_events("/img/chart%20one.png", "300x200");
+        render_images(&mut events, &ZolaOptions::default());
+        assert_eq!(
+            events[1],
+            Event::InlineHtml(CowStr::from(
+                r#"<img src="/img/chart%20one.png" alt="chart one.png" width="300" height="200">"#
+            ))
+        );
+        assert_eq!(events.len(), 3);
+
+        let mut events = image_events("/img.png", r#"Say "hi"|300"#);
+        render_images(&mut events, &ZolaOptions::default());
+        assert_eq!(
+            events[1],
+            Event::InlineHtml(CowStr::from(
+                r#"<img src="/img.png" alt="Say &quot;hi&quot;" width="300">"#
+            ))
+        );
+
+        // Images without a size are untouched
+        let mut events = image_events("/img.png", "Just alt");
+        render_images(&mut events, &ZolaOptions::default());
+        assert_eq!(events, image_events("/img.png", "Just alt"));
+    }
+
+    #[test]
+    fn test_sized_images_as_shortcode() {
+        let options = ZolaOptions {
+            image_size_shortcode: Some("resized".to_string()),
+            ..Default::default()
+        };
+        let mut events = image_events("/img.png", "Alt|300x200");
+        render_images(&mut events, &options);
+        assert_eq!(
+            events[1],
+            Event::InlineHtml(CowStr::from(
+                r#"{{ resized(src="/img.png", alt="Alt", width=300, height=200) }}"#
+            ))
+        );
+    }
+
+    #[test]
+    fn test_images_as_figures() {
+        let options = ZolaOptions {
+            figure_shortcode: Some("figure".to_string()),
+            ..Default::default()
+        };
+
+        let mut events = image_events("/img/chart.png", "Quarterly results");
+        render_images(&mut events, &options);
+        assert_eq!(
+            events[1],
+            Event::InlineHtml(CowStr::from(
+                r#"{{ figure(src="/img/chart.png", alt="Quarterly results", caption="Quarterly results") }}"#
+            ))
+        );
+
+        // Wikilink embeds only carry the file name, which isn't used as a caption
+        let mut events = image_events("/img/chart.png", "chart.png|300");
+        render_images(&mut events, &options);
+        assert_eq!(
+            events[1],
+            Event::InlineHtml(CowStr::from(
+                r#"{{ figure(src="/img/chart.png", alt="chart.png", width=300) }}"#
+            ))
+        );
+
+        // The title takes precedence over the alt text
+        let mut events = image_events("/img/chart.png", "Chart");
+        if let Event::Start(Tag::Image { title, .. }) = &mut events[1] {
+            *title = CowStr::from("Figure 1: \"Sales\"");
+        }
+        render_images(&mut events, &options);
+        assert_eq!(
+            events[1],
+            Event::InlineHtml(CowStr::from(
+                r#"{{ figure(src="/img/chart.png", alt="Chart", caption='Figure 1: "Sales"') }}"#
+            ))
+        );
+
+        // Captions containing every quote character fall back to HTML
+        let mut events = image_events("/img/chart.png", "Chart");
+        if let Event::Start(Tag::Image { title, .. }) = &mut events[1] {
+            *title = CowStr::from(r#"`x` "y" 'z'"#);
+        }
+        render_images(&mut events, &options);
+        assert_eq!(
+            events[1],
+            Event::InlineHtml(CowStr::from(
+                r#"<figure><img src="/img/chart.png" alt="Chart"><figcaption>`x` &quot;y&quot; 'z'</figcaption></figure>"#
+            ))
+        );
+    }
+
+    #[test]
+    fn test_postprocessor_embeds_media() {
+        let vault = tempfile::TempDir::new().unwrap();
+        fs::write(
+            vault.path().join("page.md"),
+            "Watch [[talk.mp4]] here:\n\n![[talk.mp4]]\n\n![[paper.pdf#page=3]]\n",
+        )
+        .unwrap();
+        fs::write(vault.path().join("talk.mp4"), "video").unwrap();
+        fs::write(vault.path().join("paper.pdf"), "pdf").unwrap();
+
+        let link = |dest: &str, text: &str| {
+            vec![
+                Event::Start(Tag::Link {
+                    link_type: LinkType::Inline,
+                    dest_url: CowStr::from(dest.to_string()),
+                    title: CowStr::from(""),
+                    id: CowStr::from(""),
+                }),
+                Event::Text(CowStr::from(text.to_string())),
+                Event::End(TagEnd::Link),
+            ]
+        };
+        // What obsidian-export produces for the note above
+        let mut events = [
+            link("talk.mp4", "talk.mp4"),
+            link("talk.mp4", "talk.mp4"),
+            link("paper.pdf#page-3", "paper.pdf > page=3"),
+        ]
+        .concat();
+
+        let mut context = Context::new(vault.path().join("page.md"), PathBuf::from("/out/page.md"));
+        let postprocessor = create_zola_link_postprocessor(vault.path().to_path_buf());
+        postprocessor(&mut context, &mut events);
+
+        // The plain link stays a link
+        assert!(matches!(
+            &events[0],
+            Event::Start(Tag::Link { dest_url, .. }) if dest_url.as_ref() == "/talk.mp4"
+        ));
+        assert_eq!(
+            events[3],
+            Event::InlineHtml(CowStr::from(r#"<video controls src="/talk.mp4"></video>"#))
+        );
+        assert_eq!(
+            events[4],
+            Event::InlineHtml(CowStr::from(
+                r#"<object data="/paper.pdf#page=3" type="application/pdf" width="100%" height="600"><a href="/paper.pdf">paper.pdf</a></object>"#
+            ))
+        );
+        assert_eq!(events.len(), 5);
+    }
+
+    #[test]
+    fn test_embedded_notes_embed_media() {
+        let vault = tempfile::TempDir::new().unwrap();
+        let output = tempfile::TempDir::new().unwrap();
+        fs::create_dir_all(vault.path().join("notes")).unwrap();
+        fs::write(
+            vault.path().join("page.md"),
+            "Watch [[talk.mp4]] here:\n\n![[clip]]\n",
+        )
+        .unwrap();
+        fs::write(vault.path().join("notes/clip.md"), "![[talk.mp4]]\n").unwrap();
+        fs::write(vault.path().join("talk.mp4"), "video").unwrap();
+
+        let mut exporter =
+            obsidian_export::Exporter::new(vault.path().to_path_buf(), output.path().to_path_buf());
+        let postprocessor = create_zola_link_postprocessor(vault.path().to_path_buf());
+        exporter.add_postprocessor(&postprocessor);
+        let embed_postprocessor =
+            create_file_embed_postprocessor(vault.path().to_path_buf(), ZolaOptions::default());
+        exporter.add_embed_postprocessor(&embed_postprocessor);
+        exporter.run().unwrap();
+
+        let page = fs::read_to_string(output.path().join("page.md")).unwrap();
+        assert!(page.contains("[talk.mp4](/talk.mp4)"), "{}", page);
+        assert!(
+            page.contains(r#"<video controls src="/talk.mp4"></video>"#),
+            "{}",
+            page
+        );
+    }
+
+    #[test]
+    fn test_external_video_embeds() {
+        let mut events = image_events("https://www.youtube.com/watch?v=dQw4w9WgXcQ", "Talk");
+        render_file_embeds(
+            &mut events,
+            &test_context("page.md"),
+            Path::new("/vault"),
+            &ZolaOptions::default(),
+            &OnceLock::new(),
+        );
+        assert_eq!(
+            events,
+            vec![
+                Event::Start(Tag::Paragraph),
+                Event::InlineHtml(CowStr::from(r#"{{ youtube(id="dQw4w9WgXcQ") }}"#)),
+                Event::End(TagEnd::Paragraph),
+            ]
+        );
+
+        // Unknown providers fall back to a plain link
+        let mut events = image_events("https://twitter.com/user/status/1", "");
+        events.remove(2);
+        render_file_embeds(
+            &mut events,
+            &test_context("page.md"),
+            Path::new("/vault"),
+            &ZolaOptions::default(),
+            &OnceLock::new(),
+        );
+        assert!(matches!(
+            &events[1],
+            Event::Start(Tag::Link { dest_url, .. })
+                if dest_url.as_ref() == "https://twitter.com/user/status/1"
+        ));
+        assert_eq!(
+            events[2..],
+            [
+                Event::Text(CowStr::from("https://twitter.com/user/status/1")),
+                Event::End(TagEnd::Link),
+                Event::End(TagEnd::Paragraph),
+            ]
+        );
+
+        // Regular external images stay images
+        let mut events = image_events("https://example.com/cat.png", "Cat");
+        render_file_embeds(
+            &mut events,
+            &test_context("page.md"),
+            Path::new("/vault"),
+            &ZolaOptions::default(),
+            &OnceLock::new(),
+        );
+        assert_eq!(events, image_events("https://example.com/cat.png", "Cat"));
+    }
+
+    #[test]
+    fn test_postprocessor_embeds_code_files() {
+        let vault = tempfile::TempDir::new().unwrap();
+        fs::write(
+            vault.path().join("page.md"),
+            "Setup:\n![[setup.sh#L2-L3]]\nDone.\n",
+        )
+        .unwrap();
+        fs::write(
+            vault.path().join("setup.sh"),
+            "#!/bin/sh\nset -e\nmake install\n",
+        )
+        .unwrap();
+
+        // What obsidian-export produces for the note above
+        let mut events = vec![
+            Event::Start(Tag::Paragraph),
+            Event::Text(CowStr::from("Setup:")),
+            Event::SoftBreak,
+            Event::Start(Tag::Link {
+                link_type: LinkType::Inline,
+                dest_url: CowStr::from("setup.sh#l2-l3"),
+                title: CowStr::from(""),
+                id: CowStr::from(""),
+            }),
+            Event::Text(CowStr::from("setup.sh > L2-L3")),
+            Event::End(TagEnd::Link),
+            Event::SoftBreak,
+            Event::Text(CowStr::from("Done.")),
+            Event::End(TagEnd::Paragraph),
+        ];
+
+        let mut context = Context::new(vault.path().join("page.md"), PathBuf::from("/out/page.md"));
+        let postprocessor = create_zola_link_postprocessor(vault.path().to_path_buf());
+        postprocessor(&mut context, &mut events);
+
+        assert_eq!(
+            events,
+            vec![
+                Event::Start(Tag::Paragraph),
+                Event::Text(CowStr::from("Setup:")),
+                Event::End(TagEnd::Paragraph),
+                Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(CowStr::from("bash")))),
+                Event::Text(CowStr::from("set -e\nmake install\n")),
+                Event::End(TagEnd::CodeBlock),
+                Event::Start(Tag::Paragraph),
+                Event::Text(CowStr::from("Done.")),
+                Event::End(TagEnd::Paragraph),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_code_embeds_outside_paragraphs() {
+        let vault = tempfile::TempDir::new().unwrap();
+        let output = tempfile::TempDir::new().unwrap();
+        fs::write(
+            vault.path().join("page.md"),
+            "# Setup ![[setup.sh]]\n\n- ![[setup.sh]]\n\n![[fences.py]]\n",
+        )
+        .unwrap();
+        fs::write(vault.path().join("setup.sh"), "make install\n").unwrap();
+        fs::write(vault.path().join("fences.py"), "fence = \"````\"").unwrap();
+
+        let mut exporter =
+            obsidian_export::Exporter::new(vault.path().to_path_buf(), output.path().to_path_buf());
+        let postprocessor = create_zola_link_postprocessor(vault.path().to_path_buf());
+        exporter.add_postprocessor(&postprocessor);
+        exporter.run().unwrap();
+
+        // Headings and tight list items keep a link
+        let page = fs::read_to_string(output.path().join("page.md")).unwrap();
+        assert!(!page.contains("make install"), "{}", page);
+        assert_eq!(page.matches("[setup.sh](/setup.sh)").count(), 2, "{}", page);
+        // The fence is longer than the backticks of the file
+        assert!(
+            page.contains("`````python\nfence = \"````\"\n`````\n"),
+            "{}",
+            page
+        );
+    }
+
+    #[test]
+    fn test_postprocessor_embeds_tables() {
+        let vault = tempfile::TempDir::new().unwrap();
+        fs::write(vault.path().join("page.md"), "![[data.csv]]\n").unwrap();
+        fs::write(
+            vault.path().join("data.csv"),
+            "name,score\n\"Doe, J\",3\nRoe,5\n",
+        )
+        .unwrap();
+
+        let mut events = vec![
+            Event::Start(Tag::Paragraph),
+            Event::Start(Tag::Link {
+                link_type: LinkType::Inline,
+                dest_url: CowStr::from("data.csv"),
+                title: CowStr::from(""),
+                id: CowStr::from(""),
+            }),
+            Event::Text(CowStr::from("data.csv")),
+            Event::End(TagEnd::Link),
+            Event::End(TagEnd::Paragraph),
+        ];
+
+        let mut context = Context::new(vault.path().join("page.md"), PathBuf::from("/out/page.md"));
+        let options = ZolaOptions {
+            table_embed_max_rows: 1,
+            ..Default::default()
+        };
+        let postprocessor =
+            create_zola_link_postprocessor_with_options(vault.path().to_path_buf(), options);
+        postprocessor(&mut context, &mut events);
+
+        let cell = |text: &'static str| {
+            [
+                Event::Start(Tag::TableCell),
+                Event::Text(CowStr::from(text)),
+                Event::End(TagEnd::TableCell),
+            ]
+        };
+        let expected = [
+            vec![
+                Event::Start(Tag::Table(vec![Alignment::None; 2])),
+                Event::Start(Tag::TableHead),
+            ],
+            cell("name").to_vec(),
+            cell("score").to_vec(),
+            vec![Event::End(TagEnd::TableHead), Event::Start(Tag::TableRow)],
+            cell("Doe, J").to_vec(),
+            cell("3").to_vec(),
+            vec![
+                Event::End(TagEnd::TableRow),
+                Event::End(TagEnd::Table),
+                Event::Start(Tag::Paragraph),
+                Event::Text(CowStr::from("Showing the first 1 of 2 rows. ")),
+                Event::Start(Tag::Link {
+                    link_type: LinkType::Inline,
+                    dest_url: CowStr::from("/data.csv"),
+                    title: CowStr::from(""),
+                    id: CowStr::from(""),
+                }),
+                Event::Text(CowStr::from("Download data.csv")),
+                Event::End(TagEnd::Link),
+                Event::End(TagEnd::Paragraph),
+            ],
+        ]
+        .concat();
+        assert_eq!(events, expected);
+    }
+
+    #[test]
+    fn test_postprocessor_extracts_inline_fields() {
+        let vault = tempfile::TempDir::new().unwrap();
+        fs::write(vault.path().join("Book.md"), "").unwrap();
+        fs::write(vault.path().join("page.md"), "").unwrap();
+
+        let link = || {
+            vec![
+                Event::Start(Tag::Link {
+                    link_type: LinkType::Inline,
+                    dest_url: CowStr::from("Book.md"),
+                    title: CowStr::from(""),
+                    id: CowStr::from(""),
+                }),
+                Event::Text(CowStr::from("Book")),
+                Event::End(TagEnd::Link),
+            ]
+        };
+        let input = [
+            vec![
+                Event::Start(Tag::Paragraph),
+                Event::Text(CowStr::from("rating:: 5")),
+                Event::SoftBreak,
+                Event::Start(Tag::Strong),
+                Event::Text(CowStr::from("source")),
+                Event::End(TagEnd::Strong),
+                Event::Text(CowStr::from(":: ")),
+            ],
+            link(),
+            vec![
+                Event::SoftBreak,
+                Event::Text(CowStr::from("Some text")),
+                Event::End(TagEnd::Paragraph),
+            ],
+        ]
+        .concat();
+
+        let run = |mode: InlineFieldMode| {
+            let mut context =
+                Context::new(vault.path().join("page.md"), PathBuf::from("/out/page.md"));
+            let mut events = input.clone();
+            let options = ZolaOptions {
+                inline_fields: mode,
+                ..Default::default()
+            };
+            let postprocessor =
+                create_zola_link_postprocessor_with_options(vault.path().to_path_buf(), options);
+            postprocessor(&mut context, &mut events);
+            (context.frontmatter, events)
+        };
+
+        let (frontmatter, events) = run(InlineFieldMode::Keep);
+        let extra: Mapping = serde_yaml::from_str("rating: 5\nsource: '@/Book.md'\n").unwrap();
+        assert_eq!(
+            frontmatter.get("extra"),
+            Some(&serde_yaml::Value::Mapping(extra))
+        );
+        assert_eq!(events.len(), input.len());
+
+        let (_, events) = run(InlineFieldMode::Strip);
+        assert_eq!(
+            events,
+            vec![
+                Event::Start(Tag::Paragraph),
+                Event::Text(CowStr::from("Some text")),
+                Event::End(TagEnd::Paragraph),
+            ]
+        );
+
+        let (_, events) = run(InlineFieldMode::List);
+        let converted_link = vec![
+            Event::Start(Tag::Link {
+                link_type: LinkType::Inline,
+                dest_url: CowStr::from("@/Book.md"),
+                title: CowStr::from(""),
+                id: CowStr::from(""),
+            }),
+            Event::Text(CowStr::from("Book")),
+            Event::End(TagEnd::Link),
+        ];
+        let expected = [
+            vec![
+                Event::Start(Tag::DefinitionList),
+                Event::Start(Tag::DefinitionListTitle),
+                Event::Text(CowStr::from("rating")),
+                Event::End(TagEnd::DefinitionListTitle),
+                Event::Start(Tag::DefinitionListDefinition),
+                Event::Text(CowStr::from("5")),
+                Event::End(TagEnd::DefinitionListDefinition),
+                Event::Start(Tag::DefinitionListTitle),
+                Event::Text(CowStr::from("source")),
+                Event::End(TagEnd::DefinitionListTitle),
+                Event::Start(Tag::DefinitionListDefinition),
+            ],
+            converted_link,
+            vec![
+                Event::End(TagEnd::DefinitionListDefinition),
+                Event::End(TagEnd::DefinitionList),
+                Event::Start(Tag::Paragraph),
+                Event::Text(CowStr::from("Some text")),
+                Event::End(TagEnd::Paragraph),
+            ],
+        ]
+        .concat();
+        assert_eq!(events, expected);
+    }
+
+    #[test]
+    fn test_postprocessor_renders_dataview_queries() {
+        let vault = tempfile::TempDir::new().unwrap();
+        fs::create_dir_all(vault.path().join("books")).unwrap();
+        fs::create_dir_all(vault.path().join("index")).unwrap();
+        fs::write(
+            vault.path().join("books/Dune.md"),
+            "---\ntags: [book]\nrating: 5\n---\n",
+        )
+        .unwrap();
+        fs::write(
+            vault.path().join("books/Old Book.md"),
+            "---\ntags: [book]\nrating: 2\n---\n",
+        )
+        .unwrap();
+        fs::write(vault.path().join("index/books.md"), "").unwrap();
+
+        let code_block = |query: &'static str| {
+            vec![
+                Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(CowStr::from(
+                    "dataview",
+                )))),
+                Event::Text(CowStr::from(query)),
+                Event::End(TagEnd::CodeBlock),
+            ]
+        };
+        let mut events = code_block("LIST FROM #book SORT rating DESC\n");
+        let unsupported = code_block("TASK FROM #book\n");
+        events.extend(unsupported.clone());
+
+        let mut context = Context::new(
+            vault.path().join("index/books.md"),
+            PathBuf::from("/out/index/books.md"),
+        );
+        let postprocessor = create_zola_link_postprocessor(vault.path().to_path_buf());
+        postprocessor(&mut context, &mut events);
+
+        let item = |dest: &'static str, text: &'static str| {
+            vec![
+                Event::Start(Tag::Item),
+                Event::Start(Tag::Link {
+                    link_type: LinkType::Inline,
+                    dest_url: CowStr::from(dest),
+                    title: CowStr::from(""),
+                    id: CowStr::from(""),
+                }),
+                Event::Text(CowStr::from(text)),
+                Event::End(TagEnd::Link),
+                Event::End(TagEnd::Item),
+            ]
+        };
+        let expected = [
+            vec![Event::Start(Tag::List(None))],
+            item("@/books/Dune.md", "Dune"),
+            item("@/books/Old Book.md", "Old Book"),
+            vec![Event::End(TagEnd::List(false))],
+            unsupported,
+        ]
+        .concat();
+        assert_eq!(events, expected);
+    }
+
+    #[test]
+    fn test_rewrite_html_links() {
+        let context = test_context("docs/page.md");
+        let source_dir = Path::new("/vault");
+
+        assert_eq!(
+            rewrite_html_links(
+                r#"<a class="btn" href="../folder/note.md#intro">Note</a>"#,
+                &context,
+                source_dir
+            ),
+            Some(r#"<a class="btn" href="@/folder/note.md#intro">Note</a>"#.to_string())
+        );
+        assert_eq!(
+            rewrite_html_links(
+                r#"<img src='img/x.png' alt="X" width=300>"#,
+                &context,
+                source_dir
+            ),
+            Some(r#"<img src="/docs/img/x.png" alt="X" width=300>"#.to_string())
+        );
+        assert_eq!(
+            rewrite_html_links(r#"<IMG SRC=../static/logo.png>"#, &context, source_dir),
+            Some(r#"<IMG SRC="/logo.png">"#.to_string())
+        );
+
+        // Values are decoded before resolving them, and quoted and escaped again
+        assert_eq!(
+            rewrite_html_links(r#"<a href=my%20note.md>x</a>"#, &context, source_dir),
+            Some(r#"<a href="@/docs/my note.md">x</a>"#.to_string())
+        );
+        assert_eq!(
+            rewrite_html_links(
+                r#"<a href="sub&#47;Q&amp;A.md">x</a>"#,
+                &context,
+                source_dir
+            ),
+            Some(r#"<a href="@/docs/sub/Q&amp;A.md">x</a>"#.to_string())
+        );
+    }
+
+    #[test]
+    fn test_rewrite_html_links_leaves_other_markup_alone() {
+        let context = test_context("page.md");
+        let source_dir = Path::new("/vault");
+
+        // External, fragment-only and root-relative targets are kept
+        assert_eq!(
+            rewrite_html_links(
+                r#"<a href="https://example.com">x</a>"#,
+                &context,
+                source_dir
+            ),
+            None
+        );
+        assert_eq!(
+            rewrite_html_links(r##"<a href="#top">x</a>"##, &context, source_dir),
+            None
+        );
+        assert_eq!(
+            rewrite_html_links(r#"<img src="/logo.png">"#, &context, source_dir),
+            None
+        );
+
+        // Only href on <a> and src on <img> are rewritten
+        assert_eq!(
+            rewrite_html_links(
+                r#"<img data-src="x.png" src="y.png">"#,
+                &context,
+                source_dir
+            ),
+            Some(r#"<img data-src="x.png" src="/y.png">"#.to_string())
+        );
+        assert_eq!(
+            rewrite_html_links(r#"<a src="note.md">"#, &context, source_dir),
+            None
+        );
+        assert_eq!(
+            rewrite_html_links(r#"<div href="note.md"></div>"#, &context, source_dir),
+            None
         );
+        assert_eq!(rewrite_html_links("</a>", &context, source_dir), None);
     }
 }
@@ -0,0 +1,707 @@
+//! Removal of plugin syntax that means nothing outside of Obsidian.
+//!
+//! Notes created from templates can keep Templater commands (`<% tp.date.now() %>`) or core
+//! template variables (`{{date}}`) that were never run, and blocks evaluated by plugins
+//! (```` ```dataviewjs ````, ```` ```tasks ````) would be published as their source code.
+//! These are removed from exported notes and their frontmatter, and every removal is reported
+//! with the file and line it comes from. Code spans and other code blocks are left alone, so
+//! notes can still document the syntax.
+
+use crate::dataview::frontmatter_tags;
+use crate::postprocessors::remove_empty_paragraphs;
+use obsidian_export::pulldown_cmark::{CodeBlockKind, CowStr, Event, Tag, TagEnd};
+use obsidian_export::serde_yaml::{Mapping, Value};
+use obsidian_export::{Context, MarkdownEvents, PostprocessorResult};
+use regex::{Captures, Regex};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+/// Plugin syntax that can be removed from exported notes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum PluginSyntax {
+    /// Templater commands: `<% ... %>`
+    Templater,
+    /// Core template variables: `{{date}}` and `{{time}}` are removed, `{{title}}` becomes the
+    /// note name
+    Templates,
+    /// ```` ```dataviewjs ```` blocks and inline `$=` queries
+    Dataviewjs,
+    /// ```` ```tasks ```` query blocks
+    Tasks,
+    /// Spaced-repetition cards: `<!--SR:...-->` review data, and in notes with a `#flashcards`
+    /// deck tag, `?`/`??` separator lines and the tag itself
+    SpacedRepetition,
+}
+
+impl PluginSyntax {
+    /// Every plugin syntax, the default set of sanitizers.
+    pub const ALL: [PluginSyntax; 5] = [
+        PluginSyntax::Templater,
+        PluginSyntax::Templates,
+        PluginSyntax::Dataviewjs,
+        PluginSyntax::Tasks,
+        PluginSyntax::SpacedRepetition,
+    ];
+
+    fn description(self) -> &'static str {
+        match self {
+            PluginSyntax::Templater => "Templater command",
+            PluginSyntax::Templates => "template variable",
+            PluginSyntax::Dataviewjs => "dataviewjs query",
+            PluginSyntax::Tasks => "Tasks query",
+            PluginSyntax::SpacedRepetition => "spaced-repetition marker",
+        }
+    }
+}
+
+/// Creates an embed postprocessor removing plugin syntax from embedded notes, so removals
+/// are reported against the embedded note rather than the note embedding it.
+///
+/// # Arguments
+///
+/// * `syntaxes` - The plugin syntax to remove
+///
+/// # Returns
+///
+/// An embed postprocessor function that can be used with obsidian-export
+pub fn create_plugin_syntax_embed_postprocessor(
+    syntaxes: Vec<PluginSyntax>,
+) -> impl Fn(&mut Context, &mut MarkdownEvents<'_>) -> PostprocessorResult {
+    move |context: &mut Context, events: &mut MarkdownEvents<'_>| {
+        sanitize_plugin_syntax(events, context, &syntaxes);
+        PostprocessorResult::Continue
+    }
+}
+
+/// Removes plugin syntax from the events and the frontmatter of a note, reporting each
+/// removal.
+///
+/// # Arguments
+///
+/// * `events` - The markdown events of the note
+/// * `context` - The context of the note, used for the note name and to report removals
+/// * `syntaxes` - The plugin syntax to remove
+pub fn sanitize_plugin_syntax(
+    events: &mut MarkdownEvents<'_>,
+    context: &mut Context,
+    syntaxes: &[PluginSyntax],
+) {
+    if syntaxes.is_empty() {
+        return;
+    }
+
+    let mut source = SourceLines::new(context.current_file().to_path_buf());
+    let before = events.len();
+    remove_plugin_blocks(events, syntaxes, &mut source);
+
+    let note_name = context
+        .current_file()
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut rules = Vec::new();
+    if syntaxes.contains(&PluginSyntax::Templater) {
+        rules.push(TextRule {
+            syntax: PluginSyntax::Templater,
+            regex: templater_regex(),
+            replacement: Box::new(|_| String::new()),
+        });
+    }
+    if syntaxes.contains(&PluginSyntax::Templates) {
+        rules.push(TextRule {
+            syntax: PluginSyntax::Templates,
+            regex: template_variable_regex(),
+            replacement: Box::new(move |caps| {
+                if caps[1].eq_ignore_ascii_case("title") {
+                    note_name.clone()
+                } else {
+                    String::new()
+                }
+            }),
+        });
+    }
+    if syntaxes.contains(&PluginSyntax::SpacedRepetition)
+        && is_flashcard_note(events, &context.frontmatter)
+    {
+        rules.push(TextRule {
+            syntax: PluginSyntax::SpacedRepetition,
+            regex: card_marker_regex(),
+            replacement: Box::new(|_| String::new()),
+        });
+    }
+
+    // The frontmatter comes first in the source
+    source.in_frontmatter = true;
+    source.cursor = None;
+    context
+        .frontmatter
+        .retain(|_, value| sanitize_yaml_value(value, &rules, &mut source));
+    source.in_frontmatter = false;
+
+    let mut changed = events.len() != before;
+    // Runs are rewritten in document order, so repeated text is located on the right line
+    source.cursor = None;
+    let mut shift = 0isize;
+    for (start, end) in text_runs(events) {
+        let (start, end) = (
+            start.saturating_add_signed(shift),
+            end.saturating_add_signed(shift),
+        );
+        let mut run = events[start..end].to_vec();
+        for rule in &rules {
+            run = rewrite_run(run, rule.regex, &rule.replacement, |text, replaced| {
+                changed = true;
+                source.report(rule.syntax, text, replaced);
+            });
+        }
+        shift += run.len() as isize - (end - start) as isize;
+        events.splice(start..end, run);
+    }
+
+    if changed {
+        remove_empty_paragraphs(events);
+    }
+}
+
+/// Plugin syntax found in the text of paragraphs, headings, ...
+struct TextRule {
+    syntax: PluginSyntax,
+    regex: &'static Regex,
+    replacement: Box<dyn Fn(&Captures) -> String>,
+}
+
+/// Checks whether a note holds spaced-repetition cards: it has a `#flashcards` deck tag in its
+/// text or its frontmatter `tags`. Elsewhere a `?` line is ordinary prose.
+fn is_flashcard_note(events: &MarkdownEvents<'_>, frontmatter: &Mapping) -> bool {
+    let in_text = text_runs(events)
+        .into_iter()
+        .flat_map(|(start, end)| &events[start..end])
+        .any(|event| matches!(event, Event::Text(text) if deck_tag_regex().is_match(text)));
+    in_text
+        || frontmatter_tags(frontmatter)
+            .iter()
+            .any(|tag| tag == "flashcards" || tag.starts_with("flashcards/"))
+}
+
+/// Removes plugin syntax from the strings of a frontmatter value, reporting each removal.
+///
+/// # Returns
+///
+/// `false` if a string was left empty, so its key or list item is removed rather than
+/// published empty (Zola rejects an empty `date`, for instance).
+fn sanitize_yaml_value(value: &mut Value, rules: &[TextRule], source: &mut SourceLines) -> bool {
+    match value {
+        Value::String(text) => {
+            let mut changed = false;
+            for rule in rules {
+                let sanitized = rule.regex.replace_all(text, |caps: &Captures| {
+                    let replacement = (rule.replacement)(caps);
+                    source.report(rule.syntax, &caps[0], !replacement.is_empty());
+                    replacement
+                });
+                if sanitized != text.as_str() {
+                    *text = sanitized.into_owned();
+                    changed = true;
+                }
+            }
+            !changed || !text.trim().is_empty()
+        }
+        Value::Sequence(items) => {
+            items.retain_mut(|item| sanitize_yaml_value(item, rules, source));
+            true
+        }
+        Value::Mapping(mapping) => {
+            mapping.retain(|_, value| sanitize_yaml_value(value, rules, source));
+            true
+        }
+        Value::Tagged(tagged) => sanitize_yaml_value(&mut tagged.value, rules, source),
+        _ => true,
+    }
+}
+
+/// Removes dataviewjs and Tasks blocks, inline dataviewjs queries and spaced-repetition
+/// review comments.
+fn remove_plugin_blocks(
+    events: &mut MarkdownEvents<'_>,
+    syntaxes: &[PluginSyntax],
+    source: &mut SourceLines,
+) {
+    let mut i = 0;
+    while i < events.len() {
+        match &events[i] {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) => {
+                let language = info.split_whitespace().next().unwrap_or("").to_lowercase();
+                let syntax = match language.as_str() {
+                    "dataviewjs" => Some(PluginSyntax::Dataviewjs),
+                    "tasks" => Some(PluginSyntax::Tasks),
+                    _ => None,
+                };
+                let end = events[i..]
+                    .iter()
+                    .position(|event| matches!(event, Event::End(TagEnd::CodeBlock)))
+                    .map(|offset| i + offset);
+                match (syntax, end) {
+                    (Some(syntax), Some(end)) if syntaxes.contains(&syntax) => {
+                        source.report(syntax, &format!("```{}", language), false);
+                        events.drain(i..=end);
+                    }
+                    (_, Some(end)) => i = end + 1,
+                    _ => i += 1,
+                }
+            }
+            Event::Code(code)
+                if code.starts_with("$=") && syntaxes.contains(&PluginSyntax::Dataviewjs) =>
+            {
+                source.report(PluginSyntax::Dataviewjs, &format!("`{}`", code), false);
+                events.remove(i);
+            }
+            Event::Html(html) | Event::InlineHtml(html)
+                if syntaxes.contains(&PluginSyntax::SpacedRepetition)
+                    && review_data_regex().is_match(html) =>
+            {
+                for review_data in review_data_regex().find_iter(html) {
+                    source.report(PluginSyntax::SpacedRepetition, review_data.as_str(), false);
+                }
+                let remaining = review_data_regex().replace_all(html, "").into_owned();
+                if remaining.trim().is_empty() {
+                    events.remove(i);
+                } else {
+                    events[i] = match &events[i] {
+                        Event::Html(_) => Event::Html(CowStr::from(remaining)),
+                        _ => Event::InlineHtml(CowStr::from(remaining)),
+                    };
+                    i += 1;
+                }
+            }
+            Event::End(TagEnd::HtmlBlock)
+                if i > 0 && matches!(events[i - 1], Event::Start(Tag::HtmlBlock)) =>
+            {
+                // An HTML block holding nothing but review data
+                events.drain(i - 1..=i);
+                i -= 1;
+            }
+            _ => i += 1,
+        }
+    }
+}
+
+/// Finds the runs of inline events outside of code blocks, as `start..end` ranges.
+fn text_runs(events: &MarkdownEvents<'_>) -> Vec<(usize, usize)> {
+    let mut runs = Vec::new();
+    let mut start = None;
+    let mut in_code = false;
+
+    for (i, event) in events.iter().enumerate() {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => in_code = true,
+            Event::End(TagEnd::CodeBlock) => in_code = false,
+            _ => {}
+        }
+
+        if !in_code && is_inline(event) {
+            start.get_or_insert(i);
+        } else if let Some(start) = start.take() {
+            runs.push((start, i));
+        }
+    }
+    if let Some(start) = start {
+        runs.push((start, events.len()));
+    }
+
+    runs
+}
+
+fn is_inline(event: &Event<'_>) -> bool {
+    match event {
+        Event::Text(_)
+        | Event::Code(_)
+        | Event::InlineHtml(_)
+        | Event::InlineMath(_)
+        | Event::FootnoteReference(_)
+        | Event::SoftBreak
+        | Event::HardBreak => true,
+        Event::Start(tag) => matches!(
+            tag,
+            Tag::Emphasis | Tag::Strong | Tag::Strikethrough | Tag::Link { .. } | Tag::Image { .. }
+        ),
+        Event::End(tag) => matches!(
+            tag,
+            TagEnd::Emphasis
+                | TagEnd::Strong
+                | TagEnd::Strikethrough
+                | TagEnd::Link
+                | TagEnd::Image
+        ),
+        _ => false,
+    }
+}
+
+/// Applies `regex` to the text of a run of inline events, joined with line breaks as `\n`.
+///
+/// Each match is replaced by `replacement`, removing the events it spans. Matches that would
+/// leave a link or emphasis half open are left as they are. `report` is called with the
+/// matched text and whether it was replaced rather than removed.
+fn rewrite_run<'a>(
+    run: Vec<Event<'a>>,
+    regex: &Regex,
+    replacement: &dyn Fn(&Captures) -> String,
+    mut report: impl FnMut(&str, bool),
+) -> Vec<Event<'a>> {
+    let text: String = run.iter().map(event_text).collect();
+    let matches: Vec<(usize, usize, String, String)> = regex
+        .captures_iter(&text)
+        .map(|caps| {
+            let found = caps.get(0).unwrap();
+            (
+                found.start(),
+                found.end(),
+                found.as_str().to_string(),
+                replacement(&caps),
+            )
+        })
+        .collect();
+    if matches.is_empty() {
+        return run;
+    }
+
+    // Split text events at the match boundaries, so every match covers whole events
+    let mut boundaries: Vec<usize> = matches
+        .iter()
+        .flat_map(|(start, end, ..)| [*start, *end])
+        .collect();
+    boundaries.sort_unstable();
+    let mut events = Vec::with_capacity(run.len());
+    let mut spans = Vec::with_capacity(run.len());
+    let mut offset = 0;
+    for event in run {
+        let len = event_text(&event).len();
+        match event {
+            Event::Text(text) => {
+                let mut piece_start = offset;
+                for &boundary in boundaries
+                    .iter()
+                    .filter(|&&b| offset < b && b < offset + len)
+                {
+                    if boundary > piece_start {
+                        events.push(Event::Text(CowStr::from(
+                            text[piece_start - offset..boundary - offset].to_string(),
+                        )));
+                        spans.push((piece_start, boundary));
+                        piece_start = boundary;
+                    }
+                }
+                events.push(if piece_start == offset {
+                    Event::Text(text)
+                } else {
+                    Event::Text(CowStr::from(text[piece_start - offset..].to_string()))
+                });
+                spans.push((piece_start, offset + len));
+            }
+            event => {
+                events.push(event);
+                spans.push((offset, offset + len));
+            }
+        }
+        offset += len;
+    }
+
+    for (start, end, matched, replacement) in matches.into_iter().rev() {
+        let covered: Vec<usize> = spans
+            .iter()
+            .enumerate()
+            .filter(|(_, &(s, e))| {
+                if s == e {
+                    start < s && s < end
+                } else {
+                    start <= s && e <= end
+                }
+            })
+            .map(|(i, _)| i)
+            .collect();
+        let (Some(&first), Some(&last)) = (covered.first(), covered.last()) else {
+            continue;
+        };
+        let opened = covered
+            .iter()
+            .filter(|&&i| matches!(events[i], Event::Start(_)))
+            .count();
+        let closed = covered
+            .iter()
+            .filter(|&&i| matches!(events[i], Event::End(_)))
+            .count();
+        if opened != closed || covered.len() != last - first + 1 {
+            continue;
+        }
+
+        report(&matched, !replacement.is_empty());
+        let replaced = (!replacement.is_empty()).then(|| Event::Text(CowStr::from(replacement)));
+        events.splice(first..=last, replaced);
+        spans.splice(first..=last, None);
+    }
+
+    events
+}
+
+/// The text an inline event contributes to its run.
+fn event_text<'e>(event: &'e Event<'_>) -> &'e str {
+    match event {
+        Event::Text(text) => text,
+        Event::SoftBreak | Event::HardBreak => "\n",
+        _ => "",
+    }
+}
+
+fn templater_regex() -> &'static Regex {
+    static TEMPLATER_RE: OnceLock<Regex> = OnceLock::new();
+    TEMPLATER_RE.get_or_init(|| Regex::new(r"(?s)<%.*?%>").unwrap())
+}
+
+fn template_variable_regex() -> &'static Regex {
+    static TEMPLATE_VARIABLE_RE: OnceLock<Regex> = OnceLock::new();
+    TEMPLATE_VARIABLE_RE
+        .get_or_init(|| Regex::new(r"(?i)\{\{\s*(date|time|title)(?::[^}]*)?\s*\}\}").unwrap())
+}
+
+/// `#flashcards` deck tags, with the whitespace before them.
+const DECK_TAG_PATTERN: &str = r"(?:^|[ \t]+)#flashcards(?:/[\w/-]*\w)?\b";
+
+fn deck_tag_regex() -> &'static Regex {
+    static DECK_TAG_RE: OnceLock<Regex> = OnceLock::new();
+    DECK_TAG_RE.get_or_init(|| Regex::new(DECK_TAG_PATTERN).unwrap())
+}
+
+/// Matches `?`/`??` card separator lines and `#flashcards` deck tags.
+fn card_marker_regex() -> &'static Regex {
+    static CARD_MARKER_RE: OnceLock<Regex> = OnceLock::new();
+    CARD_MARKER_RE
+        .get_or_init(|| Regex::new(&format!(r"(?m)^\?\??$|{}", DECK_TAG_PATTERN)).unwrap())
+}
+
+fn review_data_regex() -> &'static Regex {
+    static REVIEW_DATA_RE: OnceLock<Regex> = OnceLock::new();
+    REVIEW_DATA_RE.get_or_init(|| Regex::new(r"<!--SR:.*?-->").unwrap())
+}
+
+/// Locates removed text in the source of a note to report its line.
+struct SourceLines {
+    path: PathBuf,
+    /// Read on the first removal
+    content: Option<String>,
+    /// Where the previous removal was found, repeated text is looked up after it. Lookups
+    /// start at the beginning of the frontmatter or the body when `None`.
+    cursor: Option<usize>,
+    /// Whether the frontmatter is sanitized, lookups then stay inside of it instead of the body
+    in_frontmatter: bool,
+}
+
+impl SourceLines {
+    fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            content: None,
+            cursor: None,
+            in_frontmatter: false,
+        }
+    }
+
+    fn content(&mut self) -> &str {
+        self.content
+            .get_or_insert_with(|| fs::read_to_string(&self.path).unwrap_or_default())
+    }
+
+    /// Returns the offset of the body, after the frontmatter if there is one.
+    fn body_start(&mut self) -> usize {
+        let content = self.content();
+        let Some(frontmatter) = content.strip_prefix("---\n") else {
+            return 0;
+        };
+        let mut offset = content.len() - frontmatter.len();
+        for line in frontmatter.split_inclusive('\n') {
+            offset += line.len();
+            if line.trim_end() == "---" {
+                return offset;
+            }
+        }
+        0
+    }
+
+    fn line_of(&mut self, text: &str) -> Option<usize> {
+        let body_start = self.body_start();
+        let in_frontmatter = self.in_frontmatter;
+        let cursor = self.cursor;
+        let content = self.content();
+        let (start, content) = if in_frontmatter {
+            (0, &content[..body_start])
+        } else {
+            (body_start, content)
+        };
+        let cursor = cursor.unwrap_or(start);
+        let needle = text.lines().next().unwrap_or("").trim();
+        if needle.is_empty() {
+            return None;
+        }
+
+        // Text making up a whole line (like a `?` card separator) is looked up as a line first
+        let find = |from: usize| {
+            let rest = content.get(from..)?;
+            let mut offset = 0;
+            for line in rest.split_inclusive('\n') {
+                if line.trim() == needle {
+                    return Some(from + offset + line.find(needle).unwrap_or(0));
+                }
+                offset += line.len();
+            }
+            rest.find(needle).map(|found| from + found)
+        };
+        let position = find(cursor).or_else(|| find(start))?;
+        let line = content[..position].matches('\n').count() + 1;
+        self.cursor = Some(position + needle.len());
+        Some(line)
+    }
+
+    fn report(&mut self, syntax: PluginSyntax, text: &str, replaced: bool) {
+        let location = match self.line_of(text) {
+            Some(line) => format!("{}:{}", self.path.display(), line),
+            None => self.path.display().to_string(),
+        };
+        let mut lines = text.trim().lines();
+        let shown = lines.next().unwrap_or("");
+        let ellipsis = if lines.next().is_some() { " ..." } else { "" };
+        eprintln!(
+            "Warning: {} {}\n\tText: '{}{}'\n\tSource: '{}'\n",
+            if replaced { "Replaced" } else { "Removed" },
+            syntax.description(),
+            shown,
+            ellipsis,
+            location,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use obsidian_export::pulldown_cmark::Parser;
+
+    fn sanitize(markdown: &str, syntaxes: &[PluginSyntax]) -> Vec<Event<'static>> {
+        let mut events: MarkdownEvents = Parser::new(markdown).map(Event::into_static).collect();
+        let mut context = Context::new(PathBuf::from("/vault/Daily Note.md"), PathBuf::new());
+        sanitize_plugin_syntax(&mut events, &mut context, syntaxes);
+        events
+    }
+
+    fn parse(markdown: &str) -> Vec<Event<'static>> {
+        Parser::new(markdown).map(Event::into_static).collect()
+    }
+
+    fn text(events: &[Event<'_>]) -> String {
+        events.iter().map(event_text).collect()
+    }
+
+    #[test]
+    fn test_templater() {
+        let events = sanitize(
+            "Created <% tp.date.now(\"YYYY\") %> today\n<%* tR += *value* %>\nEnd `<% kept %>`",
+            &PluginSyntax::ALL,
+        );
+        assert_eq!(text(&events), "Created  today\nEnd ");
+        assert!(events.contains(&Event::Code(CowStr::from("<% kept %>"))));
+
+        let events = sanitize("<% tp.file.title %>", &[PluginSyntax::Tasks]);
+        assert_eq!(events, parse("<% tp.file.title %>"));
+    }
+
+    #[test]
+    fn test_frontmatter() {
+        let mut context = Context::new(PathBuf::from("/vault/Daily Note.md"), PathBuf::new());
+        context.frontmatter = obsidian_export::serde_yaml::from_str(
+            "created: <% tp.file.creation_date() %>\ntitle: '{{title}} log'\ntags: [daily, '<% tp.x %>']\nextra:\n  mood: '{{date}}'\n",
+        )
+        .unwrap();
+        sanitize_plugin_syntax(&mut vec![], &mut context, &PluginSyntax::ALL);
+        assert_eq!(
+            context.frontmatter,
+            obsidian_export::serde_yaml::from_str(
+                "title: Daily Note log\ntags: [daily]\nextra: {}\n"
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_template_variables() {
+        let events = sanitize(
+            "# {{title}}\n\nWritten {{date:YYYY-MM-DD}} at {{ time }}, see {{ youtube(id=\"x\") }}",
+            &PluginSyntax::ALL,
+        );
+        assert_eq!(
+            text(&events),
+            "Daily NoteWritten  at , see {{ youtube(id=\"x\") }}"
+        );
+    }
+
+    #[test]
+    fn test_plugin_blocks() {
+        let events = sanitize(
+            "Before\n\n```dataviewjs\ndv.list()\n```\n\n```tasks\nnot done\n```\n\n```js\nkept()\n```\n\nInline `$= dv.current().file.name` query",
+            &PluginSyntax::ALL,
+        );
+        assert_eq!(events[..6], parse("Before\n\n```js\nkept()\n```\n")[..]);
+        assert_eq!(text(&events[6..]), "Inline  query");
+    }
+
+    #[test]
+    fn test_spaced_repetition() {
+        let events = sanitize(
+            "Capital of France #flashcards/geo\n?\nParis <!--SR:!2024-01-05,3,250-->\n\n<!--SR:!2024-01-05,3,250-->\n",
+            &PluginSyntax::ALL,
+        );
+        assert_eq!(events, parse("Capital of France\nParis"));
+
+        // Separators are only removed from notes with cards
+        let events = sanitize("Really\n?\nYes", &PluginSyntax::ALL);
+        assert_eq!(events, parse("Really\n?\nYes"));
+
+        let mut events = parse("Question\n??\nAnswer");
+        let mut context = Context::new(PathBuf::from("/vault/Cards.md"), PathBuf::new());
+        context.frontmatter =
+            obsidian_export::serde_yaml::from_str("tags: [flashcards/geo]").unwrap();
+        sanitize_plugin_syntax(&mut events, &mut context, &PluginSyntax::ALL);
+        assert_eq!(events, parse("Question\nAnswer"));
+    }
+
+    #[test]
+    fn test_unbalanced_matches_are_kept() {
+        // Removing the command would leave the emphasis open
+        let events = sanitize("*<% a* %>", &PluginSyntax::ALL);
+        assert_eq!(events, parse("*<% a* %>"));
+    }
+
+    #[test]
+    fn test_source_lines() {
+        let vault = tempfile::TempDir::new().unwrap();
+        let note = vault.path().join("note.md");
+        let note_with_frontmatter = vault.path().join("dated.md");
+        fs::write(&note, "{{date}}\nWhat?\n?\n{{date}}\n").unwrap();
+
+        let mut source = SourceLines::new(note);
+        assert_eq!(source.line_of("{{date}}"), Some(1));
+        assert_eq!(source.line_of("?"), Some(3));
+        assert_eq!(source.line_of("{{date}}"), Some(4));
+        assert_eq!(source.line_of("missing"), None);
+        assert_eq!(source.body_start(), 0);
+
+        fs::write(
+            &note_with_frontmatter,
+            "---\ndate: {{date}}\n---\n{{date}}\n",
+        )
+        .unwrap();
+        let mut source = SourceLines::new(note_with_frontmatter);
+        source.in_frontmatter = true;
+        assert_eq!(source.line_of("{{date}}"), Some(2));
+        source.in_frontmatter = false;
+        source.cursor = None;
+        assert_eq!(source.line_of("{{date}}"), Some(4));
+    }
+}
@@ -1,7 +1,21 @@
 //! Utility functions for obsidian-zola operations.
 
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
 use eyre::Result;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+
+/// Characters escaped in asset URLs (images, attachments). This is a superset of what
+/// obsidian-export itself encodes, so already exported URLs round-trip unchanged.
+const ASSET_URL_ENCODE_SET: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'(')
+    .add(b')')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?');
 
 /// Validates that a path exists and is a directory.
 /// 
@@ -27,6 +41,64 @@ pub fn validate_directory<P: AsRef<Path>>(path: P, description: &str) -> Result<
     Ok(())
 }
 
+/// Validates that the source vault and the destination don't overlap. A destination inside
+/// the vault would be exported again as notes on the next run, and a vault inside the
+/// destination would be replaced by the export.
+/// 
+/// # Arguments
+/// 
+/// * `source` - The source vault directory
+/// * `destination` - The destination directory, which doesn't have to exist yet
+/// 
+/// # Returns
+/// 
+/// `Ok(())` if neither directory is inside the other, otherwise an error.
+pub fn validate_no_overlap<P: AsRef<Path>, Q: AsRef<Path>>(source: P, destination: Q) -> Result<()> {
+    let source = resolve_path(source.as_ref())?;
+    let destination = resolve_path(destination.as_ref())?;
+    
+    if destination.starts_with(&source) {
+        return Err(eyre::eyre!(
+            "Destination directory {} is inside the source vault {}, it would be exported again as part of the vault",
+            destination.display(),
+            source.display()
+        ));
+    }
+    
+    if source.starts_with(&destination) {
+        return Err(eyre::eyre!(
+            "Source vault {} is inside the destination directory {}, the export would replace it",
+            source.display(),
+            destination.display()
+        ));
+    }
+    
+    Ok(())
+}
+
+/// Resolves a path that may not exist yet to an absolute path without symlinks, through its
+/// deepest existing ancestor.
+fn resolve_path(path: &Path) -> Result<PathBuf> {
+    let absolute = std::path::absolute(path)?;
+    let Some((mut resolved, existing)) = absolute
+        .ancestors()
+        .find_map(|ancestor| Some((ancestor.canonicalize().ok()?, ancestor)))
+    else {
+        return Ok(absolute);
+    };
+    
+    for component in absolute.strip_prefix(existing)?.components() {
+        match component {
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            Component::Normal(name) => resolved.push(name),
+            _ => {}
+        }
+    }
+    Ok(resolved)
+}
+
 /// Checks if a file has a markdown extension (.md or .markdown).
 /// 
 /// # Arguments
@@ -46,6 +118,60 @@ pub fn is_markdown_file<P: AsRef<Path>>(path: P) -> bool {
     }
 }
 
+/// Checks if a file has an image extension that browsers can display.
+/// 
+/// # Arguments
+/// 
+/// * `path` - The path to check
+/// 
+/// # Returns
+/// 
+/// `true` if the file has an image extension, `false` otherwise.
+pub fn is_image_file<P: AsRef<Path>>(path: P) -> bool {
+    match path.as_ref().extension() {
+        Some(ext) => matches!(
+            ext.to_string_lossy().to_lowercase().as_str(),
+            "png" | "jpg" | "jpeg" | "gif" | "bmp" | "svg" | "webp" | "avif"
+        ),
+        None => false,
+    }
+}
+
+/// Checks if a file is an Obsidian Canvas (.canvas).
+/// 
+/// # Arguments
+/// 
+/// * `path` - The path to check
+/// 
+/// # Returns
+/// 
+/// `true` if the file has a canvas extension, `false` otherwise.
+pub fn is_canvas_file<P: AsRef<Path>>(path: P) -> bool {
+    match path.as_ref().extension() {
+        Some(ext) => ext.to_string_lossy().eq_ignore_ascii_case("canvas"),
+        None => false,
+    }
+}
+
+/// Checks if a file is an Excalidraw drawing by its name (.excalidraw.md or .excalidraw).
+/// 
+/// # Arguments
+/// 
+/// * `path` - The path to check
+/// 
+/// # Returns
+/// 
+/// `true` if the file name marks an Excalidraw drawing, `false` otherwise.
+pub fn is_excalidraw_file<P: AsRef<Path>>(path: P) -> bool {
+    match path.as_ref().file_name() {
+        Some(name) => {
+            let name = name.to_string_lossy().to_lowercase();
+            name.ends_with(".excalidraw.md") || name.ends_with(".excalidraw")
+        }
+        None => false,
+    }
+}
+
 /// Normalizes a path by removing redundant components and converting to forward slashes.
 /// This ensures consistent path formatting for Zola links.
 /// 
@@ -64,6 +190,143 @@ pub fn normalize_path<P: AsRef<Path>>(path: P) -> String {
         .join("/")
 }
 
+/// Decodes percent-encoded sequences (such as `%20`) in a URL path.
+///
+/// obsidian-export percent-encodes the links it generates for `[[My Note]]`, so paths have
+/// to be decoded before they can be matched against files in the vault. Invalid sequences
+/// are kept as-is.
+///
+/// # Arguments
+///
+/// * `path` - The URL path to decode
+///
+/// # Returns
+///
+/// The decoded path.
+pub fn decode_url_path(path: &str) -> String {
+    percent_decode_str(path).decode_utf8_lossy().into_owned()
+}
+
+/// Encodes a vault-relative path for use in a Zola `@/` internal link.
+///
+/// # Arguments
+///
+/// * `path` - The decoded, vault-relative path
+///
+/// # Returns
+///
+/// The path with only the characters that would break the link escaped. Spaces, parentheses
+/// and unicode are kept as-is since the markdown writer wraps such destinations in `<...>`,
+/// and Zola looks pages up by their literal file path.
+pub fn encode_internal_link_path(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for c in path.chars() {
+        if c.is_ascii_control() || matches!(c, '<' | '>' | '#' | '?') {
+            encoded.push_str(&format!("%{:02X}", c as u32));
+        } else {
+            encoded.push(c);
+        }
+    }
+    encoded
+}
+
+/// Encodes a decoded path for use as an asset URL in the generated site.
+///
+/// # Arguments
+///
+/// * `path` - The decoded path
+///
+/// # Returns
+///
+/// The percent-encoded URL path.
+pub fn encode_asset_url_path(path: &str) -> String {
+    utf8_percent_encode(path, ASSET_URL_ENCODE_SET).to_string()
+}
+
+/// Escapes a value for use inside a double-quoted HTML attribute.
+///
+/// # Arguments
+///
+/// * `value` - The raw attribute value
+///
+/// # Returns
+///
+/// The value with `&`, `"`, `<` and `>` replaced by HTML entities.
+pub fn escape_html_attribute(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Decodes the HTML entities of an attribute value, the reverse of [`escape_html_attribute`].
+///
+/// # Arguments
+///
+/// * `value` - The attribute value as written in the HTML
+///
+/// # Returns
+///
+/// The value with the named entities `&amp;`, `&quot;`, `&apos;`, `&lt;`, `&gt;` and `&nbsp;`
+/// and numeric ones like `&#39;` or `&#x27;` decoded. Other `&` are kept as written.
+pub fn unescape_html_attribute(value: &str) -> String {
+    let mut decoded = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest[1..].find(';').map(|end| &rest[1..end + 1]);
+        let character = entity.and_then(|entity| match entity {
+            "amp" => Some('&'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "nbsp" => Some('\u{a0}'),
+            _ => {
+                let number = entity.strip_prefix('#')?;
+                let code = match number.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                    None => number.parse().ok()?,
+                };
+                char::from_u32(code)
+            }
+        });
+        match (entity, character) {
+            (Some(entity), Some(character)) => {
+                decoded.push(character);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Quotes a value as a Zola shortcode string argument.
+///
+/// Shortcode strings have no escape sequences, so the value is enclosed in the first of
+/// double quotes, single quotes and backticks it doesn't contain.
+///
+/// # Arguments
+///
+/// * `value` - The raw argument value
+///
+/// # Returns
+///
+/// The quoted value, or `None` if the value contains all three quote characters.
+pub fn quote_shortcode_string(value: &str) -> Option<String> {
+    ['"', '\'', '`']
+        .into_iter()
+        .find(|quote| !value.contains(*quote))
+        .map(|quote| format!("{}{}{}", quote, value, quote))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -95,6 +358,27 @@ mod tests {
         assert!(result.unwrap_err().to_string().contains("is not a directory"));
     }
     
+    #[test]
+    fn test_validate_no_overlap() {
+        let temp_dir = TempDir::new().unwrap();
+        let vault = temp_dir.path().join("vault");
+        let site = temp_dir.path().join("site");
+        fs::create_dir_all(vault.join("notes")).unwrap();
+        fs::create_dir_all(&site).unwrap();
+        
+        assert!(validate_no_overlap(&vault, site.join("content")).is_ok());
+        assert!(validate_no_overlap(&vault, temp_dir.path().join("vault-export")).is_ok());
+        
+        let result = validate_no_overlap(&vault, vault.join("public/content"));
+        assert!(result.unwrap_err().to_string().contains("is inside the source vault"));
+        assert!(validate_no_overlap(&vault, &vault).is_err());
+        assert!(validate_no_overlap(&vault, vault.join("notes/../public")).is_err());
+        assert!(validate_no_overlap(vault.join("notes"), vault.join("notes/new/../../public")).is_ok());
+        
+        let result = validate_no_overlap(&vault, temp_dir.path());
+        assert!(result.unwrap_err().to_string().contains("is inside the destination directory"));
+    }
+    
     #[test]
     fn test_is_markdown_file() {
         assert!(is_markdown_file("test.md"));
@@ -108,6 +392,30 @@ mod tests {
         assert!(!is_markdown_file("test.md.backup"));
     }
     
+    #[test]
+    fn test_is_image_file() {
+        assert!(is_image_file("photo.JPG"));
+        assert!(is_image_file("path/to/diagram.svg"));
+        assert!(!is_image_file("notes.md"));
+        assert!(!is_image_file("image"));
+    }
+    
+    #[test]
+    fn test_is_canvas_file() {
+        assert!(is_canvas_file("board.canvas"));
+        assert!(is_canvas_file("boards/Plan.Canvas"));
+        assert!(!is_canvas_file("board.md"));
+    }
+    
+    #[test]
+    fn test_is_excalidraw_file() {
+        assert!(is_excalidraw_file("diagram.excalidraw.md"));
+        assert!(is_excalidraw_file("art/Flow.Excalidraw.md"));
+        assert!(is_excalidraw_file("legacy.excalidraw"));
+        assert!(!is_excalidraw_file("excalidraw.md"));
+        assert!(!is_excalidraw_file("diagram.excalidraw.svg"));
+    }
+    
     #[test]
     fn test_normalize_path() {
         assert_eq!(normalize_path("folder/file.md"), "folder/file.md");
@@ -117,4 +425,53 @@ mod tests {
         assert_eq!(normalize_path("folder\\file.md"), "folder\\file.md");
         assert_eq!(normalize_path("./folder/../other/file.md"), "./folder/../other/file.md");
     }
-} 
\ No newline at end of file
+    
+    #[test]
+    fn test_decode_url_path() {
+        assert_eq!(decode_url_path("My%20Note.md"), "My Note.md");
+        assert_eq!(decode_url_path("Caf%C3%A9.md"), "Café.md");
+        assert_eq!(decode_url_path("Note%20%28draft%29.md"), "Note (draft).md");
+        assert_eq!(decode_url_path("plain/path.md"), "plain/path.md");
+        assert_eq!(decode_url_path("100%.md"), "100%.md");
+    }
+    
+    #[test]
+    fn test_encode_internal_link_path() {
+        assert_eq!(encode_internal_link_path("My Note.md"), "My Note.md");
+        assert_eq!(encode_internal_link_path("Café (draft).md"), "Café (draft).md");
+        assert_eq!(encode_internal_link_path("C# tips?.md"), "C%23 tips%3F.md");
+    }
+    
+    #[test]
+    fn test_encode_asset_url_path() {
+        assert_eq!(encode_asset_url_path("My Image.png"), "My%20Image.png");
+        assert_eq!(encode_asset_url_path("chart (v2).png"), "chart%20%28v2%29.png");
+        assert_eq!(encode_asset_url_path("café.png"), "caf%C3%A9.png");
+        assert_eq!(encode_asset_url_path("/static/logo.png"), "/static/logo.png");
+    }
+    
+    #[test]
+    fn test_unescape_html_attribute() {
+        assert_eq!(unescape_html_attribute("x.md?a=1&amp;b=2"), "x.md?a=1&b=2");
+        assert_eq!(
+            unescape_html_attribute("&quot;&#39;&#x27;&lt;&gt;"),
+            "\"''<>"
+        );
+        assert_eq!(unescape_html_attribute("a&b;c & d&#xZZ;"), "a&b;c & d&#xZZ;");
+        assert_eq!(
+            unescape_html_attribute(&escape_html_attribute("a&b \"c\" <d>")),
+            "a&b \"c\" <d>"
+        );
+    }
+    
+    #[test]
+    fn test_quote_shortcode_string() {
+        assert_eq!(quote_shortcode_string("Chart").as_deref(), Some(r#""Chart""#));
+        assert_eq!(quote_shortcode_string(r#"Say "hi""#).as_deref(), Some(r#"'Say "hi"'"#));
+        assert_eq!(
+            quote_shortcode_string(r#"Say "hi", it's late"#).as_deref(),
+            Some(r#"`Say "hi", it's late`"#)
+        );
+        assert_eq!(quote_shortcode_string(r#"`a` "b" 'c'"#), None);
+    }
+}
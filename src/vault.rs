@@ -0,0 +1,237 @@
+//! The files of a vault an export publishes.
+//!
+//! obsidian-export only converts and copies the files its walk accepts: `.export-ignore`,
+//! `.gitignore` and hidden files are left out, and so is anything the walk filter rejects.
+//! The CLI installs a filter rejecting passthrough files, which it copies itself. Links are
+//! only rewritten to files that end up in the export, see [`VaultFiles`], and `check`
+//! resolves them against the same files.
+
+use crate::postprocessors::resolve_relative_path;
+use eyre::{Result, WrapErr};
+use ignore::WalkBuilder;
+use obsidian_export::{vault_contents, WalkOptions};
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// The files of a vault published by an export, as vault-relative paths.
+#[derive(Debug, Clone, Default)]
+pub struct VaultFiles {
+    /// Files obsidian-export walks over, converted notes and copied attachments
+    exported: BTreeSet<String>,
+    /// Files only the walk filter rejects, copied as passthrough files
+    copied: BTreeSet<String>,
+}
+
+impl VaultFiles {
+    /// Lists the files of a vault the way an export with the given walk options sees them.
+    ///
+    /// Files rejected by the walk filter (`filter_fn`) are counted as published, since the
+    /// CLI only uses the filter to hand passthrough files over to its own copy.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The source vault directory
+    /// * `walk_options` - The walk options of the export
+    ///
+    /// # Returns
+    ///
+    /// The files, or an error if the vault can't be read.
+    pub fn walk(source: &Path, walk_options: &WalkOptions<'_>) -> Result<Self> {
+        let exported: BTreeSet<String> = vault_contents(source, walk_options.clone())
+            .wrap_err_with(|| format!("Failed to read the vault at {}", source.display()))?
+            .iter()
+            .map(|path| relative_path(source, path))
+            .collect();
+
+        let mut copied = BTreeSet::new();
+        if let Some(filter) = walk_options.filter_fn {
+            for entry in WalkBuilder::new(source).standard_filters(false).build() {
+                let entry = entry.wrap_err("Failed to read directory entry")?;
+                let is_file = entry
+                    .file_type()
+                    .is_some_and(|file_type| file_type.is_file());
+                if is_file && !filter(&entry) {
+                    copied.insert(relative_path(source, entry.path()));
+                }
+            }
+        }
+
+        Ok(Self { exported, copied })
+    }
+
+    /// Creates the file list from vault-relative paths of exported files.
+    pub fn new<I: IntoIterator<Item = String>>(files: I) -> Self {
+        Self {
+            exported: files.into_iter().collect(),
+            copied: BTreeSet::new(),
+        }
+    }
+
+    /// Checks whether obsidian-export walks over a file, so notes are converted.
+    pub fn is_exported(&self, path: &str) -> bool {
+        self.exported.contains(path)
+    }
+
+    /// Checks whether a file ends up in the export, converted or copied.
+    pub fn is_published(&self, path: &str) -> bool {
+        self.exported.contains(path) || self.copied.contains(path)
+    }
+
+    /// Returns the vault-relative paths of the files obsidian-export walks over, sorted.
+    pub fn exported(&self) -> impl Iterator<Item = &str> {
+        self.exported.iter().map(String::as_str)
+    }
+
+    /// Returns the vault-relative paths of the files copied as passthrough files, sorted.
+    pub fn copied(&self) -> impl Iterator<Item = &str> {
+        self.copied.iter().map(String::as_str)
+    }
+
+    /// Finds the files a wikilink target points to, see [`resolve_wikilink`]. Only files
+    /// obsidian-export walks over can be linked to, passthrough files are hidden from it.
+    pub fn resolve_wikilink(&self, target: &str) -> Vec<&str> {
+        resolve_wikilink(self.exported(), target)
+    }
+
+    /// Finds the published file a markdown link destination points to.
+    ///
+    /// # Arguments
+    ///
+    /// * `note_dir` - The vault-relative directory the link is relative to
+    /// * `path` - The decoded path of the destination, without fragment
+    ///
+    /// # Returns
+    ///
+    /// The vault-relative path of the file, or `None` if it climbs above the vault root or
+    /// the export leaves the file out.
+    pub fn resolve_link(&self, note_dir: &Path, path: &str) -> Option<String> {
+        resolve_relative_path(note_dir, path).filter(|resolved| self.is_published(resolved))
+    }
+}
+
+/// Finds the files a wikilink target points to.
+///
+/// Like obsidian-export, a target matches the files whose path ends with it, with or without
+/// the `.md` extension and ignoring case. A target naming the whole path of a file only
+/// matches that file, like it does in Obsidian.
+///
+/// # Arguments
+///
+/// * `files` - Vault-relative paths of the files that can be linked to
+/// * `target` - The target of the wikilink, without heading or alias
+///
+/// # Returns
+///
+/// The matching files, in the order of `files`.
+pub fn resolve_wikilink<'f>(
+    files: impl IntoIterator<Item = &'f str>,
+    target: &str,
+) -> Vec<&'f str> {
+    let target = target.trim_start_matches('/').to_lowercase();
+    let with_extension = format!("{}.md", target);
+    let matches: Vec<&str> = files
+        .into_iter()
+        .filter(|file| {
+            let file = file.to_lowercase();
+            [&target, &with_extension]
+                .into_iter()
+                .any(|name| file == *name || file.ends_with(&format!("/{}", name)))
+        })
+        .collect();
+
+    let exact: Vec<&str> = matches
+        .iter()
+        .copied()
+        .filter(|file| {
+            let file = file.to_lowercase();
+            file == target || file == with_extension
+        })
+        .collect();
+    if exact.len() == 1 {
+        exact
+    } else {
+        matches
+    }
+}
+
+/// Returns the vault-relative path of a file with `/` separators.
+fn relative_path(source: &Path, path: &Path) -> String {
+    path.strip_prefix(source)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .replace('\\', "/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ignore::DirEntry;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn not_private(entry: &DirEntry) -> bool {
+        !entry.path().ends_with("private.pdf")
+    }
+
+    #[test]
+    fn test_walk() {
+        let vault = TempDir::new().unwrap();
+        fs::create_dir_all(vault.path().join("drafts")).unwrap();
+        fs::write(vault.path().join("note.md"), "# Note").unwrap();
+        fs::write(vault.path().join("private.pdf"), "pdf").unwrap();
+        fs::write(vault.path().join("drafts/draft.md"), "# Draft").unwrap();
+        fs::write(vault.path().join(".export-ignore"), "drafts/\n").unwrap();
+
+        let files = VaultFiles::walk(vault.path(), &WalkOptions::default()).unwrap();
+        assert_eq!(
+            files.exported().collect::<Vec<_>>(),
+            vec!["note.md", "private.pdf"]
+        );
+        assert!(!files.is_published("drafts/draft.md"));
+
+        let walk_options = WalkOptions {
+            filter_fn: Some(&not_private),
+            ..WalkOptions::default()
+        };
+        let files = VaultFiles::walk(vault.path(), &walk_options).unwrap();
+        assert!(files.is_exported("note.md"));
+        assert!(!files.is_exported("private.pdf"));
+        assert!(files.is_published("private.pdf"));
+        assert!(!files.is_published("drafts/draft.md"));
+        assert_eq!(files.copied().collect::<Vec<_>>(), vec!["private.pdf"]);
+
+        // Links only resolve to published files, wikilinks only to exported ones
+        assert_eq!(
+            files.resolve_link(Path::new("docs"), "../private.pdf"),
+            Some("private.pdf".to_string())
+        );
+        assert_eq!(files.resolve_link(Path::new(""), "drafts/draft.md"), None);
+        assert!(files.resolve_wikilink("draft").is_empty());
+        assert!(files.resolve_wikilink("private.pdf").is_empty());
+        assert_eq!(files.resolve_wikilink("Note"), vec!["note.md"]);
+    }
+
+    #[test]
+    fn test_resolve_wikilink() {
+        let files = [
+            "Guide.md",
+            "a/note.md",
+            "b/note.md",
+            "docs/guide.md",
+            "img.png",
+        ];
+        assert_eq!(resolve_wikilink(files, "a/Note"), vec!["a/note.md"]);
+        assert_eq!(resolve_wikilink(files, "img.png"), vec!["img.png"]);
+        assert_eq!(
+            resolve_wikilink(files, "note"),
+            vec!["a/note.md", "b/note.md"]
+        );
+        // A whole path only matches its file
+        assert_eq!(resolve_wikilink(files, "guide"), vec!["Guide.md"]);
+        assert_eq!(
+            resolve_wikilink(files, "docs/guide.md"),
+            vec!["docs/guide.md"]
+        );
+        assert!(resolve_wikilink(files, "uide").is_empty());
+    }
+}
@@ -0,0 +1,323 @@
+//! Watching a vault for changes.
+//!
+//! Changes are reported by filesystem notifications, or by polling the vault when they are
+//! unavailable (network drives, some containers). Bursts of changes, like Obsidian saving a
+//! note while it is being typed, are gathered into a single batch. Changes inside hidden
+//! directories such as `.obsidian/`, which Obsidian rewrites constantly and which aren't
+//! exported, are ignored.
+
+use eyre::{Result, WrapErr};
+use notify::event::{MetadataKind, ModifyKind};
+use notify::{Config, Event, EventKind, PollWatcher, RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+/// How long the vault has to stay quiet before a batch of changes is reported.
+pub const QUIET_PERIOD: Duration = Duration::from_millis(500);
+
+/// Longest a batch of changes is held back while changes keep coming.
+pub const MAX_DELAY: Duration = Duration::from_secs(3);
+
+/// How often the vault is scanned when filesystem notifications are unavailable.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Decides which changes of a vault matter.
+#[derive(Debug, Clone)]
+pub struct ChangeFilter {
+    source: PathBuf,
+    ignored: Vec<PathBuf>,
+}
+
+impl ChangeFilter {
+    /// Creates a filter for a vault.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The source vault directory
+    /// * `ignored` - Directories whose changes are ignored, like the destination when it is
+    ///   inside the vault
+    pub fn new(source: PathBuf, ignored: Vec<PathBuf>) -> Self {
+        Self { source, ignored }
+    }
+
+    /// Lists the paths of an event that may change the export.
+    ///
+    /// Reads and metadata changes other than the modification time are ignored, so the export
+    /// reading the vault doesn't trigger another one. So are paths inside hidden directories
+    /// and ignored directories.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event reported by the watcher
+    ///
+    /// # Returns
+    ///
+    /// The changed paths. An event asking for a rescan reports the vault itself.
+    pub fn changed_paths(&self, event: &Event) -> Vec<PathBuf> {
+        if event.need_rescan() {
+            return vec![self.source.clone()];
+        }
+        match event.kind {
+            EventKind::Access(_) => return Vec::new(),
+            EventKind::Modify(ModifyKind::Metadata(kind)) if kind != MetadataKind::WriteTime => {
+                return Vec::new()
+            }
+            _ => {}
+        }
+
+        event
+            .paths
+            .iter()
+            .filter(|path| self.is_relevant(path))
+            .cloned()
+            .collect()
+    }
+
+    /// Checks whether a path is inside the vault, outside of hidden and ignored directories.
+    fn is_relevant(&self, path: &Path) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.source) else {
+            return false;
+        };
+        if self.ignored.iter().any(|ignored| path.starts_with(ignored)) {
+            return false;
+        }
+
+        // Hidden files, like `.export-ignore`, can change the export, hidden directories can't
+        let mut directories = relative.parent().into_iter().flat_map(Path::components);
+        !directories.any(|component| component.as_os_str().to_string_lossy().starts_with('.'))
+    }
+}
+
+/// Watches a vault and calls `on_change` with each batch of changes, until the watcher stops.
+/// Errors returned by `on_change` are reported and watching goes on, so a note that can't be
+/// exported halfway through an edit doesn't stop it.
+///
+/// # Arguments
+///
+/// * `filter` - Which changes matter, its source directory is the one watched
+/// * `on_change` - Called with the changed paths of each batch
+///
+/// # Returns
+///
+/// An error if the vault can't be watched at all.
+pub fn watch_vault<F>(filter: &ChangeFilter, mut on_change: F) -> Result<()>
+where
+    F: FnMut(&[PathBuf]) -> Result<()>,
+{
+    let (sender, receiver) = mpsc::channel();
+    let _watcher = start_watcher(&filter.source, sender)?;
+
+    while let Some(changes) = next_changes(&receiver, filter, QUIET_PERIOD, MAX_DELAY) {
+        if let Err(e) = on_change(&changes) {
+            eprintln!(
+                "Warning: Export failed, waiting for the next change\n\t{:#}\n",
+                e
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Starts watching a directory with filesystem notifications, or by polling when they are
+/// unavailable.
+fn start_watcher(
+    source: &Path,
+    sender: mpsc::Sender<notify::Result<Event>>,
+) -> Result<Box<dyn Watcher>> {
+    let notifications = notify::recommended_watcher(sender.clone()).and_then(|mut watcher| {
+        watcher.watch(source, RecursiveMode::Recursive)?;
+        Ok(watcher)
+    });
+    match notifications {
+        Ok(watcher) => Ok(Box::new(watcher)),
+        Err(e) => {
+            eprintln!(
+                "Warning: Filesystem notifications are unavailable ({}), polling every {}s instead\n\tSource: '{}'\n",
+                e,
+                POLL_INTERVAL.as_secs(),
+                source.display(),
+            );
+            let config = Config::default().with_poll_interval(POLL_INTERVAL);
+            let mut watcher =
+                PollWatcher::new(sender, config).wrap_err("Failed to start polling the vault")?;
+            watcher
+                .watch(source, RecursiveMode::Recursive)
+                .wrap_err("Failed to watch the vault")?;
+            Ok(Box::new(watcher))
+        }
+    }
+}
+
+/// Waits for the next batch of relevant changes. The batch ends once no change came for
+/// `quiet_period`, or `max_delay` after its first change.
+///
+/// # Returns
+///
+/// The changed paths, sorted, or `None` once the watcher is gone.
+fn next_changes(
+    receiver: &Receiver<notify::Result<Event>>,
+    filter: &ChangeFilter,
+    quiet_period: Duration,
+    max_delay: Duration,
+) -> Option<Vec<PathBuf>> {
+    let mut changes = BTreeSet::new();
+    while changes.is_empty() {
+        add_changes(&mut changes, filter, receiver.recv().ok()?);
+    }
+
+    let start = Instant::now();
+    loop {
+        let remaining = max_delay.saturating_sub(start.elapsed());
+        if remaining.is_zero() {
+            break;
+        }
+        match receiver.recv_timeout(quiet_period.min(remaining)) {
+            Ok(event) => add_changes(&mut changes, filter, event),
+            Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => break,
+        }
+    }
+    Some(changes.into_iter().collect())
+}
+
+/// Adds the relevant paths of an event to a batch, reporting watcher errors.
+fn add_changes(
+    changes: &mut BTreeSet<PathBuf>,
+    filter: &ChangeFilter,
+    event: notify::Result<Event>,
+) {
+    match event {
+        Ok(event) => changes.extend(filter.changed_paths(&event)),
+        Err(e) => eprintln!("Warning: Unable to watch the vault ({})\n", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{AccessKind, CreateKind, DataChange, Flag, RemoveKind, RenameMode};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    fn filter() -> ChangeFilter {
+        ChangeFilter::new(
+            PathBuf::from("/vault"),
+            vec![PathBuf::from("/vault/public")],
+        )
+    }
+
+    fn event(kind: EventKind, path: &str) -> Event {
+        Event::new(kind).add_path(PathBuf::from(path))
+    }
+
+    #[test]
+    fn test_changed_paths() {
+        let filter = filter();
+        let modify = EventKind::Modify(ModifyKind::Data(DataChange::Content));
+
+        assert_eq!(
+            filter.changed_paths(&event(modify, "/vault/notes/a.md")),
+            vec![PathBuf::from("/vault/notes/a.md")]
+        );
+        assert_eq!(
+            filter
+                .changed_paths(&event(
+                    EventKind::Create(CreateKind::File),
+                    "/vault/.export-ignore"
+                ))
+                .len(),
+            1
+        );
+        let rename = Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::Both)))
+            .add_path(PathBuf::from("/vault/old.md"))
+            .add_path(PathBuf::from("/vault/new.md"));
+        assert_eq!(filter.changed_paths(&rename).len(), 2);
+        assert_eq!(
+            filter
+                .changed_paths(&event(
+                    EventKind::Modify(ModifyKind::Metadata(MetadataKind::WriteTime)),
+                    "/vault/a.md"
+                ))
+                .len(),
+            1
+        );
+
+        // Workspace churn, hidden directories, the destination and reads don't matter
+        assert!(filter
+            .changed_paths(&event(modify, "/vault/.obsidian/workspace.json"))
+            .is_empty());
+        assert!(filter
+            .changed_paths(&event(
+                EventKind::Remove(RemoveKind::File),
+                "/vault/notes/.trash/a.md"
+            ))
+            .is_empty());
+        assert!(filter
+            .changed_paths(&event(modify, "/vault/public/a.md"))
+            .is_empty());
+        assert!(filter
+            .changed_paths(&event(EventKind::Access(AccessKind::Read), "/vault/a.md"))
+            .is_empty());
+        assert!(filter
+            .changed_paths(&event(
+                EventKind::Modify(ModifyKind::Metadata(MetadataKind::Any)),
+                "/vault/a.md"
+            ))
+            .is_empty());
+        assert!(filter
+            .changed_paths(&event(modify, "/other/a.md"))
+            .is_empty());
+
+        // Missed events ask for a rescan
+        let rescan = Event::new(EventKind::Other).set_flag(Flag::Rescan);
+        assert_eq!(filter.changed_paths(&rescan), vec![PathBuf::from("/vault")]);
+    }
+
+    #[test]
+    fn test_next_changes() {
+        let filter = filter();
+        let (sender, receiver) = mpsc::channel();
+        let modify = EventKind::Modify(ModifyKind::Data(DataChange::Any));
+        let quiet = Duration::from_millis(20);
+
+        // Irrelevant events don't start a batch, a burst is reported once
+        sender
+            .send(Ok(event(modify, "/vault/.obsidian/workspace.json")))
+            .unwrap();
+        sender.send(Ok(event(modify, "/vault/b.md"))).unwrap();
+        sender.send(Ok(event(modify, "/vault/a.md"))).unwrap();
+        sender.send(Ok(event(modify, "/vault/b.md"))).unwrap();
+        assert_eq!(
+            next_changes(&receiver, &filter, quiet, Duration::from_secs(1)),
+            Some(vec![
+                PathBuf::from("/vault/a.md"),
+                PathBuf::from("/vault/b.md")
+            ])
+        );
+
+        // Changes that keep coming are reported after the maximum delay. The writer only stops
+        // once the batch is returned, or after 10 seconds if it never is.
+        let done = Arc::new(AtomicBool::new(false));
+        let writer_done = Arc::clone(&done);
+        let start = Instant::now();
+        let writer = std::thread::spawn(move || {
+            while !writer_done.load(Ordering::Relaxed) && start.elapsed() < Duration::from_secs(10)
+            {
+                sender.send(Ok(event(modify, "/vault/a.md"))).unwrap();
+                std::thread::sleep(Duration::from_millis(5));
+            }
+        });
+        assert_eq!(
+            next_changes(&receiver, &filter, quiet, Duration::from_millis(30)),
+            Some(vec![PathBuf::from("/vault/a.md")])
+        );
+        assert!(start.elapsed() < Duration::from_secs(5));
+        done.store(true, Ordering::Relaxed);
+        writer.join().unwrap();
+
+        // The watcher is gone
+        while receiver.try_recv().is_ok() {}
+        assert_eq!(next_changes(&receiver, &filter, quiet, quiet), None);
+    }
+}
@@ -2,8 +2,11 @@ use std::fs;
 use std::path::Path;
 use std::process::Command;
 use tempfile::TempDir;
-use obsidian_export::{Exporter, FrontmatterStrategy};
-use obsidian_zola::postprocessors::create_zola_link_postprocessor;
+use obsidian_export::{FrontmatterStrategy, WalkOptions};
+use obsidian_zola::canvas::CanvasMode;
+use obsidian_zola::check::check_vault;
+use obsidian_zola::export::ZolaExport;
+use obsidian_zola::postprocessors::ZolaOptions;
 
 /// Copy the test vault to a temporary directory for testing
 fn copy_test_vault_to_temp(temp_dir: &Path) {
@@ -40,19 +43,16 @@ fn test_export_matches_expected() {
     
     copy_test_vault_to_temp(temp_vault.path());
     
-    // Export
-    let mut exporter = Exporter::new(
-        temp_vault.path().to_path_buf(),
-        temp_output.path().to_path_buf(),
-    );
+    // Export, set up like the export command
+    let export = ZolaExport::new(temp_vault.path().to_path_buf(), ZolaOptions::default());
+    let mut exporter = export.exporter(temp_output.path().to_path_buf());
     
     // Configure frontmatter strategy to match CLI behavior
     exporter.frontmatter_strategy(FrontmatterStrategy::Always);
     
-    let zola_postprocessor = create_zola_link_postprocessor(temp_vault.path().to_path_buf());
-    exporter.add_postprocessor(&zola_postprocessor);
-    
     exporter.run().expect("Export should succeed");
+    export.export_canvases(temp_output.path(), CanvasMode::Layout, true)
+        .expect("Canvas export should succeed");
     
     // Compare with expected output using system diff
     let expected_output = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/expected_output");
@@ -73,4 +73,27 @@ fn test_export_matches_expected() {
         }
         panic!("Generated output does not match expected output");
     }
-} 
\ No newline at end of file
+}
+
+#[test]
+fn test_check_reports_test_vault_problems() {
+    let vault = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/test_vault");
+    
+    let diagnostics: Vec<String> = check_vault(&vault, &WalkOptions::default())
+        .expect("Check should succeed")
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    
+    // Wikilinks can't go up with `../`, and the edge cases link to missing notes on purpose
+    assert_eq!(
+        diagnostics,
+        vec![
+            "docs/tutorial.md:6: broken link '../index'",
+            "docs/tutorial.md:7: broken link '../about'",
+            "edge-cases.md:4: broken link 'missing-file'",
+            "edge-cases.md:5: broken link '../nonexistent'",
+            "edge-cases.md:6: broken link '../outside.md'",
+        ]
+    );
+}